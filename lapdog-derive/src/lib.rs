@@ -2,27 +2,68 @@ use std::{collections::HashMap, ops::BitOr};
 
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
-use syn::{DataStruct, DeriveInput, Field, Fields, Ident, parse_quote};
+use syn::{DataStruct, DeriveInput, Field, Fields, Ident, ext::IdentExt, parse_quote};
 
+/// Implements `FromEntry` for a struct with named fields, reading each field from the attribute of the same name.
+///
+/// Field options, e.g. `#[lapdog(rename = "memberOf", default, multiple)]`:
+/// - `rename = "..."`: read a different attribute than the field name
+/// - `options = "..."`: attribute options to request, separated by `;`, e.g. `options = "binary"` for
+///   `userCertificate;binary`. The attribute is matched like `lapdog::attribute::description_matches` does, so a
+///   server leaving out or adding `binary` still fills the field. Other options have to match exactly, so a `cn`
+///   field and a `cn` field with `options = "lang-fr"` are filled from `cn` and `cn;lang-fr` respectively
+/// - `multiple`: parse all values with `FromMultipleOctetStrings`
+/// - `first`: take the first value if the server returns several, instead of failing with `TooManyValues`.
+///   Servers don't guarantee any order of values, so which one is "first" may change between searches
+/// - `default`: use `Default::default()` if the attribute is missing
+/// - `object_name`: fill the field with the entry's DN
+///
+/// Structs with a lifetime, e.g. `struct UserRef<'a> { cn: &'a str }`, implement `FromEntryRef<'a>` instead and
+/// borrow their values from the entry, parsing them with `FromOctetStringRef` and `FromMultipleOctetStringsRef`.
 #[proc_macro_derive(Entry, attributes(lapdog))]
 pub fn implement_from_entry(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = syn::parse_macro_input!(item as DeriveInput);
     let name = input.ident;
-    let (fields, object_name_field) = match parse_fields(
-        match input.data {
-            syn::Data::Struct(DataStruct { fields, .. }) => match fields {
-                Fields::Named(f) => f,
-                _ => panic!("Structs fields/attributes must be named to be derivable"),
-            },
-            _ => unimplemented!("non-struct derives are not supported"),
+    let named_fields = match input.data {
+        syn::Data::Struct(DataStruct {
+            fields: Fields::Named(f),
+            ..
+        }) => f.named,
+        syn::Data::Struct(DataStruct { fields, .. }) => {
+            return syn::Error::new_spanned(fields, "struct fields must be named to be derivable")
+                .into_compile_error()
+                .into();
+        }
+        syn::Data::Enum(e) => {
+            return syn::Error::new_spanned(e.enum_token, "only structs can derive Entry")
+                .into_compile_error()
+                .into();
         }
-        .named,
-    ) {
+        syn::Data::Union(u) => {
+            return syn::Error::new_spanned(u.union_token, "only structs can derive Entry")
+                .into_compile_error()
+                .into();
+        }
+    };
+    let (fields, object_name_field) = match parse_fields(named_fields) {
         Ok(f) => f,
         Err(e) => return e.into_compile_error().into(),
     };
     let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
 
+    // A struct with a lifetime borrows its fields from the entry
+    let mut lifetimes = input.generics.lifetimes().map(|l| &l.lifetime);
+    let borrowed = lifetimes.next();
+    if let Some(extra) = lifetimes.next() {
+        return syn::Error::new_spanned(
+            extra,
+            "Entry can only be derived for structs with at most one lifetime",
+        )
+        .into_compile_error()
+        .into();
+    }
+    let traits = ParseTraits::new(borrowed);
+
     // Generics' type parameters
     let generic_params: Vec<syn::Ident> = input
         .generics
@@ -58,16 +99,17 @@ pub fn implement_from_entry(item: proc_macro::TokenStream) -> proc_macro::TokenS
         .unwrap_or_default();
 
     for (ident, needs_bound) in generic_bounds {
+        let ParseTraits { single, multiple, .. } = &traits;
         let multi = || {
             [
-                parse_quote!(#ident: lapdog::search::FromMultipleOctetStrings),
-                parse_quote!(<#ident as lapdog::search::FromMultipleOctetStrings>::Err: 'static),
+                parse_quote!(#ident: #multiple),
+                parse_quote!(<#ident as #multiple>::Err: 'static),
             ]
         };
         let single = || {
             [
-                parse_quote!(#ident: lapdog::search::FromOctetString),
-                parse_quote!(<#ident as lapdog::search::FromOctetString>::Err: 'static),
+                parse_quote!(#ident: #single),
+                parse_quote!(<#ident as #single>::Err: 'static),
             ]
         };
         match needs_bound {
@@ -90,15 +132,39 @@ pub fn implement_from_entry(item: proc_macro::TokenStream) -> proc_macro::TokenS
         quote!(where #(#where_preds),*)
     };
 
-    let insert_object_name = object_name_field.as_ref().map(insert_object_name);
-    let field_quotes = fields.iter().map(field_line);
+    let insert_object_name = object_name_field
+        .as_ref()
+        .map(|field| insert_object_name(field, borrowed));
+    // Attribute descriptions are case-insensitive and may differ in their options, so look them up by their
+    // normalized form in a map built once per entry
+    let attribute_map = (!fields.is_empty()).then(|| {
+        quote! {
+            let mut __lapdog_attributes = std::collections::HashMap::with_capacity(__lapdog_entry.attributes.len());
+            for attr in &__lapdog_entry.attributes {
+                __lapdog_attributes.entry(lapdog::attribute::description_key(&attr.r#type)).or_insert(attr);
+            }
+        }
+    });
+    let field_quotes = fields.iter().map(|field| field_line(field, &traits));
     let field_names = fields.iter().map(|x| x.ident());
+    let field_vars = fields.iter().map(|x| x.var());
     let attribute_names = fields.iter().map(|x| x.attribute_name.clone());
+    let (from_entry_trait, from_entry_fn) = match borrowed {
+        Some(lt) => (
+            quote!(lapdog::search::FromEntryRef<#lt>),
+            quote!(from_entry_ref(__lapdog_entry: &#lt lapdog::search::RawEntry)),
+        ),
+        None => (
+            quote!(lapdog::search::FromEntry),
+            quote!(from_entry(__lapdog_entry: lapdog::search::RawEntry)),
+        ),
+    };
     quote!(
-        impl #impl_generics lapdog::search::FromEntry for #name #type_generics #where_clause {
-            fn from_entry(entry: lapdog::search::RawEntry) -> Result<#name #type_generics, lapdog::search::FailedToGetFromEntry> {
+        impl #impl_generics #from_entry_trait for #name #type_generics #where_clause {
+            fn #from_entry_fn -> Result<#name #type_generics, lapdog::search::FailedToGetFromEntry> {
+                #attribute_map
                 #( #field_quotes )*
-                Ok(#name { #(#field_names,)* #insert_object_name })
+                Ok(#name { #(#field_names: #field_vars,)* #insert_object_name })
             }
 
             fn attributes() -> Option<impl Iterator<Item = &'static str>> {
@@ -126,17 +192,49 @@ impl BitOr for NeedsBound {
     }
 }
 
-fn insert_object_name(field: &Field) -> TokenStream {
+fn insert_object_name(field: &Field, borrowed: Option<&syn::Lifetime>) -> TokenStream {
     let field_name = field.ident.as_ref().expect("checked to be named field");
     let ty = &field.ty;
-    quote! {
-        #field_name: <#ty as From<String>>::from(entry.object_name)
+    match borrowed {
+        Some(lt) => quote! {
+            #field_name: <#ty as From<&#lt str>>::from(__lapdog_entry.object_name.as_str())
+        },
+        None => quote! {
+            #field_name: <#ty as From<String>>::from(__lapdog_entry.object_name)
+        },
+    }
+}
+
+/// The traits the generated code parses values with, which borrow from the entry for structs with a lifetime
+struct ParseTraits {
+    single: TokenStream,
+    single_fn: Ident,
+    multiple: TokenStream,
+    multiple_fn: Ident,
+}
+impl ParseTraits {
+    fn new(borrowed: Option<&syn::Lifetime>) -> Self {
+        match borrowed {
+            Some(lt) => Self {
+                single: quote!(lapdog::search::FromOctetStringRef<#lt>),
+                single_fn: format_ident!("from_octet_string_ref"),
+                multiple: quote!(lapdog::search::FromMultipleOctetStringsRef<#lt>),
+                multiple_fn: format_ident!("from_multiple_octet_strings_ref"),
+            },
+            None => Self {
+                single: quote!(lapdog::search::FromOctetString),
+                single_fn: format_ident!("from_octet_string"),
+                multiple: quote!(lapdog::search::FromMultipleOctetStrings),
+                multiple_fn: format_ident!("from_multiple_octet_strings"),
+            },
+        }
     }
 }
 
 struct AttributeField {
     attribute_name: String,
     multiple: bool,
+    first: bool,
     default: bool,
     field: Field,
 }
@@ -144,34 +242,39 @@ impl AttributeField {
     fn ident(&self) -> Ident {
         self.field.ident.clone().expect("checked to be named field")
     }
+    /// The local the field's value is parsed into, prefixed so it can't shadow the generated code's own locals
+    fn var(&self) -> Ident {
+        format_ident!("__lapdog_field_{}", self.ident().unraw())
+    }
 }
 fn parse_fields(
     raw_fields: impl IntoIterator<Item = Field>,
 ) -> Result<(Vec<AttributeField>, Option<Field>), syn::Error> {
     let mut fields: Vec<AttributeField> = Vec::new();
     let mut object_name_field = None;
-    'fields: for field in raw_fields {
-        let mut multiple = false;
-        let mut default = false;
+    for field in raw_fields {
+        // The paths are kept to point compile errors at the offending option
+        let mut object_name = None;
+        let mut multiple = None;
+        let mut first = None;
+        let mut default = None;
+        let mut rename = None;
         let mut replaced_attribute_name = None;
-        for attr in &field.attrs {
-            let mut has_set_object_name_field = false;
+        let mut options = None;
+        let mut attribute_options = None;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("lapdog")) {
             attr.parse_nested_meta(|meta| {
                 if meta.path.is_ident("object_name") {
                     if object_name_field.replace(field.clone()).is_some() {
                         return Err(meta.error("\"object_name\" can only be declared on one field"));
                     };
-                    has_set_object_name_field = true;
+                    object_name = Some(meta.path.clone());
                     return Ok(());
                 }
                 if meta.path.require_ident()? == "rename" {
                     let lookahead = meta.input.lookahead1();
                     if lookahead.peek(syn::Token![=]) {
-                        let expr = meta
-                            .value()
-                            .expect("Meta has no value")
-                            .parse()
-                            .expect("Meta is no expression");
+                        let expr: syn::Expr = meta.value()?.parse()?;
                         let mut value = &expr;
                         while let syn::Expr::Group(e) = value {
                             value = &e.expr;
@@ -182,6 +285,7 @@ fn parse_fields(
                         }) = value
                         {
                             replaced_attribute_name = Some(lit.value());
+                            rename = Some(meta.path.clone());
                         } else {
                             return Err(meta.error("rename argument must be a string literal"));
                         }
@@ -189,39 +293,87 @@ fn parse_fields(
                         return Err(meta.error("rename must be used like \"rename = <LDAP NAME>\""));
                     }
                 }
+                if meta.path.require_ident()? == "options" {
+                    let lit: syn::LitStr = meta
+                        .value()
+                        .map_err(|_| meta.error("options must be used like \"options = <OPTIONS>\""))?
+                        .parse()?;
+                    attribute_options = Some(lit.value());
+                    options = Some(meta.path.clone());
+                }
                 if meta.path.require_ident()? == "multiple" {
-                    multiple = true;
+                    multiple = Some(meta.path.clone());
+                }
+                if meta.path.require_ident()? == "first" {
+                    first = Some(meta.path.clone());
                 }
                 if meta.path.require_ident()? == "default" {
-                    default = true;
+                    default = Some(meta.path.clone());
+                }
+                if !["rename", "options", "multiple", "first", "default"]
+                    .iter()
+                    .any(|o| meta.path.is_ident(o))
+                {
+                    return Err(meta.error("unknown lapdog option"));
                 }
                 Ok(())
             })?;
-            if has_set_object_name_field {
-                continue 'fields;
+        }
+        if object_name.is_some() {
+            // the DN is always present and single-valued, options about reading attributes don't apply
+            for (option, path) in [
+                ("rename", &rename),
+                ("options", &options),
+                ("multiple", &multiple),
+                ("first", &first),
+                ("default", &default),
+            ] {
+                if let Some(path) = path {
+                    return Err(syn::Error::new_spanned(
+                        path,
+                        format!("\"{option}\" can't be combined with \"object_name\""),
+                    ));
+                }
+            }
+            continue;
+        }
+        if let (Some(_), Some(path)) = (&multiple, &first) {
+            return Err(syn::Error::new_spanned(
+                path,
+                "\"first\" only applies to single-valued fields and can't be combined with \"multiple\"",
+            ));
+        }
+        let mut attribute_name = replaced_attribute_name
+            .unwrap_or_else(|| field.ident.as_ref().expect("checked as named field").to_string());
+        if let Some(attribute_options) = attribute_options {
+            for option in attribute_options.split(';').filter(|o| !o.is_empty()) {
+                attribute_name.push(';');
+                attribute_name.push_str(option);
             }
         }
-        let attribute_name = replaced_attribute_name.unwrap_or_else(|| {
-            field
-                .ident
-                .as_ref()
-                .expect("checked as named field")
-                .to_string()
-        });
         fields.push(AttributeField {
             attribute_name,
-            multiple,
-            default,
+            multiple: multiple.is_some(),
+            first: first.is_some(),
+            default: default.is_some(),
             field,
         })
     }
     Ok((fields, object_name_field))
 }
 
-fn field_line(data: &AttributeField) -> TokenStream {
+fn field_line(data: &AttributeField, traits: &ParseTraits) -> TokenStream {
+    let ParseTraits {
+        single,
+        single_fn,
+        multiple,
+        multiple_fn,
+    } = traits;
     let lookup_name = &data.attribute_name;
+    // normalized by lapdog itself, so the keys always match those of the attribute map
+    let lookup_key = quote!(lapdog::attribute::description_key(#lookup_name).as_str());
     let field_type = &data.field.ty;
-    let varname = format_ident!("{}", data.ident());
+    let varname = data.var();
     let fallback = if data.default {
         quote! { <#field_type as Default>::default() }
     } else {
@@ -229,16 +381,23 @@ fn field_line(data: &AttributeField) -> TokenStream {
     };
     if data.multiple {
         quote! {
-            let #varname = match entry.attributes.iter().find(|x| x.r#type == #lookup_name) {
-                Some(attrs) => <#field_type as lapdog::search::FromMultipleOctetStrings>::from_multiple_octet_strings(attrs.values.iter().map(|x| x.as_ref()))
+            let #varname = match __lapdog_attributes.get(#lookup_key) {
+                Some(attrs) => <#field_type as #multiple>::#multiple_fn(attrs.values.iter().map(|x| x.as_ref()))
                     .map_err(|b| lapdog::search::FailedToGetFromEntry::FailedToParseField(#lookup_name, Box::new(b)))?,
                 None => {#fallback},
             };
         }
+    } else if data.first {
+        quote! {
+            let #varname = match __lapdog_attributes.get(#lookup_key).map(|x| x.values.as_slice()) {
+                Some([attr, ..]) => <#field_type as #single>::#single_fn(attr).map_err(|b| lapdog::search::FailedToGetFromEntry::FailedToParseField(#lookup_name, Box::new(b)))?,
+                Some([]) | None => {#fallback},
+            };
+        }
     } else {
         quote! {
-            let #varname = match entry.attributes.iter().find(|x| x.r#type == #lookup_name).map(|x| x.values.as_slice()) {
-                Some([attr]) => <#field_type as lapdog::search::FromOctetString>::from_octet_string(attr).map_err(|b| lapdog::search::FailedToGetFromEntry::FailedToParseField(#lookup_name, Box::new(b)))?,
+            let #varname = match __lapdog_attributes.get(#lookup_key).map(|x| x.values.as_slice()) {
+                Some([attr]) => <#field_type as #single>::#single_fn(attr).map_err(|b| lapdog::search::FailedToGetFromEntry::FailedToParseField(#lookup_name, Box::new(b)))?,
                 Some([]) | None => {#fallback},
                 Some(_) => {return Err(lapdog::search::FailedToGetFromEntry::TooManyValues(#lookup_name))}
             };
@@ -0,0 +1,122 @@
+use lapdog::search::{Attribute, Entry, FailedToGetFromEntry, FromEntry, FromEntryRef, RawEntry};
+
+fn entry(attributes: &[(&str, &[&str])]) -> RawEntry {
+    RawEntry {
+        object_name: "cn=bob,dc=example,dc=com".to_string(),
+        attributes: attributes
+            .iter()
+            .map(|(name, values)| Attribute {
+                r#type: name.to_string(),
+                values: values.iter().map(|v| v.as_bytes().to_vec()).collect(),
+            })
+            .collect(),
+    }
+}
+
+/// Fields named like the generated code's locals don't shadow them
+#[test]
+fn fields_named_like_locals() {
+    #[derive(Entry)]
+    struct Shadowing {
+        attributes: String,
+        entry: String,
+        cn: String,
+        #[lapdog(object_name)]
+        dn: String,
+    }
+    let parsed = Shadowing::from_entry(entry(&[
+        ("attributes", &["a"]),
+        ("entry", &["e"]),
+        ("cn", &["bob"]),
+    ]))
+    .unwrap();
+    assert_eq!(parsed.attributes, "a");
+    assert_eq!(parsed.entry, "e");
+    assert_eq!(parsed.cn, "bob");
+    assert_eq!(parsed.dn, "cn=bob,dc=example,dc=com");
+}
+
+/// Language tags are options that have to match, unlike `binary`
+#[test]
+fn language_tag_options() {
+    #[derive(Entry)]
+    struct Names {
+        cn: String,
+        #[lapdog(rename = "cn", options = "lang-fr")]
+        cn_fr: String,
+    }
+    let parsed = Names::from_entry(entry(&[("cn;lang-fr", &["Robert"]), ("CN", &["Bob"])])).unwrap();
+    assert_eq!(parsed.cn, "Bob");
+    assert_eq!(parsed.cn_fr, "Robert");
+    assert_eq!(
+        Names::attributes().unwrap().collect::<Vec<_>>(),
+        ["cn", "cn;lang-fr"]
+    );
+}
+
+#[test]
+fn first_of_several_values() {
+    #[derive(Entry)]
+    struct Mail {
+        #[lapdog(first)]
+        mail: String,
+        #[lapdog(first, default)]
+        telephone_number: String,
+        cn: String,
+    }
+    let parsed = Mail::from_entry(entry(&[("mail", &["a@x", "b@x"]), ("cn", &["bob"])])).unwrap();
+    assert_eq!(parsed.mail, "a@x");
+    assert_eq!(parsed.telephone_number, "");
+    assert_eq!(parsed.cn, "bob");
+
+    // without `first`, several values are an error
+    let result = Mail::from_entry(entry(&[("mail", &["a@x"]), ("cn", &["bob", "robert"])]));
+    assert!(matches!(result, Err(FailedToGetFromEntry::TooManyValues("cn"))));
+}
+
+/// `binary` may be left out or added by the server, other options are part of the name
+#[test]
+fn binary_option() {
+    #[derive(Entry)]
+    struct Certificate {
+        #[lapdog(rename = "userCertificate", options = "binary")]
+        certificate: Vec<u8>,
+    }
+    assert_eq!(
+        Certificate::attributes().unwrap().collect::<Vec<_>>(),
+        ["userCertificate;binary"]
+    );
+    for name in ["userCertificate;binary", "usercertificate"] {
+        let parsed = Certificate::from_entry(entry(&[(name, &["der"])])).unwrap();
+        assert_eq!(parsed.certificate, b"der");
+    }
+}
+
+#[test]
+fn borrowed_fields() {
+    #[derive(Entry)]
+    struct UserRef<'a> {
+        #[lapdog(object_name)]
+        dn: &'a str,
+        cn: &'a str,
+        #[lapdog(multiple, rename = "memberOf")]
+        groups: Vec<&'a str>,
+        #[lapdog(default)]
+        description: Option<&'a str>,
+        uid: String,
+    }
+    let raw = entry(&[
+        ("cn", &["bob"]),
+        ("memberOf", &["cn=a", "cn=b"]),
+        ("uid", &["bob1"]),
+    ]);
+    let parsed = UserRef::from_entry_ref(&raw).unwrap();
+    assert_eq!(parsed.dn, raw.object_name);
+    assert!(std::ptr::eq(
+        parsed.cn.as_bytes(),
+        raw.attributes[0].values[0].as_slice()
+    ));
+    assert_eq!(parsed.groups, ["cn=a", "cn=b"]);
+    assert_eq!(parsed.description, None);
+    assert_eq!(parsed.uid, "bob1");
+}
@@ -0,0 +1,11 @@
+use lapdog::search::Entry;
+
+#[derive(Entry)]
+struct User {
+    #[lapdog(object_name)]
+    dn: String,
+    #[lapdog(object_name)]
+    distinguished_name: String,
+}
+
+fn main() {}
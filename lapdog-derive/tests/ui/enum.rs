@@ -0,0 +1,8 @@
+use lapdog::search::Entry;
+
+#[derive(Entry)]
+enum User {
+    Person { cn: String },
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use lapdog::search::Entry;
+
+#[derive(Entry)]
+struct User {
+    #[lapdog(multiple, first)]
+    mail: Vec<String>,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use lapdog::search::Entry;
+
+#[derive(Entry)]
+struct User {
+    #[lapdog(rename = 5)]
+    mail: String,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use lapdog::search::Entry;
+
+#[derive(Entry)]
+struct User(String);
+
+fn main() {}
@@ -0,0 +1,9 @@
+use lapdog::search::Entry;
+
+#[derive(Entry)]
+struct User {
+    #[lapdog(optional)]
+    mail: String,
+}
+
+fn main() {}
@@ -1,10 +1,6 @@
 use kenobi::{cred::Credentials, mech::Mechanism};
 
-use lapdog::{
-    LDAP_PORT, LdapConnection, StreamConfig,
-    search::{DerefPolicy, Filter, Scope, SearchResult},
-};
-use lapdog_derive::Entry;
+use lapdog::prelude::*;
 
 #[tokio::main]
 async fn main() {
@@ -53,7 +49,7 @@ async fn search_users(ldap: &mut LdapConnection) {
                 count += 1
             }
             Ok(Some(Err(e))) => println!("Encountered search error: {e:?}"),
-            Ok(Some(Ok(SearchResult::Reference))) => {}
+            Ok(Some(Ok(SearchResult::Reference(_) | SearchResult::Intermediate { .. }))) => {}
             Ok(Some(Ok(SearchResult::Done { .. }))) | Ok(None) | Err(_) => break,
         };
     }
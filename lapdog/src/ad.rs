@@ -0,0 +1,287 @@
+//! Typed Active Directory attributes
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    str::FromStr,
+};
+
+use crate::{modify::ToOctetString, search::FromOctetString};
+
+#[cfg(feature = "ad-repl")]
+pub mod repl;
+
+/// The `userAccountControl` bit flags of an Active Directory account
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UserAccountControl(u32);
+impl UserAccountControl {
+    pub const SCRIPT: u32 = 0x1;
+    pub const ACCOUNTDISABLE: u32 = 0x2;
+    pub const HOMEDIR_REQUIRED: u32 = 0x8;
+    pub const LOCKOUT: u32 = 0x10;
+    pub const PASSWD_NOTREQD: u32 = 0x20;
+    pub const PASSWD_CANT_CHANGE: u32 = 0x40;
+    pub const ENCRYPTED_TEXT_PWD_ALLOWED: u32 = 0x80;
+    pub const TEMP_DUPLICATE_ACCOUNT: u32 = 0x100;
+    pub const NORMAL_ACCOUNT: u32 = 0x200;
+    pub const INTERDOMAIN_TRUST_ACCOUNT: u32 = 0x800;
+    pub const WORKSTATION_TRUST_ACCOUNT: u32 = 0x1000;
+    pub const SERVER_TRUST_ACCOUNT: u32 = 0x2000;
+    pub const DONT_EXPIRE_PASSWORD: u32 = 0x10000;
+    pub const MNS_LOGON_ACCOUNT: u32 = 0x20000;
+    pub const SMARTCARD_REQUIRED: u32 = 0x40000;
+    pub const TRUSTED_FOR_DELEGATION: u32 = 0x80000;
+    pub const NOT_DELEGATED: u32 = 0x100000;
+    pub const USE_DES_KEY_ONLY: u32 = 0x200000;
+    pub const DONT_REQ_PREAUTH: u32 = 0x400000;
+    pub const PASSWORD_EXPIRED: u32 = 0x800000;
+    pub const TRUSTED_TO_AUTH_FOR_DELEGATION: u32 = 0x1000000;
+    pub const PARTIAL_SECRETS_ACCOUNT: u32 = 0x4000000;
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+    /// Whether all bits of `flag` are set, e.g. [`UserAccountControl::SMARTCARD_REQUIRED`]
+    pub const fn contains(self, flag: u32) -> bool {
+        self.0 & flag == flag
+    }
+    pub const fn is_disabled(self) -> bool {
+        self.contains(Self::ACCOUNTDISABLE)
+    }
+    /// Active Directory doesn't keep this flag up to date in `userAccountControl`,
+    /// read `msDS-User-Account-Control-Computed` for a reliable value.
+    pub const fn is_locked_out(self) -> bool {
+        self.contains(Self::LOCKOUT)
+    }
+    pub const fn password_not_required(self) -> bool {
+        self.contains(Self::PASSWD_NOTREQD)
+    }
+    pub const fn password_never_expires(self) -> bool {
+        self.contains(Self::DONT_EXPIRE_PASSWORD)
+    }
+    /// Active Directory doesn't keep this flag up to date in `userAccountControl`,
+    /// read `msDS-User-Account-Control-Computed` for a reliable value.
+    pub const fn is_password_expired(self) -> bool {
+        self.contains(Self::PASSWORD_EXPIRED)
+    }
+    pub const fn smartcard_required(self) -> bool {
+        self.contains(Self::SMARTCARD_REQUIRED)
+    }
+    pub const fn is_normal_account(self) -> bool {
+        self.contains(Self::NORMAL_ACCOUNT)
+    }
+    pub const fn is_workstation_account(self) -> bool {
+        self.contains(Self::WORKSTATION_TRUST_ACCOUNT)
+    }
+    pub const fn is_domain_controller(self) -> bool {
+        self.contains(Self::SERVER_TRUST_ACCOUNT)
+    }
+    pub const fn is_trusted_for_delegation(self) -> bool {
+        self.contains(Self::TRUSTED_FOR_DELEGATION)
+    }
+    /// Accounts that don't require Kerberos pre-authentication are prone to AS-REP roasting
+    pub const fn preauth_not_required(self) -> bool {
+        self.contains(Self::DONT_REQ_PREAUTH)
+    }
+}
+impl ToOctetString for UserAccountControl {
+    fn to_octet_string(&self) -> Vec<u8> {
+        (self.bits() as i32).to_octet_string()
+    }
+}
+impl FromOctetString for UserAccountControl {
+    type Err = <i32 as FromOctetString>::Err;
+    fn from_octet_string(bytes: &[u8]) -> Result<Self, Self::Err> {
+        // stored as a signed 32 bit integer
+        i32::from_octet_string(bytes).map(|i| Self(i as u32))
+    }
+}
+
+/// A security identifier like `objectSid`, e.g. `S-1-5-21-1004336348-1177238915-682003330-512`.
+///
+/// To search for an entry by its SID, pass [`Sid::to_filter_bytes`] to [`Filter::equal`](crate::search::Filter::equal).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Sid {
+    pub revision: u8,
+    /// 48 bit identifier authority, e.g. 5 for `NT AUTHORITY`
+    pub authority: u64,
+    /// At most [`Sid::MAX_SUB_AUTHORITIES`], parsing rejects longer SIDs
+    pub sub_authorities: Vec<u32>,
+}
+impl Sid {
+    /// Windows doesn't accept SIDs with more sub-authorities
+    pub const MAX_SUB_AUTHORITIES: usize = 15;
+    /// The binary form Active Directory stores and compares
+    ///
+    /// # Panics
+    /// If there are more than 255 sub-authorities, which the binary form can't count.
+    pub fn to_filter_bytes(&self) -> Vec<u8> {
+        let count = u8::try_from(self.sub_authorities.len()).expect("too many sub-authorities for a SID");
+        let mut bytes = Vec::with_capacity(8 + 4 * self.sub_authorities.len());
+        bytes.push(self.revision);
+        bytes.push(count);
+        bytes.extend_from_slice(&self.authority.to_be_bytes()[2..]);
+        for sub_authority in &self.sub_authorities {
+            bytes.extend_from_slice(&sub_authority.to_le_bytes());
+        }
+        bytes
+    }
+    /// The last sub-authority, which identifies the account within its domain, e.g. 512 for Domain Admins
+    pub fn relative_id(&self) -> Option<u32> {
+        self.sub_authorities.last().copied()
+    }
+    /// The SID without its relative ID, i.e. the SID of the account's domain
+    pub fn domain(&self) -> Option<Sid> {
+        let (_, domain) = self.sub_authorities.split_last()?;
+        Some(Self {
+            sub_authorities: domain.to_vec(),
+            ..*self
+        })
+    }
+    /// The SID of the account with `relative_id` in this domain
+    pub fn with_relative_id(&self, relative_id: u32) -> Sid {
+        let mut sid = self.clone();
+        sid.sub_authorities.push(relative_id);
+        sid
+    }
+}
+
+/// The SID of a user's primary group, from the user's `objectSid` and `primaryGroupID`.
+///
+/// Active Directory doesn't list the primary group, usually Domain Users, in the user's `memberOf` or the group's
+/// `member`. To get all groups of a user, search the group with `Filter::equal("objectSid", &sid.to_filter_bytes())`
+/// and add it to the groups from `memberOf`.
+///
+/// To change the primary group, replace `primaryGroupID` with the relative ID of the new group.
+/// The user has to be a member of that group already.
+///
+/// ```
+/// # use lapdog::ad::{Sid, primary_group_sid};
+/// let user: Sid = "S-1-5-21-1004336348-1177238915-682003330-1105".parse().unwrap();
+/// let group = primary_group_sid(&user, 513).unwrap();
+/// assert_eq!(group.to_string(), "S-1-5-21-1004336348-1177238915-682003330-513");
+/// ```
+pub fn primary_group_sid(user_sid: &Sid, primary_group_id: u32) -> Option<Sid> {
+    Some(user_sid.domain()?.with_relative_id(primary_group_id))
+}
+impl FromOctetString for Sid {
+    type Err = InvalidSid;
+    fn from_octet_string(bytes: &[u8]) -> Result<Self, Self::Err> {
+        let [revision, count, authority @ ..] = bytes else {
+            return Err(InvalidSid);
+        };
+        let (authority, sub_authorities) = authority.split_at_checked(6).ok_or(InvalidSid)?;
+        if sub_authorities.len() != 4 * *count as usize || *count as usize > Self::MAX_SUB_AUTHORITIES {
+            return Err(InvalidSid);
+        }
+        let mut authority_bytes = [0; 8];
+        authority_bytes[2..].copy_from_slice(authority);
+        Ok(Self {
+            revision: *revision,
+            authority: u64::from_be_bytes(authority_bytes),
+            sub_authorities: sub_authorities
+                .chunks_exact(4)
+                .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .collect(),
+        })
+    }
+}
+impl ToOctetString for Sid {
+    fn to_octet_string(&self) -> Vec<u8> {
+        self.to_filter_bytes()
+    }
+}
+impl FromStr for Sid {
+    type Err = InvalidSid;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.strip_prefix("S-").ok_or(InvalidSid)?.split('-');
+        let revision = parts.next().and_then(|r| r.parse().ok()).ok_or(InvalidSid)?;
+        let authority = parts
+            .next()
+            .and_then(|a| a.parse().ok())
+            .filter(|a| *a < 1 << 48)
+            .ok_or(InvalidSid)?;
+        let sub_authorities = parts
+            .map(|p| p.parse().map_err(|_| InvalidSid))
+            .collect::<Result<Vec<_>, _>>()?;
+        if sub_authorities.len() > Self::MAX_SUB_AUTHORITIES {
+            return Err(InvalidSid);
+        }
+        Ok(Self {
+            revision,
+            authority,
+            sub_authorities,
+        })
+    }
+}
+impl Display for Sid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "S-{}-{}", self.revision, self.authority)?;
+        for sub_authority in &self.sub_authorities {
+            write!(f, "-{sub_authority}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidSid;
+impl Error for InvalidSid {}
+impl Display for InvalidSid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "invalid security identifier")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Sid, UserAccountControl};
+    use crate::search::FromOctetString;
+
+    #[test]
+    fn parse_user_account_control() {
+        let uac = UserAccountControl::from_octet_string(b"66050").unwrap();
+        assert_eq!(uac.bits(), 0x10202);
+        assert!(uac.is_disabled());
+        assert!(uac.is_normal_account());
+        assert!(uac.password_never_expires());
+        assert!(!uac.smartcard_required());
+        assert!(UserAccountControl::from_octet_string(b"enabled").is_err());
+    }
+
+    #[test]
+    fn sid_forms() {
+        let bytes = [
+            1, 5, 0, 0, 0, 0, 0, 5, 21, 0, 0, 0, 220, 244, 220, 59, 131, 61, 43, 70, 130, 139, 166, 40, 0, 2,
+            0, 0,
+        ];
+        let text = "S-1-5-21-1004336348-1177238915-682003330-512";
+        let sid = Sid::from_octet_string(&bytes).unwrap();
+        assert_eq!(sid.to_string(), text);
+        assert_eq!(sid.relative_id(), Some(512));
+        assert_eq!(text.parse::<Sid>().unwrap().to_filter_bytes(), bytes);
+        assert!(Sid::from_octet_string(&bytes[..27]).is_err());
+        assert!("S-1-x".parse::<Sid>().is_err());
+        assert_eq!(
+            sid.domain().unwrap().to_string(),
+            "S-1-5-21-1004336348-1177238915-682003330"
+        );
+        assert!("S-1-5".parse::<Sid>().unwrap().domain().is_none());
+    }
+
+    #[test]
+    fn too_many_sub_authorities() {
+        let fifteen = format!("S-1-5{}", "-1".repeat(15));
+        let bytes = fifteen.parse::<Sid>().unwrap().to_filter_bytes();
+        assert_eq!(bytes[1], 15);
+        assert!(format!("{fifteen}-1").parse::<Sid>().is_err());
+
+        let mut sixteen = bytes;
+        sixteen[1] = 16;
+        sixteen.extend_from_slice(&1u32.to_le_bytes());
+        assert!(Sid::from_octet_string(&sixteen).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! Active Directory's binary replication metadata, e.g. to audit when and where an attribute was last changed.
+//!
+//! The layouts follow `replPropertyMetaDataBlob` and `replUpToDateVectorBlob` of MS-DRSR.
+//! Invocation IDs are GUIDs in their binary form, parse them with `BinaryGuid` from the `uuid` feature for display.
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    time::{Duration, SystemTime},
+};
+
+use crate::search::FromOctetString;
+
+/// Seconds between 1601-01-01, the start of Windows' `FILETIME` and `DSTIME`, and the Unix epoch
+const FILETIME_UNIX_OFFSET: u64 = 11_644_473_600;
+
+/// The values of `replPropertyMetaData`, one per replicated attribute of the entry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplPropertyMetaData(pub Vec<PropertyMetaData>);
+
+/// When and where one attribute was last changed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PropertyMetaData {
+    /// The internal attribute ID (`ATTRTYP`), mapped to an attribute through the schema's prefix table.
+    /// For attributes with an `msDS-IntId`, this is that ID.
+    pub attribute_id: u32,
+    /// Incremented on every originating change of the attribute
+    pub version: u32,
+    /// `None` if the server reported no time
+    pub originating_change_time: Option<SystemTime>,
+    /// The invocation ID of the domain controller the change originated on
+    pub originating_invocation_id: [u8; 16],
+    /// The USN of the change on the originating domain controller
+    pub originating_usn: i64,
+    /// The USN of the change on the domain controller that returned the entry
+    pub local_usn: i64,
+}
+
+impl FromOctetString for ReplPropertyMetaData {
+    type Err = InvalidReplMetaData;
+    fn from_octet_string(bytes: &[u8]) -> Result<Self, Self::Err> {
+        let mut r = Reader(bytes);
+        if r.u32()? != 1 {
+            return Err(InvalidReplMetaData);
+        }
+        let _reserved = r.u32()?;
+        let count = r.u32()?;
+        let _reserved = r.u32()?;
+        let entries = (0..count)
+            .map(|_| {
+                Ok(PropertyMetaData {
+                    attribute_id: r.u32()?,
+                    version: r.u32()?,
+                    originating_change_time: dstime(r.u64()?),
+                    originating_invocation_id: r.guid()?,
+                    originating_usn: r.i64()?,
+                    local_usn: r.i64()?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        r.finish()?;
+        Ok(Self(entries))
+    }
+}
+
+/// The value of `replUpToDateVector` on a naming context head: up to which USN this domain controller has seen
+/// the changes of each other domain controller
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplUpToDateVector(pub Vec<UpToDateCursor>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpToDateCursor {
+    /// The invocation ID of the domain controller the changes originated on
+    pub invocation_id: [u8; 16],
+    pub highest_usn: i64,
+    /// Only present in the version 2 format used since Windows Server 2003
+    pub last_sync_success: Option<SystemTime>,
+}
+
+impl FromOctetString for ReplUpToDateVector {
+    type Err = InvalidReplMetaData;
+    fn from_octet_string(bytes: &[u8]) -> Result<Self, Self::Err> {
+        let mut r = Reader(bytes);
+        let version = r.u32()?;
+        if !matches!(version, 1 | 2) {
+            return Err(InvalidReplMetaData);
+        }
+        let _reserved = r.u32()?;
+        let count = r.u32()?;
+        let _reserved = r.u32()?;
+        let cursors = (0..count)
+            .map(|_| {
+                Ok(UpToDateCursor {
+                    invocation_id: r.guid()?,
+                    highest_usn: r.i64()?,
+                    last_sync_success: match version {
+                        2 => dstime(r.u64()?),
+                        _ => None,
+                    },
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        r.finish()?;
+        Ok(Self(cursors))
+    }
+}
+
+/// Converts a time stored as whole seconds since 1601, the `DSTIME` of MS-DRSR, `None` for 0 or a time before the
+/// Unix epoch
+fn dstime(seconds: u64) -> Option<SystemTime> {
+    let since_unix = seconds.checked_sub(FILETIME_UNIX_OFFSET)?;
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(since_unix))
+}
+
+/// Little-endian fields, as the blobs are NDR-encoded
+struct Reader<'a>(&'a [u8]);
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], InvalidReplMetaData> {
+        let (bytes, rest) = self.0.split_first_chunk().ok_or(InvalidReplMetaData)?;
+        self.0 = rest;
+        Ok(*bytes)
+    }
+    fn u32(&mut self) -> Result<u32, InvalidReplMetaData> {
+        self.take().map(u32::from_le_bytes)
+    }
+    fn u64(&mut self) -> Result<u64, InvalidReplMetaData> {
+        self.take().map(u64::from_le_bytes)
+    }
+    fn i64(&mut self) -> Result<i64, InvalidReplMetaData> {
+        self.take().map(i64::from_le_bytes)
+    }
+    fn guid(&mut self) -> Result<[u8; 16], InvalidReplMetaData> {
+        self.take()
+    }
+    fn finish(self) -> Result<(), InvalidReplMetaData> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidReplMetaData)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidReplMetaData;
+impl Error for InvalidReplMetaData {}
+impl Display for InvalidReplMetaData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "invalid replication metadata")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use super::{ReplPropertyMetaData, ReplUpToDateVector};
+    use crate::search::FromOctetString;
+
+    /// A `replPropertyMetaData` value with entries for objectClass and cn, the times are
+    /// 2024-01-01T00:00:00Z and 2024-01-01T01:02:03Z
+    const PROPERTY_META_DATA: [u8; 112] = [
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, //
+        0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x80, 0x91, 0xa2, 0x1b, 0x03, 0x00, 0x00,
+        0x00, //
+        0x5c, 0x2f, 0x6e, 0x9b, 0x0a, 0x41, 0x4f, 0x4e, 0x8d, 0x1e, 0x77, 0x3a, 0xc0, 0xf2, 0x18,
+        0x64, //
+        0x39, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x39, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, //
+        0x03, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x0b, 0xa0, 0xa2, 0x1b, 0x03, 0x00, 0x00,
+        0x00, //
+        0x5c, 0x2f, 0x6e, 0x9b, 0x0a, 0x41, 0x4f, 0x4e, 0x8d, 0x1e, 0x77, 0x3a, 0xc0, 0xf2, 0x18,
+        0x64, //
+        0x7e, 0x31, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xa6, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, //
+    ];
+
+    #[test]
+    fn property_meta_data() {
+        let ReplPropertyMetaData(entries) =
+            ReplPropertyMetaData::from_octet_string(&PROPERTY_META_DATA).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].attribute_id, 0);
+        assert_eq!(entries[0].version, 1);
+        assert_eq!(
+            entries[0].originating_change_time,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200))
+        );
+        assert_eq!(entries[0].originating_usn, 12345);
+        assert_eq!(entries[0].local_usn, 12345);
+        assert_eq!(entries[1].attribute_id, 3);
+        assert_eq!(entries[1].version, 2);
+        assert_eq!(
+            entries[1].originating_change_time,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_070_923))
+        );
+        assert_eq!(entries[1].originating_usn, 12670);
+        assert_eq!(entries[1].local_usn, 678);
+
+        assert!(ReplPropertyMetaData::from_octet_string(&PROPERTY_META_DATA[..111]).is_err());
+    }
+
+    #[test]
+    fn up_to_date_vector() {
+        let mut bytes = vec![2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        bytes.extend_from_slice(&[0x11; 16]);
+        bytes.extend_from_slice(&99i64.to_le_bytes());
+        bytes.extend_from_slice(&13_348_540_800u64.to_le_bytes());
+        let ReplUpToDateVector(cursors) = ReplUpToDateVector::from_octet_string(&bytes).unwrap();
+        assert_eq!(cursors[0].highest_usn, 99);
+        assert_eq!(
+            cursors[0].last_sync_success,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200))
+        );
+
+        // version 1 has no sync time
+        bytes[0] = 1;
+        assert!(ReplUpToDateVector::from_octet_string(&bytes).is_err());
+        assert!(ReplUpToDateVector::from_octet_string(&bytes[..bytes.len() - 8]).is_ok());
+    }
+}
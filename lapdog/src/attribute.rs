@@ -5,6 +5,45 @@ use crate::{
     tag::{OCTET_STRING, UNIVERSAL_SEQUENCE},
 };
 
+/// Whether an attribute the server returned answers a requested attribute description, e.g. `userCertificate;binary`.
+///
+/// Attribute types and options are compared case-insensitively, and options in any order. The `binary` transfer
+/// option is ignored: servers may return an attribute requested with it without it, or add it on their own like
+/// OpenLDAP does for `userCertificate`. Other options, e.g. `lang-de`, have to match.
+///
+/// Subtypes don't match either: a bare `cn` only matches `cn`, not `cn;lang-fr`, even though a server returns both
+/// for a request of `cn`. That way language variants can be read into fields of their own.
+///
+/// ```
+/// use lapdog::attribute::description_matches;
+///
+/// assert!(description_matches("userCertificate;binary", "usercertificate"));
+/// assert!(description_matches("jpegPhoto", "jpegPhoto;binary"));
+/// assert!(description_matches("cn;lang-de;phonetic", "CN;phonetic;lang-de"));
+/// assert!(!description_matches("cn;lang-de", "cn"));
+/// assert!(!description_matches("cn", "cn;lang-fr"));
+/// ```
+pub fn description_matches(requested: &str, returned: &str) -> bool {
+    description_key(requested) == description_key(returned)
+}
+
+/// The form [`description_matches`] compares, used by the code of `#[derive(Entry)]` to look up attributes.
+#[doc(hidden)]
+pub fn description_key(description: &str) -> String {
+    let mut parts = description.split(';');
+    let mut key = parts.next().unwrap_or_default().to_ascii_lowercase();
+    let mut options: Vec<String> = parts
+        .filter(|option| !option.eq_ignore_ascii_case("binary"))
+        .map(str::to_ascii_lowercase)
+        .collect();
+    options.sort_unstable();
+    for option in options {
+        key.push(';');
+        key.push_str(&option);
+    }
+    key
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct AttributeValueAssertion<'d> {
     pub attribute_desc: &'d str,
@@ -1,12 +1,17 @@
 use std::borrow::Cow;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "kerberos"), allow(dead_code))]
 pub enum Authentication<'a> {
+    Simple {
+        password: &'a [u8],
+    },
     Sasl {
         mechanism: SaslMechanism,
         credentials: Option<Cow<'a, [u8]>>,
     },
 }
+#[cfg_attr(not(feature = "kerberos"), allow(dead_code))]
 impl Authentication<'_> {
     pub fn sasl_kerberos<'t>(token: Option<&'t [u8]>) -> Authentication<'t> {
         Authentication::Sasl {
@@ -18,7 +23,9 @@ impl Authentication<'_> {
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[allow(clippy::upper_case_acronyms)]
+#[cfg_attr(not(feature = "kerberos"), allow(dead_code))]
 pub enum SaslMechanism {
     GSSAPI,
     GSSSPNEGO,
+    EXTERNAL,
 }
@@ -1,11 +1,21 @@
-use std::io::Read;
+use std::{
+    borrow::Cow,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::Read,
+};
 
 const SASL_CREDS: u8 = TagClass::Universal.into_bits() | PrimOrCons::Primitive.into_bit() | 0x7;
+const SIMPLE: u8 = TagClass::ContextSpecific.into_bits() | PrimOrCons::Primitive.into_bit();
+const REFERRAL: u8 = TagClass::ContextSpecific.into_bits() | PrimOrCons::Constructed.into_bit() | 0x3;
 
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use crate::TlsConnection;
 use crate::{
-    LDAP_VERSION, WriteExt,
+    EncodeError, LDAP_VERSION, LdapConnection, ResponseProtocolOp, SendMessageError, WriteExt,
     auth::{Authentication, SaslMechanism},
+    extended::ExtendedOpError,
     length::{LengthError, read_length},
+    message::{ProtocolOp, ReadProtocolOpError, RequestProtocolOp},
     parse::{ParseLdap, ReadIntegerError},
     read::ReadExt,
     result::ResultCode,
@@ -13,12 +23,269 @@ use crate::{
         OCTET_STRING, PrimitiveOrConstructed as PrimOrCons, TagClass, UNIVERSAL_ENUMERATED,
         UNIVERSAL_INTEGER, get_tag_number,
     },
+    url::{LdapUrl, Scheme, select_referral},
 };
 
 #[cfg(feature = "kerberos")]
 pub mod kerberos;
 
-pub(crate) fn write_bind(auth: &Authentication) -> Vec<u8> {
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+impl TlsConnection {
+    /// Binds with a name (usually a DN) and password.
+    ///
+    /// Only available on TLS connections, so the password can't leak; connect with
+    /// [`TlsConnection::new`] or upgrade with [`LdapConnection::start_tls`] first.
+    pub async fn bind_simple(&mut self, name: &str, password: &[u8]) -> Result<(), SimpleBindError> {
+        self.bind_simple_unchecked(name, password).await
+    }
+    /// Binds like [`TlsConnection::bind_simple`], then asks the server with [`LdapConnection::who_am_i`] which
+    /// identity the connection is bound as.
+    ///
+    /// Servers can map the bind name to another identity, e.g. with `authz-regexp` in OpenLDAP. Checking right away
+    /// catches a mapping that doesn't do what was intended. Returns the authorization ID like
+    /// `dn:cn=bob,dc=example,dc=com`, `None` if the server considers the connection anonymous.
+    pub async fn bind_and_whoami(
+        &mut self,
+        name: &str,
+        password: &[u8],
+    ) -> Result<Option<String>, BindAndWhoAmIError> {
+        self.bind_simple(name, password)
+            .await
+            .map_err(BindAndWhoAmIError::Bind)?;
+        self.who_am_i().await.map_err(BindAndWhoAmIError::WhoAmI)
+    }
+}
+
+impl LdapConnection {
+    /// Binds anonymously, dropping the privileges of an earlier bind, e.g. before returning a connection to a pool
+    /// so the next user doesn't act as the previous one.
+    ///
+    /// No password is sent, so unlike `TlsConnection::bind_simple` this works on unencrypted connections too.
+    pub async fn bind_anonymously(&mut self) -> Result<(), SimpleBindError> {
+        self.bind_simple_unchecked("", b"").await
+    }
+    /// Binds with a name and password without checking whether the connection is encrypted.
+    ///
+    /// On a plain connection, the password is sent in cleartext. This is the one way around
+    /// `TlsConnection::bind_simple`, so it needs the `dangerous-cleartext-bind` feature; only use it on trusted
+    /// networks, e.g. for a test server on localhost.
+    #[cfg(feature = "dangerous-cleartext-bind")]
+    pub async fn unsafe_bind_simple_unencrypted(
+        &mut self,
+        name: &str,
+        password: &[u8],
+    ) -> Result<(), SimpleBindError> {
+        self.bind_simple_unchecked(name, password).await
+    }
+    pub(crate) async fn bind_simple_unchecked(
+        &mut self,
+        name: &str,
+        password: &[u8],
+    ) -> Result<(), SimpleBindError> {
+        let response = self
+            .send_message(RequestProtocolOp::Bind {
+                name,
+                authentication: Authentication::Simple { password },
+            })
+            .await?
+            .into_message();
+        let ResponseProtocolOp::Bind {
+            status: BindStatus::Finished,
+            ..
+        } = ResponseProtocolOp::read_from(&mut response.as_slice())?
+        else {
+            return Err(SimpleBindError::InvalidSchema);
+        };
+        Ok(())
+    }
+    /// Binds with SASL EXTERNAL, i.e. with an identity the server already knows from outside LDAP,
+    /// usually the client certificate of the TLS connection, see
+    /// [`StreamConfig::native_tls_with_identity`](crate::StreamConfig::native_tls_with_identity).
+    ///
+    /// The server has to map the certificate to a directory identity, e.g. with `authz-regexp` in OpenLDAP.
+    /// `authorization_id` asks to act as a different identity, like `dn:cn=admin,dc=example,dc=com`;
+    /// `None` uses the identity derived from the certificate.
+    pub async fn bind_sasl_external(
+        &mut self,
+        authorization_id: Option<&str>,
+    ) -> Result<(), ExternalBindError> {
+        let response = self
+            .send_message(RequestProtocolOp::Bind {
+                name: "",
+                authentication: Authentication::Sasl {
+                    mechanism: SaslMechanism::EXTERNAL,
+                    credentials: authorization_id.map(|id| Cow::Borrowed(id.as_bytes())),
+                },
+            })
+            .await?
+            .into_message();
+        let ResponseProtocolOp::Bind {
+            status: BindStatus::Finished,
+            ..
+        } = ResponseProtocolOp::read_from(&mut response.as_slice())?
+        else {
+            return Err(ExternalBindError::InvalidSchema);
+        };
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ExternalBindError {
+    Io(std::io::Error),
+    Encode(EncodeError),
+    InvalidSchema,
+    Disconnected,
+    ServerError { code: ResultCode, message: String },
+}
+impl From<SendMessageError> for ExternalBindError {
+    fn from(value: SendMessageError) -> Self {
+        match value {
+            SendMessageError::Io(error) => Self::Io(error),
+            SendMessageError::Encode(e) => Self::Encode(e),
+            SendMessageError::ChannelClosed | SendMessageError::ReceiveMessage(_) => Self::Disconnected,
+        }
+    }
+}
+impl From<ReadProtocolOpError> for ExternalBindError {
+    fn from(value: ReadProtocolOpError) -> Self {
+        match value {
+            ReadProtocolOpError::Io(error) => Self::Io(error),
+            ReadProtocolOpError::ServerError { code, message } => Self::ServerError { code, message },
+            ReadProtocolOpError::Referral(urls) => Self::ServerError {
+                code: ResultCode::Referral,
+                message: urls.join(" "),
+            },
+            ReadProtocolOpError::InvalidSchema => Self::InvalidSchema,
+        }
+    }
+}
+impl std::error::Error for ExternalBindError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(io) => Some(io),
+            Self::Encode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl Display for ExternalBindError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Io(error) => write!(f, "An IO error occured: {error}"),
+            Self::Encode(e) => write!(f, "Failed to encode request: {e}"),
+            Self::InvalidSchema => write!(f, "Server returned an invalid message"),
+            Self::Disconnected => write!(f, "Connection disconnected"),
+            Self::ServerError { code, message } => {
+                write!(f, "Server returned an error. Code: {code} (\"{message}\")",)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SimpleBindError {
+    Io(std::io::Error),
+    Encode(EncodeError),
+    InvalidSchema,
+    Disconnected,
+    ServerError {
+        code: ResultCode,
+        message: String,
+    },
+    /// The server can't handle the bind and refers to other servers, see [`SimpleBindError::referral`]
+    Referral(Vec<String>),
+    /// The server requires a stronger authentication, usually because it only accepts simple binds over TLS.
+    /// Upgrade the connection with `start_tls` or bind with SASL instead.
+    StrongerAuthRequired(String),
+}
+impl SimpleBindError {
+    /// The server to retry the bind with if the server returned a referral,
+    /// preferring URLs with the `preferred` scheme. Malformed URLs are skipped, see [`select_referral`].
+    ///
+    /// The [`LdapUrl::bind_name`] of the result is the DN the server suggests binding as, if any.
+    pub fn referral(&self, preferred: Scheme) -> Option<LdapUrl> {
+        let Self::Referral(urls) = self else {
+            return None;
+        };
+        select_referral(urls.iter().map(String::as_str), preferred)
+    }
+}
+impl From<SendMessageError> for SimpleBindError {
+    fn from(value: SendMessageError) -> Self {
+        match value {
+            SendMessageError::Io(error) => Self::Io(error),
+            SendMessageError::Encode(e) => Self::Encode(e),
+            SendMessageError::ChannelClosed | SendMessageError::ReceiveMessage(_) => Self::Disconnected,
+        }
+    }
+}
+impl From<ReadProtocolOpError> for SimpleBindError {
+    fn from(value: ReadProtocolOpError) -> Self {
+        match value {
+            ReadProtocolOpError::Io(error) => Self::Io(error),
+            ReadProtocolOpError::ServerError {
+                code: ResultCode::StrongerAuthRequired,
+                message,
+            } => Self::StrongerAuthRequired(message),
+            ReadProtocolOpError::ServerError { code, message } => Self::ServerError { code, message },
+            ReadProtocolOpError::Referral(urls) => Self::Referral(urls),
+            ReadProtocolOpError::InvalidSchema => Self::InvalidSchema,
+        }
+    }
+}
+impl std::error::Error for SimpleBindError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(io) => Some(io),
+            Self::Encode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl Display for SimpleBindError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Io(error) => write!(f, "An IO error occured: {error}"),
+            Self::Encode(e) => write!(f, "Failed to encode request: {e}"),
+            Self::InvalidSchema => write!(f, "Server returned an invalid message"),
+            Self::Disconnected => write!(f, "Connection disconnected"),
+            Self::ServerError { code, message } => {
+                write!(f, "Server returned an error. Code: {code} (\"{message}\")",)
+            }
+            Self::Referral(urls) => write!(f, "Server returned a referral to {}", urls.join(", ")),
+            Self::StrongerAuthRequired(message) => write!(
+                f,
+                "Server requires stronger authentication, use StartTLS or a SASL bind (\"{message}\")"
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BindAndWhoAmIError {
+    Bind(SimpleBindError),
+    /// The bind succeeded, but the Who Am I? request failed
+    WhoAmI(ExtendedOpError),
+}
+impl std::error::Error for BindAndWhoAmIError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Bind(e) => Some(e),
+            Self::WhoAmI(e) => Some(e),
+        }
+    }
+}
+impl Display for BindAndWhoAmIError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Bind(e) => e.fmt(f),
+            Self::WhoAmI(e) => write!(f, "Bound, but failed to confirm the identity: {e}"),
+        }
+    }
+}
+
+pub(crate) fn write_bind(name: &str, auth: &Authentication) -> Vec<u8> {
     let mut bind_msg = Vec::new();
     // version
     bind_msg.push(UNIVERSAL_INTEGER);
@@ -27,33 +294,45 @@ pub(crate) fn write_bind(auth: &Authentication) -> Vec<u8> {
 
     // name
     bind_msg.push(TagClass::Universal.into_bits() | PrimOrCons::Primitive.into_bit() | 0x04);
-    bind_msg.write_ber_length(0).expect("infallible");
+    bind_msg.write_ber_length(name.len()).expect("infallible");
+    bind_msg.extend_from_slice(name.as_bytes());
 
     // authentication
-    let Authentication::Sasl {
-        mechanism,
-        credentials,
-    } = auth;
-    bind_msg
-        .write_sequence(
-            TagClass::ContextSpecific.into_bits() | PrimOrCons::Constructed.into_bit() | 0x3,
-            |sasl| {
-                sasl.push(OCTET_STRING);
-                let mech = match mechanism {
-                    SaslMechanism::GSSAPI => "GSSAPI",
-                    SaslMechanism::GSSSPNEGO => "GSS-SPNEGO",
-                };
-                sasl.write_ber_length(mech.len()).expect("infallible");
-                sasl.extend(mech.as_bytes());
-                if let Some(cred) = credentials {
-                    sasl.push(TagClass::Universal.into_bits() | PrimOrCons::Primitive.into_bit() | 0x04);
-                    sasl.write_ber_length(cred.len()).expect("infallible");
-                    sasl.extend_from_slice(cred);
-                }
-                Ok(())
-            },
-        )
-        .expect("infallible");
+    match auth {
+        Authentication::Simple { password } => {
+            bind_msg.push(SIMPLE);
+            bind_msg.write_ber_length(password.len()).expect("infallible");
+            bind_msg.extend_from_slice(password);
+        }
+        Authentication::Sasl {
+            mechanism,
+            credentials,
+        } => {
+            bind_msg
+                .write_sequence(
+                    TagClass::ContextSpecific.into_bits() | PrimOrCons::Constructed.into_bit() | 0x3,
+                    |sasl| {
+                        sasl.push(OCTET_STRING);
+                        let mech = match mechanism {
+                            SaslMechanism::GSSAPI => "GSSAPI",
+                            SaslMechanism::GSSSPNEGO => "GSS-SPNEGO",
+                            SaslMechanism::EXTERNAL => "EXTERNAL",
+                        };
+                        sasl.write_ber_length(mech.len()).expect("infallible");
+                        sasl.extend(mech.as_bytes());
+                        if let Some(cred) = credentials {
+                            sasl.push(
+                                TagClass::Universal.into_bits() | PrimOrCons::Primitive.into_bit() | 0x04,
+                            );
+                            sasl.write_ber_length(cred.len()).expect("infallible");
+                            sasl.extend_from_slice(cred);
+                        }
+                        Ok(())
+                    },
+                )
+                .expect("infallible");
+        }
+    }
     bind_msg
 }
 
@@ -66,7 +345,7 @@ pub(crate) fn read_response<R: Read>(mut r: R) -> Result<BindResponse, ReadBindE
     let enum_int = i
         .try_into()
         .ok()
-        .and_then(ResultCode::from_code)
+        .and_then(ResultCode::from_u16)
         .ok_or(ReadBindError::InvalidResultCode)?;
     let bind_status = match enum_int {
         ResultCode::Success => Ok(BindStatus::Finished),
@@ -93,14 +372,31 @@ pub(crate) fn read_response<R: Read>(mut r: R) -> Result<BindResponse, ReadBindE
     let mut diagnostics_message = vec![0; diagnostics_len];
     r.read_exact(&mut diagnostics_message)?;
     let diagnostics_message = String::from_utf8_lossy(&diagnostics_message).to_string();
-    let bind_status = bind_status.map_err(|code| ReadBindError::BindError {
-        code,
-        message: diagnostics_message.clone(),
-    })?;
-
-    let referral_or_sasl_tag = r.read_single_byte()?;
-    let sasl_creds = match get_tag_number(referral_or_sasl_tag) {
-        0x3 => panic!("unexpected referral"),
+    let bind_status = match bind_status {
+        Ok(status) => status,
+        Err(ResultCode::Referral) => return Err(ReadBindError::Referral(read_referral(&mut r)?)),
+        Err(code) => {
+            return Err(ReadBindError::BindError {
+                code,
+                message: diagnostics_message,
+            });
+        }
+    };
+
+    // Simple binds don't get any server credentials, so the message may end here
+    let mut rest = Vec::new();
+    r.read_to_end(&mut rest)?;
+    let mut r = rest.as_slice();
+    if r.is_empty() {
+        return Ok(BindResponse {
+            bind_status,
+            sasl_creds: None,
+            diagnostics_message,
+            matched_dn,
+        });
+    }
+    let sasl_tag = r.read_single_byte()?;
+    let sasl_creds = match get_tag_number(sasl_tag) {
         SASL_CREDS => {
             let sasl_len = read_length(&mut r)?;
             if sasl_len == 0 {
@@ -111,7 +407,7 @@ pub(crate) fn read_response<R: Read>(mut r: R) -> Result<BindResponse, ReadBindE
                 Some(sasl_creds)
             }
         }
-        _ => todo!(),
+        _ => return Err(ReadBindError::InvalidSchema),
     };
     Ok(BindResponse {
         bind_status,
@@ -121,10 +417,31 @@ pub(crate) fn read_response<R: Read>(mut r: R) -> Result<BindResponse, ReadBindE
     })
 }
 
+/// Reads the `referral` field of an LDAPResult, the URLs of servers to retry the request with
+fn read_referral(mut r: impl Read) -> Result<Vec<String>, ReadBindError> {
+    if r.read_single_byte()? != REFERRAL {
+        return Err(ReadBindError::InvalidSchema);
+    }
+    let len = read_length(&mut r)?;
+    let mut r = r.take(len as u64);
+    let mut urls = Vec::new();
+    while r.limit() > 0 {
+        if r.read_single_byte()? != OCTET_STRING {
+            return Err(ReadBindError::InvalidSchema);
+        }
+        let url_len = read_length(&mut r)?;
+        let mut url = vec![0; url_len];
+        r.read_exact(&mut url)?;
+        urls.push(String::from_utf8(url).map_err(|_| ReadBindError::InvalidSchema)?);
+    }
+    Ok(urls)
+}
+
 #[derive(Debug)]
 pub(crate) enum ReadBindError {
     Io(std::io::Error),
     BindError { code: ResultCode, message: String },
+    Referral(Vec<String>),
     InvalidResultCode,
     InvalidSchema,
 }
@@ -145,7 +462,7 @@ impl From<ReadIntegerError> for ReadBindError {
     fn from(value: ReadIntegerError) -> Self {
         match value {
             ReadIntegerError::Io(io) => ReadBindError::Io(io),
-            ReadIntegerError::Length(_) | ReadIntegerError::OutOfRange => Self::InvalidSchema,
+            ReadIntegerError::Length | ReadIntegerError::OutOfRange => Self::InvalidSchema,
         }
     }
 }
@@ -165,3 +482,57 @@ pub(crate) struct BindResponse {
     #[expect(dead_code)]
     pub matched_dn: String,
 }
+
+#[cfg(test)]
+mod test {
+    use super::{ReadBindError, ReadProtocolOpError, ResultCode, SimpleBindError, read_response};
+
+    #[test]
+    fn referral_response() {
+        // resultCode referral, empty matchedDN and diagnosticMessage, one referral URL
+        let mut response = vec![0x0a, 0x01, 0x0a, 0x04, 0x00, 0x04, 0x00, 0xa3, 0x16, 0x04, 0x14];
+        response.extend_from_slice(b"ldap://dc2.x/dc=x??b");
+        let Err(ReadBindError::Referral(urls)) = read_response(response.as_slice()) else {
+            panic!("expected a referral");
+        };
+        assert_eq!(urls, ["ldap://dc2.x/dc=x??b"]);
+    }
+
+    #[tokio::test]
+    async fn anonymous_bind_on_plain_connection() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        use crate::test::mock_connection;
+        let (mut connection, mut server) = mock_connection().await;
+        let server = tokio::spawn(async move {
+            let mut request = [0; 14];
+            server.read_exact(&mut request).await.unwrap();
+            // success
+            let response = [
+                0x30, 0x0c, 0x02, 0x01, 0x01, 0x61, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00,
+            ];
+            server.write_all(&response).await.unwrap();
+            request
+        });
+        connection.bind_anonymously().await.unwrap();
+        assert_eq!(connection.bytes_received(), 14);
+        // version 3, empty name and password
+        assert_eq!(
+            server.await.unwrap(),
+            [
+                0x30, 0x0c, 0x02, 0x01, 0x01, 0x60, 0x07, 0x02, 0x01, 0x03, 0x04, 0x00, 0x80, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn stronger_auth_required() {
+        let error = SimpleBindError::from(ReadProtocolOpError::ServerError {
+            code: ResultCode::StrongerAuthRequired,
+            message: "confidentiality required".to_string(),
+        });
+        assert!(
+            matches!(error, SimpleBindError::StrongerAuthRequired(ref m) if m == "confidentiality required")
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::{ops::Deref, sync::Arc};
+use std::{borrow::Cow, ops::Deref, sync::Arc};
 
 use kenobi::{
     client::{ClientBuilder, ClientContext, InitializeError, StepOut},
@@ -9,7 +9,7 @@ use kenobi::{
 use tokio::sync::{Mutex, mpsc, oneshot};
 
 use crate::{
-    LdapConnection, RequestProtocolOp, ResponseProtocolOp, SendMessageError, StreamWriteHalf,
+    EncodeError, LdapConnection, RequestProtocolOp, ResponseProtocolOp, SendMessageError, StreamWriteHalf,
     auth::{Authentication, SaslMechanism},
     bind::BindStatus,
     message::{ProtocolOp, ReadProtocolOpError},
@@ -68,23 +68,75 @@ impl MaybeEncryptClientContext {
     }
 }
 
+/// Turns the host-based service form `ldap@host` into the principal form `ldap/host`, which both GSSAPI and SSPI
+/// accept. Principals like `ldap/host` and `ldap/host@REALM` are passed through.
+fn normalize_spn(spn: &str) -> Cow<'_, str> {
+    match spn.split_once('@') {
+        Some((service, host)) if !service.contains('/') && !host.is_empty() => {
+            Cow::Owned(format!("{service}/{host}"))
+        }
+        _ => Cow::Borrowed(spn),
+    }
+}
+
 type FinishedClientContext = ClientContext<Outbound, MaybeSigning, MaybeEncryption, MaybeDelegation>;
 impl LdapConnection {
     /// Binds the current connection via Kerberos using the kenobi crate.
     /// The mechanism used depends on the underlying mechanism on the Kenobi credentials handle.
     /// If the connection is already on TLS, channel bindings will be provided.
     /// If the connection is not, it will upgrade to a GSSAPI-wrapped LDAP stream.
+    ///
+    /// `spn` may be given as `ldap/host`, `ldap/host@REALM` or in the host-based service form `ldap@host`.
+    /// On Windows, [`Credentials::outbound`] with no principal uses the logged-in user's ticket through SSPI,
+    /// no keytab is needed.
+    ///
+    /// The credential source is chosen when creating `cred`: pass a principal to [`Credentials::outbound`] to use
+    /// that principal's ticket instead of the default one. On Unix, GSSAPI reads the client keytab from
+    /// `KRB5_CLIENT_KTNAME` and the ticket cache from `KRB5CCNAME`. Set them for daemons, e.g. with `Environment=`
+    /// in a systemd unit, before acquiring the credentials.
+    ///
+    /// There's no variant taking a principal and password: kenobi only acquires credentials that already exist, from
+    /// the ticket cache, a keytab or the Windows logon session. For service accounts without a keytab, get a ticket
+    /// first, e.g. with `kinit` into a cache named by `KRB5CCNAME`.
     pub async fn bind_sasl_kenobi(
         &mut self,
         cred: Credentials<Outbound>,
         spn: Option<&str>,
+    ) -> Result<(), BindError> {
+        self.bind_kenobi(cred, spn, None).await
+    }
+    /// Like [`LdapConnection::bind_sasl_kenobi`], but asks the server to act as `authzid` instead of the
+    /// authenticated principal, e.g. `u:otheruser` or `dn:cn=otheruser,dc=example,dc=com`.
+    ///
+    /// The authorization identity is sent in the final GSSAPI security layer message (RFC 4752), so this needs a
+    /// Kerberos credentials handle; SPNEGO has no such message and fails with [`BindError::AuthzIdUnsupported`].
+    /// The server has to allow the principal to assume the identity, e.g. through `authzTo` in OpenLDAP,
+    /// otherwise the bind fails with [`BindError::AuthzIdRejected`].
+    pub async fn bind_sasl_kenobi_with_authzid(
+        &mut self,
+        cred: Credentials<Outbound>,
+        spn: Option<&str>,
+        authzid: &str,
+    ) -> Result<(), BindError> {
+        self.bind_kenobi(cred, spn, Some(authzid)).await
+    }
+    async fn bind_kenobi(
+        &mut self,
+        cred: Credentials<Outbound>,
+        spn: Option<&str>,
+        authzid: Option<&str>,
     ) -> Result<(), BindError> {
         use kenobi::mech::Mechanism;
 
+        let spn = spn.map(normalize_spn);
+        let spn = spn.as_deref();
         let mech = match cred.mechanism() {
             Mechanism::KerberosV5 => SaslMechanism::GSSAPI,
             Mechanism::Spnego => SaslMechanism::GSSSPNEGO,
         };
+        if authzid.is_some() && mech == SaslMechanism::GSSSPNEGO {
+            return Err(BindError::AuthzIdUnsupported);
+        }
         let is_tls = match self.tcp.lock().await.as_ref().unwrap() {
             StreamWriteHalf::Plain(_) => false,
             #[cfg(feature = "native-tls")]
@@ -95,11 +147,11 @@ impl LdapConnection {
         };
         if is_tls {
             #[cfg(any(feature = "native-tls", feature = "rustls"))]
-            return self.bind_gss_tls(cred, mech, spn).await;
+            return self.bind_gss_tls(cred, mech, spn, authzid).await;
             #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
             unreachable!()
         } else {
-            self.bind_gss(cred, mech, spn).await
+            self.bind_gss(cred, mech, spn, authzid).await
         }
     }
 
@@ -109,12 +161,11 @@ impl LdapConnection {
         cred: Credentials<Outbound>,
         mechanism: SaslMechanism,
         spn: Option<&str>,
+        authzid: Option<&str>,
     ) -> Result<(), BindError> {
-        let inflight_requests = self.inflight_requests.lock().await;
-        if !inflight_requests.is_empty() {
-            panic!("cannot be active requests in flight for bind operations");
+        if !self.inflight_requests.lock().await.is_empty() {
+            return Err(BindError::RequestsInFlight);
         }
-        drop(inflight_requests);
         // take both streams, join them for the channel binding, give them back
         let (return_envelope, rec_stream_half) = tokio::sync::oneshot::channel();
         let (give_back_stream_half, return_return_envelope) = tokio::sync::oneshot::channel();
@@ -126,9 +177,9 @@ impl LdapConnection {
             let (mut own_lock, read_half) = tokio::join!(self.tcp.lock(), rec_stream_half);
             use crate::stream::Stream;
             let write = own_lock.take().unwrap();
-            let stream = Stream::unsplit(read_half.unwrap(), write);
+            let stream = Stream::unsplit(read_half.unwrap(), write).map_err(BindError::Io)?;
             let client_builder = get_context_builder(cred, spn, mechanism, true).bind_to_channel(&stream);
-            let (r, w) = stream.split();
+            let (r, w) = stream.split(self.read_buffer_size);
             *own_lock = Some(w);
             if give_back_stream_half.send(r).is_err() {
                 panic!("read half was dropped before we could give it back to the main loop")
@@ -146,7 +197,7 @@ impl LdapConnection {
                 let Ok(signing) = ctx.check_signing() else {
                     return Err(BindError::Insecure);
                 };
-                self.do_kerberos_negotiation_exchange(signing).await?;
+                self.do_kerberos_negotiation_exchange(signing, authzid).await?;
                 Ok(())
             }
             _ => todo!(),
@@ -159,12 +210,11 @@ impl LdapConnection {
         cred: Credentials<Outbound>,
         mechanism: SaslMechanism,
         spn: Option<&str>,
+        authzid: Option<&str>,
     ) -> Result<(), BindError> {
-        let inflight_requests = self.inflight_requests.lock().await;
-        if !inflight_requests.is_empty() {
-            panic!("cannot be active requests in flight for bind operations");
+        if !self.inflight_requests.lock().await.is_empty() {
+            return Err(BindError::RequestsInFlight);
         }
-        drop(inflight_requests);
         let client_builder = get_context_builder(cred, spn, mechanism, false);
         let (Some(finished_ctx), server_status) = self.exchange_gss_tokens(client_builder, mechanism).await?
         else {
@@ -185,20 +235,29 @@ impl LdapConnection {
                     kind,
                     sign_only: false,
                 };
-                encrypt_stream(&mut self.yoink_read_half, &self.tcp, Arc::new(Mutex::new(ctx))).await;
+                encrypt_stream(
+                    &mut self.yoink_read_half,
+                    &self.tcp,
+                    Arc::new(Mutex::new(ctx)),
+                    self.read_buffer_size,
+                )
+                .await
+                .map_err(BindError::Io)?;
                 Ok(())
             }
             (SaslMechanism::GSSAPI, BindStatus::Pending) => {
                 let Ok(signing) = finished_ctx.check_signing() else {
                     return Err(BindError::Insecure);
                 };
-                let enc_layer = self.do_kerberos_negotiation_exchange(signing).await?;
+                let enc_layer = self.do_kerberos_negotiation_exchange(signing, authzid).await?;
                 encrypt_stream(
                     &mut self.yoink_read_half,
                     &self.tcp,
                     Arc::new(Mutex::new(enc_layer)),
+                    self.read_buffer_size,
                 )
-                .await;
+                .await
+                .map_err(BindError::Io)?;
                 Ok(())
             }
             (_, _) => todo!(),
@@ -220,6 +279,7 @@ impl LdapConnection {
                 };
                 let body = self
                     .send_message(RequestProtocolOp::Bind {
+                        name: "",
                         authentication: Authentication::Sasl {
                             mechanism,
                             credentials: Some(token.into()),
@@ -238,6 +298,7 @@ impl LdapConnection {
                 use std::borrow::Cow;
                 let body = self
                     .send_message(RequestProtocolOp::Bind {
+                        name: "",
                         authentication: Authentication::Sasl {
                             mechanism,
                             credentials: Some(Cow::Borrowed(ctx.next_token())),
@@ -266,11 +327,15 @@ impl LdapConnection {
     async fn do_kerberos_negotiation_exchange(
         &self,
         mut ctx: ClientContext<Outbound, Signing, MaybeEncryption, MaybeDelegation>,
+        authzid: Option<&str>,
     ) -> Result<MaybeEncryptClientContext, BindError> {
         // Send empty token to prompt security layer negotiation
         let authentication = Authentication::sasl_kerberos(None);
         let body = self
-            .send_message(RequestProtocolOp::Bind { authentication })
+            .send_message(RequestProtocolOp::Bind {
+                name: "",
+                authentication,
+            })
             .await?
             .into_message();
         let ResponseProtocolOp::Bind {
@@ -300,11 +365,12 @@ impl LdapConnection {
             (BindSecurityOffer::Encryption, Ok(_)) => false,
         };
         buffer[0] = if sign_only { 0x2 } else { 0x4 };
+        let message = security_layer_message(buffer, authzid);
 
         // Wrap the last token and send it
         let wrapped = match &mut maybe_encrypt {
-            Ok(s) => s.sign(&buffer)?,
-            Err(e) => e.sign(&buffer)?,
+            Ok(s) => s.sign(&message)?,
+            Err(e) => e.sign(&message)?,
         };
         let kind = match maybe_encrypt {
             Ok(can_encrypt) => InnerContext::CanEncrypt(can_encrypt),
@@ -313,13 +379,23 @@ impl LdapConnection {
         let encryption_layer = MaybeEncryptClientContext { kind, sign_only };
         let authentication = Authentication::sasl_kerberos(Some(&wrapped));
         let last_body = self
-            .send_message(RequestProtocolOp::Bind { authentication })
+            .send_message(RequestProtocolOp::Bind {
+                name: "",
+                authentication,
+            })
             .await?
             .into_message();
+        let response = match (ResponseProtocolOp::read_from(&mut last_body.as_slice()), authzid) {
+            // the principal was already authenticated, so a failure now is about the authorization identity
+            (Err(ReadProtocolOpError::ServerError { code, message }), Some(_)) => {
+                return Err(BindError::AuthzIdRejected { code, message });
+            }
+            (response, _) => response?,
+        };
         let ResponseProtocolOp::Bind {
             server_sasl_creds,
             status,
-        } = ResponseProtocolOp::read_from(&mut last_body.as_slice())?
+        } = response
         else {
             return Err(BindError::InvalidSchema);
         };
@@ -334,11 +410,20 @@ impl LdapConnection {
     }
 }
 
+/// The client's last message of the GSSAPI negotiation (RFC 4752): the chosen security layer and maximum buffer size,
+/// followed by the authorization identity if there is one
+fn security_layer_message(layer_and_size: [u8; 4], authzid: Option<&str>) -> Vec<u8> {
+    let mut message = layer_and_size.to_vec();
+    message.extend_from_slice(authzid.unwrap_or_default().as_bytes());
+    message
+}
+
 async fn encrypt_stream(
     yoink_read_half: &mut mpsc::Sender<(oneshot::Sender<StreamReadHalf>, oneshot::Receiver<StreamReadHalf>)>,
     own_stream: &Mutex<Option<StreamWriteHalf>>,
     client_ctx: Arc<Mutex<MaybeEncryptClientContext>>,
-) {
+    read_buffer_size: usize,
+) -> std::io::Result<()> {
     // take both streams, join them for the channel binding, give them back
     let (return_envelope, rec_stream_half) = tokio::sync::oneshot::channel();
     let (give_back_stream_half, return_return_envelope) = tokio::sync::oneshot::channel();
@@ -350,15 +435,16 @@ async fn encrypt_stream(
     use crate::stream::Stream;
 
     let write = own_lock.take().unwrap();
-    let mut stream = Stream::unsplit(read_half.unwrap(), write);
+    let mut stream = Stream::unsplit(read_half.unwrap(), write)?;
     if let Stream::Plain(tcp) = stream {
         stream = Stream::Kerberos(client_ctx, Default::default(), tcp)
     }
-    let (r, w) = stream.split();
+    let (r, w) = stream.split(read_buffer_size);
     *own_lock = Some(w);
     if give_back_stream_half.send(r).is_err() {
         panic!("read half was dropped before we could give it back to the main loop")
     };
+    Ok(())
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -385,7 +471,11 @@ impl BindSecurityOffer {
 #[derive(Debug)]
 pub enum BindError {
     Io(std::io::Error),
-    ServerError { code: ResultCode, message: String },
+    Encode(EncodeError),
+    ServerError {
+        code: ResultCode,
+        message: String,
+    },
     ChannelBind,
     SendOrReceive,
     GssAPIInit(InitializeError),
@@ -394,6 +484,51 @@ pub enum BindError {
     InvalidSchema,
     InvalidSecurityContext,
     InvalidServerToken,
+    /// An authorization identity needs the GSSAPI mechanism, SPNEGO can't carry it
+    AuthzIdUnsupported,
+    /// The principal authenticated, but the server didn't let it assume the requested authorization identity
+    AuthzIdRejected {
+        code: ResultCode,
+        message: String,
+    },
+    /// Other requests, e.g. searches that weren't read to their end, are still waiting for responses
+    RequestsInFlight,
+}
+impl std::error::Error for BindError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(io) => Some(io),
+            Self::Encode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl std::fmt::Display for BindError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "An IO error occured: {error}"),
+            Self::Encode(e) => write!(f, "Failed to encode request: {e}"),
+            Self::ServerError { code, message } => {
+                write!(f, "Server returned an error. Code: {code} (\"{message}\")",)
+            }
+            Self::ChannelBind => write!(f, "Failed to bind the security context to the TLS channel"),
+            Self::SendOrReceive => write!(f, "Connection disconnected"),
+            Self::GssAPIInit(e) => write!(f, "Failed to initialize the security context: {e:?}"),
+            Self::GssAPIWrap => write!(f, "Failed to wrap or unwrap a token"),
+            Self::Insecure => write!(f, "Security context offers neither signing nor encryption"),
+            Self::InvalidSchema => write!(f, "Server returned an invalid message"),
+            Self::InvalidSecurityContext => write!(f, "Security context was not established"),
+            Self::InvalidServerToken => write!(f, "Server returned an invalid token"),
+            Self::AuthzIdUnsupported => write!(f, "SPNEGO binds cannot request an authorization identity"),
+            Self::AuthzIdRejected { code, message } => {
+                write!(
+                    f,
+                    "Server rejected the authorization identity. Code: {code} (\"{message}\")"
+                )
+            }
+            Self::RequestsInFlight => write!(f, "Cannot bind while other requests are in flight"),
+        }
+    }
 }
 impl From<WrapError> for BindError {
     fn from(_: WrapError) -> Self {
@@ -409,6 +544,7 @@ impl From<SendMessageError> for BindError {
     fn from(value: SendMessageError) -> Self {
         match value {
             SendMessageError::Io(error) => BindError::Io(error),
+            SendMessageError::Encode(e) => BindError::Encode(e),
             SendMessageError::ChannelClosed | SendMessageError::ReceiveMessage(_) => Self::SendOrReceive,
         }
     }
@@ -418,7 +554,38 @@ impl From<ReadProtocolOpError> for BindError {
         match value {
             ReadProtocolOpError::Io(io_err) => BindError::Io(io_err),
             ReadProtocolOpError::ServerError { code, message } => BindError::ServerError { code, message },
+            ReadProtocolOpError::Referral(urls) => BindError::ServerError {
+                code: ResultCode::Referral,
+                message: urls.join(" "),
+            },
             ReadProtocolOpError::InvalidSchema => BindError::InvalidSchema,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{normalize_spn, security_layer_message};
+
+    #[test]
+    fn spn_formats() {
+        assert_eq!(normalize_spn("ldap@dc1.example.com"), "ldap/dc1.example.com");
+        assert_eq!(normalize_spn("ldap/dc1.example.com"), "ldap/dc1.example.com");
+        assert_eq!(
+            normalize_spn("ldap/dc1.example.com@EXAMPLE.COM"),
+            "ldap/dc1.example.com@EXAMPLE.COM"
+        );
+    }
+
+    #[test]
+    fn authzid_follows_security_layer() {
+        assert_eq!(
+            security_layer_message([0x04, 0x01, 0x00, 0x00], None),
+            [0x04, 0x01, 0x00, 0x00]
+        );
+        assert_eq!(
+            security_layer_message([0x02, 0x00, 0x10, 0x00], Some("u:bob")),
+            [0x02, 0x00, 0x10, 0x00, b'u', b':', b'b', b'o', b'b']
+        );
+    }
+}
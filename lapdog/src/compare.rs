@@ -4,8 +4,9 @@ use std::{
 };
 
 use crate::{
-    LdapConnection, ResponseProtocolOp, SendMessageError, WriteExt,
+    EncodeError, LdapConnection, ResponseProtocolOp, SendMessageError, WriteExt,
     attribute::AttributeValueAssertion,
+    control::Control,
     length::{LengthError, read_length},
     message::{ProtocolOp, ReadProtocolOpError, RequestProtocolOp},
     parse::{ParseLdap, ReadIntegerError},
@@ -19,12 +20,24 @@ impl LdapConnection {
         &mut self,
         entry: &str,
         value_assertion: AttributeValueAssertion<'_>,
+    ) -> Result<bool, CompareError> {
+        self.compare_with_controls(entry, value_assertion, &[]).await
+    }
+    /// Like [`LdapConnection::compare`], but attaches request controls, e.g. [`Control::dont_use_copy`].
+    pub async fn compare_with_controls(
+        &mut self,
+        entry: &str,
+        value_assertion: AttributeValueAssertion<'_>,
+        controls: &[Control],
     ) -> Result<bool, CompareError> {
         let response = self
-            .send_message(RequestProtocolOp::Compare {
-                entry,
-                value_assertion,
-            })
+            .send_message_with_controls(
+                RequestProtocolOp::Compare {
+                    entry,
+                    value_assertion,
+                },
+                controls,
+            )
             .await?
             .into_message();
         let ResponseProtocolOp::Compare { compare } =
@@ -39,14 +52,29 @@ impl LdapConnection {
 #[derive(Debug)]
 pub enum CompareError {
     Io(std::io::Error),
+    Encode(EncodeError),
     InvalidSchema,
     Disconnected,
-    ServerError { code: ResultCode, message: String },
+    ServerError {
+        code: ResultCode,
+        message: String,
+    },
+    /// The server doesn't support a critical control of the request
+    UnavailableCriticalExtension(String),
+}
+impl CompareError {
+    fn server_error(code: ResultCode, message: String) -> Self {
+        match code {
+            ResultCode::UnavailableCriticalExtension => Self::UnavailableCriticalExtension(message),
+            code => Self::ServerError { code, message },
+        }
+    }
 }
 impl From<SendMessageError> for CompareError {
     fn from(value: SendMessageError) -> Self {
         match value {
             SendMessageError::Io(error) => Self::Io(error),
+            SendMessageError::Encode(e) => Self::Encode(e),
             SendMessageError::ChannelClosed | SendMessageError::ReceiveMessage(_) => Self::Disconnected,
         }
     }
@@ -55,7 +83,11 @@ impl From<ReadProtocolOpError> for CompareError {
     fn from(value: ReadProtocolOpError) -> Self {
         match value {
             ReadProtocolOpError::Io(error) => Self::Io(error),
-            ReadProtocolOpError::ServerError { code, message } => Self::ServerError { code, message },
+            ReadProtocolOpError::ServerError { code, message } => Self::server_error(code, message),
+            ReadProtocolOpError::Referral(urls) => Self::ServerError {
+                code: ResultCode::Referral,
+                message: urls.join(" "),
+            },
             ReadProtocolOpError::InvalidSchema => Self::InvalidSchema,
         }
     }
@@ -67,17 +99,28 @@ impl From<std::io::Error> for CompareError {
 }
 impl std::error::Error for CompareError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        if let Self::Io(io) = self { Some(io) } else { None }
+        match self {
+            Self::Io(io) => Some(io),
+            Self::Encode(e) => Some(e),
+            _ => None,
+        }
     }
 }
 impl Display for CompareError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match self {
             Self::Io(error) => write!(f, "An IO error occured: {error}"),
+            Self::Encode(e) => write!(f, "Failed to encode request: {e}"),
             Self::InvalidSchema => write!(f, "Server returned an invalid message"),
             Self::Disconnected => write!(f, "Connection disconnected"),
             Self::ServerError { code, message } => {
-                write!(f, "Server returned an error. Code: {code:?} (\"{message}\")",)
+                write!(f, "Server returned an error. Code: {code} (\"{message}\")",)
+            }
+            Self::UnavailableCriticalExtension(message) => {
+                write!(
+                    f,
+                    "Server does not support a critical control of the request (\"{message}\")"
+                )
             }
         }
     }
@@ -102,7 +145,7 @@ pub(crate) fn read_response<R: Read>(mut r: R) -> Result<bool, ReadCompareError>
     let code = code
         .try_into()
         .ok()
-        .and_then(ResultCode::from_code)
+        .and_then(ResultCode::from_u16)
         .ok_or(ReadCompareError::InvalidSchema)?;
     match code {
         ResultCode::CompareTrue => return Ok(true),
@@ -156,7 +199,7 @@ impl From<ReadIntegerError> for ReadCompareError {
     fn from(value: ReadIntegerError) -> Self {
         match value {
             ReadIntegerError::Io(error) => Self::Io(error),
-            ReadIntegerError::Length(_) | ReadIntegerError::OutOfRange => Self::InvalidSchema,
+            ReadIntegerError::Length | ReadIntegerError::OutOfRange => Self::InvalidSchema,
         }
     }
 }
@@ -0,0 +1,355 @@
+use std::{
+    io::{Read, Write},
+    ops::BitOr,
+};
+
+use crate::{
+    EncodeError, EncodingRules, LdapConnection, WriteExt,
+    parse::ParseLdap,
+    read::ReadExt,
+    search::{
+        DerefPolicy, FailedToGetFromEntry, Filter, FromEntry, PageCookie, RawEntry, Scope, SearchOneError,
+    },
+    tag::{
+        OCTET_STRING, PrimitiveOrConstructed as PrimOrCons, TagClass, UNIVERSAL_BOOLEAN, UNIVERSAL_INTEGER,
+        UNIVERSAL_SEQUENCE,
+    },
+};
+
+/// OID of the matchedValues control (RFC 3876)
+pub const MATCHED_VALUES_OID: &str = "1.2.826.0.1.3344810.2.3";
+/// OID of the dontUseCopy control (RFC 6171)
+pub const DONT_USE_COPY_OID: &str = "1.3.6.1.1.22";
+/// OID of the simple paged results control (RFC 2696)
+pub const PAGED_RESULTS_OID: &str = "1.2.840.113556.1.4.319";
+/// OID of the Relax Rules control (draft-zeilenga-ldap-relax), still in OpenLDAP's experimental arc
+pub const RELAX_RULES_OID: &str = "1.3.6.1.4.1.4203.666.5.12";
+/// OID of the No-Op control (draft-zeilenga-ldap-noop), also in OpenLDAP's experimental arc
+pub const NO_OP_OID: &str = "1.3.6.1.4.1.4203.666.5.2";
+/// OID of Active Directory's `LDAP_SERVER_SD_FLAGS_OID` control
+pub const SD_FLAGS_OID: &str = "1.2.840.113556.1.4.801";
+
+const CONTROLS: u8 = TagClass::ContextSpecific.into_bits() | PrimOrCons::Constructed.into_bit();
+
+/// A request or response control, attached to an operation to extend its behavior
+#[derive(Clone, Debug)]
+pub struct Control {
+    pub oid: String,
+    /// If set, the server must reject the operation instead of ignoring a control it doesn't support
+    pub criticality: bool,
+    pub value: Option<Vec<u8>>,
+}
+impl Control {
+    pub fn new(oid: impl Into<String>, criticality: bool, value: Option<Vec<u8>>) -> Self {
+        Self {
+            oid: oid.into(),
+            criticality,
+            value,
+        }
+    }
+    /// The dontUseCopy control, which makes the server answer a search or compare from the original data,
+    /// not from a copy like a replica or cache.
+    ///
+    /// Servers that only hold a copy answer with a `Referral` or `UnwillingToPerform` result code instead.
+    pub fn dont_use_copy() -> Self {
+        Self::new(DONT_USE_COPY_OID, true, None)
+    }
+    /// The Relax Rules control, which lets a modification bypass some data and schema rules,
+    /// e.g. to set `NO-USER-MODIFICATION` attributes or change an entry's structural object class.
+    ///
+    /// This is OpenLDAP-specific: it's only an expired draft, and slapd additionally requires `manage` access.
+    /// Other servers reject the operation with `UnavailableCriticalExtension`.
+    pub fn relax_rules() -> Self {
+        Self::new(RELAX_RULES_OID, true, None)
+    }
+    /// The No-Op control, which makes the server check an update like a modify without applying it, for dry runs.
+    ///
+    /// An update that would have succeeded fails with [`ResultCode::NoOperation`](crate::ResultCode::NoOperation),
+    /// otherwise the server returns the error the update would have failed with.
+    /// This is experimental and OpenLDAP-specific (slapd needs the `noop` support compiled in),
+    /// other servers reject the operation with `UnavailableCriticalExtension`.
+    pub fn no_op() -> Self {
+        Self::new(NO_OP_OID, true, None)
+    }
+    /// The matchedValues control, which makes the server only return attribute values matching one of `filters`.
+    ///
+    /// Only simple filter items are allowed, `And`, `Or` and `Not` are rejected with [`EncodeError::InvalidFilter`].
+    /// Substring filters are not supported by lapdog's [`Filter`] yet.
+    pub fn matched_values(filters: &[Filter<'_>]) -> Result<Self, EncodeError> {
+        for filter in filters {
+            if matches!(filter, Filter::And(_) | Filter::Or(_) | Filter::Not(_)) {
+                return Err(EncodeError::InvalidFilter);
+            }
+            filter.validate()?;
+        }
+        let mut value = Vec::new();
+        value.write_sequence(UNIVERSAL_SEQUENCE, |seq| {
+            for filter in filters {
+                // DER is valid BER, so the value is fine regardless of the connection's encoding rules
+                filter.write_into(&mut *seq, EncodingRules::Der)?;
+            }
+            Ok(())
+        })?;
+        Ok(Self::new(MATCHED_VALUES_OID, true, Some(value)))
+    }
+    /// Active Directory's SD flags control, which selects the parts of `nTSecurityDescriptor` a search returns or a
+    /// modify replaces.
+    ///
+    /// Without it, Active Directory includes the SACL, which needs `SeSecurityPrivilege` ("Manage auditing and security
+    /// log", held by administrators). Accounts without it don't get the attribute at all, so to read ACLs request
+    /// `SdFlags::OWNER | SdFlags::GROUP | SdFlags::DACL` and only add [`SdFlags::SACL`] when bound with that privilege.
+    pub fn sd_flags(flags: SdFlags) -> Self {
+        let mut value = Vec::new();
+        value
+            .write_sequence(UNIVERSAL_SEQUENCE, |seq| {
+                seq.push(UNIVERSAL_INTEGER);
+                let mut int_b = Vec::new();
+                int_b.write_ber_integer_body(flags.0 as i32)?;
+                seq.write_ber_length(int_b.len())?;
+                seq.extend_from_slice(&int_b);
+                Ok(())
+            })
+            .expect("writing to a Vec can't fail");
+        Self::new(SD_FLAGS_OID, true, Some(value))
+    }
+    /// The simple paged results control, which makes the server return at most `page_size` entries per search.
+    ///
+    /// Pass the cookie of the previous page to get the next one, see [`SearchResults::next_page`](crate::search::SearchResults::next_page).
+    /// The control is critical, so servers that don't support paging fail the search instead of returning everything.
+    pub fn paged_results(page_size: u32, cookie: Option<&PageCookie>) -> Self {
+        let mut value = Vec::new();
+        let cookie = cookie.map(PageCookie::as_bytes).unwrap_or_default();
+        value
+            .write_sequence(UNIVERSAL_SEQUENCE, |seq| {
+                seq.push(UNIVERSAL_INTEGER);
+                let mut int_b = Vec::new();
+                int_b.write_ber_integer_body(i32::try_from(page_size).unwrap_or(i32::MAX))?;
+                seq.write_ber_length(int_b.len())?;
+                seq.extend_from_slice(&int_b);
+                seq.push(OCTET_STRING);
+                seq.write_ber_length(cookie.len())?;
+                seq.extend_from_slice(cookie);
+                Ok(())
+            })
+            .expect("writing to a Vec can't fail");
+        Self::new(PAGED_RESULTS_OID, true, Some(value))
+    }
+}
+
+/// The parts of a security descriptor for [`Control::sd_flags`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SdFlags(u32);
+impl SdFlags {
+    pub const OWNER: Self = Self(0x1);
+    pub const GROUP: Self = Self(0x2);
+    /// The discretionary ACL, i.e. who may access the object
+    pub const DACL: Self = Self(0x4);
+    /// The system ACL, i.e. which accesses are audited
+    pub const SACL: Self = Self(0x8);
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+impl BitOr for SdFlags {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl LdapConnection {
+    /// Reads the `supportedControl` of the root DSE and from then on refuses to send critical controls the server
+    /// doesn't list, failing the operation with [`EncodeError::UnsupportedControl`] instead of a round trip that
+    /// ends in `UnavailableCriticalExtension`.
+    ///
+    /// The list is kept until the next call or [`LdapConnection::clear_supported_controls`], so call this again after
+    /// a bind if the server only shows its controls to authenticated users. Non-critical controls are always sent.
+    pub async fn load_supported_controls(&mut self) -> Result<(), SearchOneError> {
+        let root_dse = self
+            .search_one::<SupportedControls>(
+                "",
+                Scope::Base,
+                DerefPolicy::Never,
+                Filter::Present("objectClass"),
+            )
+            .await?;
+        self.supported_controls = Some(root_dse.map(|s| s.0).unwrap_or_default());
+        Ok(())
+    }
+    /// The controls loaded by [`LdapConnection::load_supported_controls`], `None` if critical controls aren't checked
+    pub fn supported_controls(&self) -> Option<&[String]> {
+        self.supported_controls.as_deref()
+    }
+    /// Sends critical controls without checking them again
+    pub fn clear_supported_controls(&mut self) {
+        self.supported_controls = None;
+    }
+}
+
+struct SupportedControls(Vec<String>);
+impl FromEntry for SupportedControls {
+    fn from_entry(mut entry: RawEntry) -> Result<Self, FailedToGetFromEntry> {
+        entry
+            .take_attribute("supportedControl")
+            .unwrap_or_default()
+            .into_iter()
+            .map(|oid| {
+                String::from_utf8(oid)
+                    .map_err(|e| FailedToGetFromEntry::FailedToParseField("supportedControl", Box::new(e)))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+    fn attributes() -> Option<impl Iterator<Item = &'static str>> {
+        Some(["supportedControl"].into_iter())
+    }
+}
+
+/// Reads the `realSearchControlValue` of a paged results control, i.e. the size and cookie
+pub(crate) fn read_paged_results(mut value: &[u8]) -> Option<(i32, Vec<u8>)> {
+    if value.read_single_byte().ok()? != UNIVERSAL_SEQUENCE {
+        return None;
+    }
+    let len = value.read_ber_length().ok()?;
+    let (mut seq, _) = value.split_at_checked(len)?;
+    let (UNIVERSAL_INTEGER, size) = seq.read_as_tag_integer().ok()? else {
+        return None;
+    };
+    let cookie = read_octet_string(&mut seq)?;
+    Some((size, cookie))
+}
+
+/// Reads the optional `[0] Controls` following the protocol op of an LDAPMessage, `None` if they are malformed
+pub(crate) fn read_controls(mut bytes: &[u8]) -> Option<Vec<Control>> {
+    let mut controls = Vec::new();
+    if bytes.is_empty() {
+        return Some(controls);
+    }
+    if bytes.read_single_byte().ok()? != CONTROLS {
+        return None;
+    }
+    let len = bytes.read_ber_length().ok()?;
+    let (mut seq, _) = bytes.split_at_checked(len)?;
+    while !seq.is_empty() {
+        if seq.read_single_byte().ok()? != UNIVERSAL_SEQUENCE {
+            return None;
+        }
+        let len = seq.read_ber_length().ok()?;
+        let (mut control, rest) = seq.split_at_checked(len)?;
+        seq = rest;
+        let oid = String::from_utf8(read_octet_string(&mut control)?).ok()?;
+        let mut criticality = false;
+        if control.first() == Some(&UNIVERSAL_BOOLEAN) {
+            control = &control[1..];
+            let len = control.read_ber_length().ok()?;
+            let (boolean, rest) = control.split_at_checked(len)?;
+            criticality = boolean.iter().any(|b| *b != 0);
+            control = rest;
+        }
+        let value = if control.is_empty() {
+            None
+        } else {
+            Some(read_octet_string(&mut control)?)
+        };
+        controls.push(Control {
+            oid,
+            criticality,
+            value,
+        });
+    }
+    Some(controls)
+}
+
+fn read_octet_string(r: &mut &[u8]) -> Option<Vec<u8>> {
+    if r.read_single_byte().ok()? != OCTET_STRING {
+        return None;
+    }
+    let len = r.read_ber_length().ok()?;
+    let mut buf = vec![0; len];
+    r.read_exact(&mut buf).ok()?;
+    Some(buf)
+}
+
+/// Writes the optional `[0] Controls` of an LDAPMessage
+pub(crate) fn write_controls<W: Write>(mut w: W, controls: &[Control]) -> std::io::Result<()> {
+    if controls.is_empty() {
+        return Ok(());
+    }
+    w.write_sequence(CONTROLS, |seq| {
+        for control in controls {
+            seq.write_sequence(UNIVERSAL_SEQUENCE, |c| {
+                c.push(OCTET_STRING);
+                c.write_ber_length(control.oid.len())?;
+                c.extend_from_slice(control.oid.as_bytes());
+                // DEFAULT FALSE, so it's only written when set
+                if control.criticality {
+                    c.extend_from_slice(&[UNIVERSAL_BOOLEAN, 0x01, 0xff]);
+                }
+                if let Some(value) = &control.value {
+                    c.push(OCTET_STRING);
+                    c.write_ber_length(value.len())?;
+                    c.extend_from_slice(value);
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Control, PAGED_RESULTS_OID, SdFlags, read_controls, read_paged_results, write_controls};
+    use crate::{
+        EncodeError,
+        search::{Filter, PageCookie},
+    };
+
+    #[test]
+    fn encode_controls() {
+        let mut out = Vec::new();
+        write_controls(&mut out, &[Control::new("1.2", true, Some(vec![0x05]))]).unwrap();
+        assert_eq!(
+            out,
+            [
+                0xa0, 0x0d, 0x30, 0x0b, 0x04, 0x03, b'1', b'.', b'2', 0x01, 0x01, 0xff, 0x04, 0x01, 0x05
+            ]
+        );
+    }
+
+    #[test]
+    fn matched_values_rejects_complex_filters() {
+        let filter = Filter::and([Filter::equal("member", b"cn=bob")]);
+        assert!(matches!(
+            Control::matched_values(&[filter]),
+            Err(EncodeError::InvalidFilter)
+        ));
+        assert!(Control::matched_values(&[Filter::equal("member", b"cn=bob")]).is_ok());
+    }
+
+    #[test]
+    fn paged_results_roundtrip() {
+        let cookie = PageCookie::from_bytes(b"next".to_vec());
+        let mut out = Vec::new();
+        write_controls(&mut out, &[Control::paged_results(500, Some(&cookie))]).unwrap();
+        let controls = read_controls(&out).unwrap();
+        assert_eq!(controls.len(), 1);
+        assert_eq!(controls[0].oid, PAGED_RESULTS_OID);
+        assert!(controls[0].criticality);
+        let (size, bytes) = read_paged_results(controls[0].value.as_deref().unwrap()).unwrap();
+        assert_eq!(size, 500);
+        assert_eq!(bytes, b"next");
+    }
+
+    #[test]
+    fn sd_flags() {
+        let control = Control::sd_flags(SdFlags::OWNER | SdFlags::GROUP | SdFlags::DACL);
+        assert_eq!(control.value.unwrap(), [0x30, 0x03, 0x02, 0x01, 0x07]);
+        assert!((SdFlags::DACL | SdFlags::SACL).contains(SdFlags::SACL));
+        assert!(!SdFlags::DACL.contains(SdFlags::SACL));
+    }
+}
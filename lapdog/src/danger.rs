@@ -0,0 +1,83 @@
+//! TLS setups that skip certificate verification, only available with the `dangerous-insecure-tls` feature
+
+#[cfg(feature = "rustls")]
+use std::sync::Arc;
+
+use crate::StreamConfig;
+
+impl StreamConfig {
+    /// **Insecure**: rustls config that accepts any server certificate, e.g. self-signed certificates in a test environment.
+    ///
+    /// Anyone between client and server can read and change all traffic, including bind passwords.
+    /// Handshake signatures are still checked using `provider`.
+    #[cfg(feature = "rustls")]
+    pub fn unsafe_rustls_no_verification(
+        provider: Arc<rustls::crypto::CryptoProvider>,
+        domain: impl Into<String>,
+    ) -> Result<Self, rustls::Error> {
+        let config = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+            .with_no_client_auth();
+        Ok(Self::Rustls {
+            config: Arc::new(config),
+            domain: domain.into(),
+        })
+    }
+    /// **Insecure**: native-tls config that accepts any server certificate and hostname, e.g. self-signed certificates
+    /// in a test environment.
+    ///
+    /// Anyone between client and server can read and change all traffic, including bind passwords.
+    #[cfg(feature = "native-tls")]
+    pub fn unsafe_native_tls_no_verification(domain: impl Into<String>) -> Result<Self, native_tls::Error> {
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .build()?;
+        Ok(Self::NativeTls {
+            connector,
+            domain: domain.into(),
+        })
+    }
+}
+
+#[cfg(feature = "rustls")]
+#[derive(Debug)]
+struct NoVerification(Arc<rustls::crypto::CryptoProvider>);
+
+#[cfg(feature = "rustls")]
+impl rustls::client::danger::ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::pki_types::CertificateDer<'_>,
+        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
@@ -0,0 +1,167 @@
+//! Finding domain controllers through DNS SRV records, only available with the `dns-srv` feature
+
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    hash::{BuildHasher, Hasher, RandomState},
+};
+
+use hickory_resolver::{ResolveError, TokioResolver};
+
+use crate::{ConnectError, LdapConnection, StreamConfig};
+
+/// Looks up the LDAP servers of an Active Directory domain from `_ldap._tcp.dc._msdcs.<domain>`.
+///
+/// Returns host names and ports, ordered by priority. Servers of the same priority are shuffled by their weights as
+/// described in RFC 2782, so that clients spread over them as the domain intends.
+pub async fn discover_servers(domain: &str) -> Result<Vec<(String, u16)>, DiscoverError> {
+    let resolver = TokioResolver::builder_tokio()
+        .map_err(DiscoverError::Resolve)?
+        .build();
+    let lookup = resolver
+        .srv_lookup(format!("_ldap._tcp.dc._msdcs.{domain}."))
+        .await
+        .map_err(DiscoverError::Resolve)?;
+    let records = lookup
+        .iter()
+        .map(|srv| {
+            let target = srv.target().to_utf8();
+            let server = (target.trim_end_matches('.').to_string(), srv.port());
+            (srv.priority(), srv.weight(), server)
+        })
+        .collect();
+    let servers = order_by_weight(records, random_up_to);
+    if servers.is_empty() {
+        return Err(DiscoverError::NoServers);
+    }
+    Ok(servers)
+}
+
+/// Orders `(priority, weight, server)` records by priority, and within a priority by repeatedly picking one of the
+/// remaining records with a chance proportional to its weight. `random(total)` returns a number in `0..=total`.
+fn order_by_weight<T>(mut records: Vec<(u16, u16, T)>, mut random: impl FnMut(u32) -> u32) -> Vec<T> {
+    // zero weights first, so they only have a chance to be picked if they are the only ones left
+    records.sort_by_key(|(priority, weight, _)| (*priority, *weight != 0));
+    let mut ordered = Vec::with_capacity(records.len());
+    let mut records = records.into_iter().peekable();
+    while let Some((priority, ..)) = records.peek() {
+        let priority = *priority;
+        let mut group: Vec<_> = std::iter::from_fn(|| records.next_if(|(p, ..)| *p == priority)).collect();
+        while !group.is_empty() {
+            let total: u32 = group.iter().map(|(_, weight, _)| u32::from(*weight)).sum();
+            let pick = random(total);
+            let mut running = 0;
+            let index = group
+                .iter()
+                .position(|(_, weight, _)| {
+                    running += u32::from(*weight);
+                    running >= pick
+                })
+                .unwrap_or(group.len() - 1);
+            ordered.push(group.remove(index).2);
+        }
+    }
+    ordered
+}
+
+/// A random number in `0..=max`, from the randomly seeded hasher of std since this is no cryptographic use
+fn random_up_to(max: u32) -> u32 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(max);
+    (hasher.finish() % (u64::from(max) + 1)) as u32
+}
+
+impl LdapConnection {
+    /// Connects to the first reachable server of an Active Directory domain, see [`discover_servers`].
+    ///
+    /// The servers' ports are used as they are, so `config` should be a plain or StartTLS config. For TLS, the
+    /// `domain` of `config` is verified for every server, so the certificates need to contain it, e.g. the domain
+    /// name itself, which AD domain controller certificates usually do.
+    pub async fn connect_domain(domain: &str, config: &StreamConfig) -> Result<Self, ConnectDomainError> {
+        let mut last_error = None;
+        for server in discover_servers(domain)
+            .await
+            .map_err(ConnectDomainError::Discover)?
+        {
+            match Self::new(server, config).await {
+                Ok(connection) => return Ok(connection),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        // discover_servers never returns an empty list
+        Err(ConnectDomainError::Connect(
+            last_error.expect("at least one server was tried"),
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub enum DiscoverError {
+    Resolve(ResolveError),
+    /// The domain has no SRV records for LDAP
+    NoServers,
+}
+impl std::error::Error for DiscoverError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Resolve(e) => Some(e),
+            Self::NoServers => None,
+        }
+    }
+}
+impl Display for DiscoverError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Resolve(e) => write!(f, "Failed to look up SRV records: {e}"),
+            Self::NoServers => write!(f, "No LDAP servers found"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConnectDomainError {
+    Discover(DiscoverError),
+    /// None of the servers could be reached, this is the error of the last one
+    Connect(ConnectError),
+}
+impl std::error::Error for ConnectDomainError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Discover(e) => Some(e),
+            Self::Connect(e) => Some(e),
+        }
+    }
+}
+impl Display for ConnectDomainError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Discover(e) => e.fmt(f),
+            Self::Connect(e) => write!(f, "No server of the domain was reachable: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::order_by_weight;
+
+    #[test]
+    fn weighted_within_priority() {
+        let records = vec![(10, 0, "backup"), (0, 0, "zero"), (0, 30, "a"), (0, 10, "b")];
+        // always the lowest number: the first record of the running sum, zero weights first
+        assert_eq!(
+            order_by_weight(records.clone(), |_| 0),
+            ["zero", "a", "b", "backup"]
+        );
+        // always the highest number: the last record
+        assert_eq!(
+            order_by_weight(records.clone(), |total| total),
+            ["b", "a", "zero", "backup"]
+        );
+        // past the 30 of "a"
+        let mut picks = [31, 0, 0].into_iter();
+        assert_eq!(
+            order_by_weight(records, |_| picks.next().unwrap_or(0)),
+            ["b", "zero", "a", "backup"]
+        );
+    }
+}
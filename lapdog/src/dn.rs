@@ -0,0 +1,302 @@
+//! Helpers for working with distinguished names (RFC 4514)
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult, Write},
+    str::FromStr,
+};
+
+/// Compares two distinguished names, e.g. `CN=Bob,DC=x` and `cn=bob, dc=x`.
+///
+/// Attribute types are compared case-insensitively, whitespace around RDN components is ignored and
+/// escapes are resolved before comparing. The order of the parts of a multi-valued RDN doesn't matter.
+///
+/// This is a best-effort syntactic comparison: values are always compared case-insensitively,
+/// even though the server's matching rule for an attribute might be case-sensitive.
+/// DNs that fail to parse are only equal if they're byte-for-byte identical.
+pub fn equal(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+    match (a.parse::<Dn>(), b.parse::<Dn>()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// A parsed distinguished name, e.g. from a `member` or `memberOf` value.
+///
+/// Comparison follows the same rules as [`equal`].
+///
+/// ```
+/// # use lapdog::dn::Dn;
+/// let dn: Dn = r"CN=Doe\, John,OU=Users,DC=example,DC=com".parse().unwrap();
+/// assert_eq!(dn.value_of("cn"), Some("Doe, John"));
+/// assert!(dn.is_descendant_of(&"dc=example,dc=com".parse().unwrap()));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Dn(Vec<Rdn>);
+impl Dn {
+    /// RDNs from the entry itself up to the root
+    pub fn rdns(&self) -> &[Rdn] {
+        &self.0
+    }
+    /// Value of `attribute_type` in the first RDN, e.g. the `CN` of a group
+    pub fn value_of(&self, attribute_type: &str) -> Option<&str> {
+        self.0.first()?.value_of(attribute_type)
+    }
+    /// The DN without its first RDN, `None` for the root DSE
+    pub fn parent(&self) -> Option<Dn> {
+        self.0.split_first().map(|(_, rest)| Self(rest.to_vec()))
+    }
+    /// Whether this DN is strictly below `ancestor` in the tree
+    pub fn is_descendant_of(&self, ancestor: &Dn) -> bool {
+        self.0.len() > ancestor.0.len()
+            && self.0[self.0.len() - ancestor.0.len()..]
+                .iter()
+                .zip(&ancestor.0)
+                .all(|(a, b)| a == b)
+    }
+    /// The entries between `matched_dn` and this DN, including this one, from the top down.
+    ///
+    /// When an operation on this DN fails with `NoSuchObject`, the server returns the deepest existing ancestor as
+    /// matched DN, e.g. in [`SearchResult::Done`](crate::search::SearchResult::Done), so these are the entries
+    /// to create first. An empty matched DN means no part of the DN exists.
+    /// `None` if `matched_dn` isn't this DN or one of its ancestors.
+    ///
+    /// ```
+    /// # use lapdog::dn::Dn;
+    /// let base: Dn = "ou=Tokyo,ou=Sites,dc=example,dc=com".parse().unwrap();
+    /// let matched: Dn = "dc=example,dc=com".parse().unwrap();
+    /// let missing = base.missing_entries(&matched).unwrap();
+    /// assert_eq!(missing[0].to_string(), "ou=Sites,dc=example,dc=com");
+    /// assert_eq!(missing[1], base);
+    /// ```
+    pub fn missing_entries(&self, matched_dn: &Dn) -> Option<Vec<Dn>> {
+        if self != matched_dn && !self.is_descendant_of(matched_dn) {
+            return None;
+        }
+        let missing = self.0.len() - matched_dn.0.len();
+        Some((0..missing).rev().map(|i| Self(self.0[i..].to_vec())).collect())
+    }
+}
+impl PartialEq for Dn {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+impl Eq for Dn {}
+impl FromStr for Dn {
+    type Err = InvalidDn;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s).map(Self).ok_or(InvalidDn)
+    }
+}
+impl Display for Dn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        for (i, rdn) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_char(',')?;
+            }
+            write!(f, "{rdn}")?;
+        }
+        Ok(())
+    }
+}
+impl crate::modify::ToOctetString for Dn {
+    fn to_octet_string(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+#[cfg(feature = "from_octets")]
+impl crate::search::FromOctetString for Dn {
+    type Err = InvalidDn;
+    fn from_octet_string(bytes: &[u8]) -> Result<Self, Self::Err> {
+        std::str::from_utf8(bytes).map_err(|_| InvalidDn)?.parse()
+    }
+}
+
+/// One component of a [`Dn`], usually a single `type=value`, or several joined by `+`
+#[derive(Clone, Debug)]
+pub struct Rdn(Vec<(String, String)>);
+impl Rdn {
+    /// Attribute types and unescaped values
+    pub fn components(&self) -> &[(String, String)] {
+        &self.0
+    }
+    pub fn value_of(&self, attribute_type: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(t, _)| t.eq_ignore_ascii_case(attribute_type))
+            .map(|(_, v)| v.as_str())
+    }
+    fn normalized(&self) -> Vec<(String, String)> {
+        let mut components: Vec<_> = self
+            .0
+            .iter()
+            .map(|(t, v)| {
+                // like caseIgnoreMatch, inner runs of whitespace collapse to one space
+                let v = v.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+                (t.to_ascii_lowercase(), v)
+            })
+            .collect();
+        components.sort();
+        components
+    }
+}
+impl PartialEq for Rdn {
+    fn eq(&self, other: &Self) -> bool {
+        self.normalized() == other.normalized()
+    }
+}
+impl Eq for Rdn {}
+impl Display for Rdn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        for (i, (attr_type, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_char('+')?;
+            }
+            write!(f, "{attr_type}=")?;
+            let last = value.chars().count().saturating_sub(1);
+            for (j, c) in value.chars().enumerate() {
+                let escape = matches!(c, '"' | '+' | ',' | ';' | '<' | '>' | '\\')
+                    || (j == 0 && matches!(c, ' ' | '#'))
+                    || (j == last && c == ' ');
+                if escape {
+                    f.write_char('\\')?;
+                }
+                if c == '\0' {
+                    f.write_str("\\00")?;
+                } else {
+                    f.write_char(c)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidDn;
+impl Error for InvalidDn {}
+impl Display for InvalidDn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "invalid distinguished name")
+    }
+}
+
+fn parse(dn: &str) -> Option<Vec<Rdn>> {
+    let mut rdns = Vec::new();
+    if dn.trim().is_empty() {
+        return Some(rdns);
+    }
+    let mut rdn = Vec::new();
+    let mut bytes = dn.bytes();
+    loop {
+        let mut attr_type = Vec::new();
+        let mut has_value = false;
+        for b in bytes.by_ref() {
+            match b {
+                b'=' => {
+                    has_value = true;
+                    break;
+                }
+                // a separator before the type's `=` leaves an RDN empty, e.g. `cn=a,,dc=x`
+                b',' | b';' | b'+' => return None,
+                b => attr_type.push(b),
+            }
+        }
+        let attr_type = String::from_utf8(attr_type).ok()?.trim().to_string();
+        if attr_type.is_empty() || !has_value {
+            return None;
+        }
+
+        let mut value = Vec::new();
+        let mut terminator = None;
+        while let Some(b) = bytes.next() {
+            match b {
+                b',' | b';' | b'+' => {
+                    terminator = Some(b);
+                    break;
+                }
+                b'\\' => {
+                    let first = bytes.next()?;
+                    let escaped = match (hex_value(first), bytes.clone().next().and_then(hex_value)) {
+                        (Some(high), Some(low)) => {
+                            bytes.next();
+                            high << 4 | low
+                        }
+                        _ => first,
+                    };
+                    value.push(escaped);
+                }
+                b => value.push(b),
+            }
+        }
+        // surrounding whitespace is insignificant
+        let value = String::from_utf8(value).ok()?.trim().to_string();
+        rdn.push((attr_type, value));
+
+        match terminator {
+            Some(b'+') => {}
+            Some(_) => rdns.push(Rdn(std::mem::take(&mut rdn))),
+            None => {
+                rdns.push(Rdn(rdn));
+                return Some(rdns);
+            }
+        }
+    }
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Dn, equal};
+
+    #[test]
+    fn case_and_whitespace() {
+        assert!(equal("CN=Bob,DC=x", "cn=bob, dc=x"));
+        assert!(equal("cn = Bob ,dc=x", "CN=bob,DC=X"));
+        assert!(!equal("cn=Bob,dc=x", "cn=Bobby,dc=x"));
+        assert!(!equal("cn=Bob,dc=x", "cn=Bob,dc=x,dc=y"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(equal(r"cn=Doe\, John,dc=x", r"cn=doe\2C john,dc=x"));
+        assert!(!equal(r"cn=Doe\, John,dc=x", "cn=Doe,cn=John,dc=x"));
+    }
+
+    #[test]
+    fn multi_valued_rdn() {
+        assert!(equal("cn=Bob+uid=bob,dc=x", "UID=bob + CN=Bob,dc=x"));
+    }
+
+    #[test]
+    fn navigate_dn() {
+        let dn: Dn = r"CN=Doe\2C John,OU=Users,DC=x".parse().unwrap();
+        assert_eq!(dn.value_of("cn"), Some("Doe, John"));
+        assert_eq!(dn.to_string(), r"CN=Doe\, John,OU=Users,DC=x");
+        let parent = dn.parent().unwrap();
+        assert_eq!(parent, "ou=users,dc=x".parse().unwrap());
+        assert!(dn.is_descendant_of(&parent));
+        assert!(!parent.is_descendant_of(&dn));
+
+        assert_eq!(dn.missing_entries(&dn), Some(vec![]));
+        assert_eq!(dn.missing_entries(&Dn::default()).unwrap().len(), 3);
+        assert_eq!(parent.missing_entries(&dn), None);
+        assert!("".parse::<Dn>().unwrap().parent().is_none());
+        assert!("cn".parse::<Dn>().is_err());
+    }
+
+    #[test]
+    fn empty_rdn() {
+        for dn in ["cn=a,,dc=x", ",cn=a", "cn=a+,dc=x", "cn=a;;dc=x", "cn=a,"] {
+            assert!(dn.parse::<Dn>().is_err(), "{dn}");
+        }
+        assert!(r"cn=a\,,dc=x".parse::<Dn>().is_ok());
+    }
+}
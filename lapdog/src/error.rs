@@ -0,0 +1,106 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+use crate::{
+    ConnectError, EncodeError, RawRequestError,
+    bind::{BindAndWhoAmIError, ExternalBindError, SimpleBindError},
+    compare::CompareError,
+    extended::{CancelError, ExtendedOpError},
+    modify::ModifyError,
+    schema::ReadSchemaError,
+    search::{
+        AbandonError, BeginSearchError, ChaseError, CollectError, CountError, FailedToGetFromEntry,
+        RangeRetrievalError, ReadEntryError, SearchOneError, SearchResultError, ValidateFilterError,
+    },
+};
+
+macro_rules! lapdog_error {
+    ($($(#[$attr:meta])* $variant:ident($ty:ty),)*) => {
+        /// Any of the errors of lapdog's operations, so `?` works in functions doing several of them.
+        ///
+        /// This is transparent: it displays and reports the source of the wrapped error.
+        /// Match on the variants to get to the operation's own error type.
+        #[derive(Debug)]
+        #[non_exhaustive]
+        pub enum LapdogError {
+            $($(#[$attr])* $variant($ty),)*
+        }
+        $(
+            $(#[$attr])*
+            impl From<$ty> for LapdogError {
+                fn from(value: $ty) -> Self {
+                    Self::$variant(value)
+                }
+            }
+        )*
+        impl Error for LapdogError {
+            fn source(&self) -> Option<&(dyn Error + 'static)> {
+                match self {
+                    $($(#[$attr])* Self::$variant(e) => e.source(),)*
+                }
+            }
+        }
+        impl Display for LapdogError {
+            fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+                match self {
+                    $($(#[$attr])* Self::$variant(e) => e.fmt(f),)*
+                }
+            }
+        }
+    };
+}
+
+lapdog_error! {
+    Connect(ConnectError),
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    StartTls(crate::StartTlsError),
+    SimpleBind(SimpleBindError),
+    ExternalBind(ExternalBindError),
+    BindAndWhoAmI(BindAndWhoAmIError),
+    #[cfg(feature = "kerberos")]
+    KerberosBind(crate::bind::kerberos::BindError),
+    BeginSearch(BeginSearchError),
+    SearchResult(SearchResultError),
+    SearchOne(SearchOneError),
+    ReadEntry(ReadEntryError),
+    Collect(CollectError),
+    Chase(ChaseError),
+    Count(CountError),
+    ValidateFilter(ValidateFilterError),
+    Abandon(AbandonError),
+    RangeRetrieval(RangeRetrievalError),
+    InvalidEntry(FailedToGetFromEntry),
+    Compare(CompareError),
+    Modify(ModifyError),
+    ExtendedOp(ExtendedOpError),
+    Cancel(CancelError),
+    RawRequest(RawRequestError),
+    ReadSchema(ReadSchemaError),
+    Encode(EncodeError),
+    #[cfg(feature = "dns-srv")]
+    Discover(crate::discovery::DiscoverError),
+    #[cfg(feature = "dns-srv")]
+    ConnectDomain(crate::discovery::ConnectDomainError),
+}
+
+#[cfg(test)]
+mod test {
+    use super::LapdogError;
+    use crate::{EncodeError, modify::ModifyError};
+
+    #[test]
+    fn question_mark_converts() {
+        fn several_operations(fail_modify: bool) -> Result<(), LapdogError> {
+            if fail_modify {
+                Err(ModifyError::Disconnected)?;
+            }
+            Err(EncodeError::InvalidFilter)?
+        }
+        let err = several_operations(true).unwrap_err();
+        assert!(matches!(err, LapdogError::Modify(ModifyError::Disconnected)));
+        assert_eq!(err.to_string(), ModifyError::Disconnected.to_string());
+        assert!(matches!(several_operations(false), Err(LapdogError::Encode(_))));
+    }
+}
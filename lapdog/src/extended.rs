@@ -0,0 +1,387 @@
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::Read,
+    num::NonZero,
+};
+
+use crate::{
+    EncodeError, LdapConnection, ResponseProtocolOp, SendMessageError, WriteExt,
+    length::{LengthError, read_length},
+    message::{ProtocolOp, ReadProtocolOpError, RequestProtocolOp},
+    parse::{ParseLdap, ReadIntegerError},
+    read::ReadExt,
+    result::ResultCode,
+    tag::{
+        OCTET_STRING, PrimitiveOrConstructed as PrimOrCons, TagClass, UNIVERSAL_ENUMERATED,
+        UNIVERSAL_INTEGER, UNIVERSAL_SEQUENCE,
+    },
+};
+
+/// OID of the "Who am I?" extended operation (RFC 4532)
+pub const WHO_AM_I_OID: &str = "1.3.6.1.4.1.4203.1.11.3";
+/// OID of the StartTLS extended operation (RFC 4511)
+pub const START_TLS_OID: &str = "1.3.6.1.4.1.1466.20037";
+/// OID of the Cancel extended operation (RFC 3909)
+pub const CANCEL_OID: &str = "1.3.6.1.1.8";
+/// OID of the Password Modify extended operation (RFC 3062)
+pub const PASSWORD_MODIFY_OID: &str = "1.3.6.1.4.1.4203.1.11.1";
+
+const REQUEST_NAME: u8 = TagClass::ContextSpecific.into_bits() | PrimOrCons::Primitive.into_bit();
+const REQUEST_VALUE: u8 = TagClass::ContextSpecific.into_bits() | PrimOrCons::Primitive.into_bit() | 0x1;
+const REFERRAL: u8 = TagClass::ContextSpecific.into_bits() | PrimOrCons::Constructed.into_bit() | 0x3;
+const RESPONSE_NAME: u8 = TagClass::ContextSpecific.into_bits() | PrimOrCons::Primitive.into_bit() | 0xa;
+const RESPONSE_VALUE: u8 = TagClass::ContextSpecific.into_bits() | PrimOrCons::Primitive.into_bit() | 0xb;
+
+impl LdapConnection {
+    /// Sends an arbitrary extended request and waits for the server's response.
+    ///
+    /// This is the escape hatch for extended operations lapdog doesn't model itself.
+    /// Non-success result codes are returned as [`ExtendedOpError::ServerError`].
+    pub async fn extended_operation(
+        &mut self,
+        request_name: &str,
+        request_value: Option<&[u8]>,
+    ) -> Result<ExtendedResponse, ExtendedOpError> {
+        let response = self
+            .send_message(RequestProtocolOp::Extended {
+                name: request_name,
+                value: request_value,
+            })
+            .await?
+            .into_message();
+        let ResponseProtocolOp::Extended(response) = ResponseProtocolOp::read_from(&mut response.as_slice())?
+        else {
+            return Err(ExtendedOpError::InvalidSchema);
+        };
+        Ok(response)
+    }
+    /// Asks the server which authorization identity the connection is bound as.
+    ///
+    /// Returns `None` for an anonymous connection.
+    pub async fn who_am_i(&mut self) -> Result<Option<String>, ExtendedOpError> {
+        let response = self.extended_operation(WHO_AM_I_OID, None).await?;
+        match response.value {
+            None => Ok(None),
+            Some(v) if v.is_empty() => Ok(None),
+            Some(v) => String::from_utf8(v)
+                .map(Some)
+                .map_err(|_| ExtendedOpError::InvalidSchema),
+        }
+    }
+    /// Changes a password with the Password Modify operation (RFC 3062).
+    ///
+    /// `user` is the DN or authorization identity whose password changes, the bound identity if `None`. Servers
+    /// usually require `old_password` when users change their own password. Without `new_password`, the server
+    /// generates one and returns it. The passwords travel in the clear unless the connection is encrypted.
+    pub async fn password_modify(
+        &mut self,
+        user: Option<&str>,
+        old_password: Option<&[u8]>,
+        new_password: Option<&[u8]>,
+    ) -> Result<Option<Vec<u8>>, ExtendedOpError> {
+        let value = write_password_modify(user, old_password, new_password);
+        let response = self.extended_operation(PASSWORD_MODIFY_OID, Some(&value)).await?;
+        match response.value {
+            None => Ok(None),
+            Some(value) => read_generated_password(&value).ok_or(ExtendedOpError::InvalidSchema),
+        }
+    }
+    /// Cancels an outstanding operation, e.g. a search via [`SearchResults::message_id`](crate::search::SearchResults::message_id).
+    ///
+    /// Unlike abandon, the server confirms whether the operation was actually canceled.
+    /// The canceled operation itself completes with [`ResultCode::Canceled`].
+    pub async fn cancel(&mut self, message_id: NonZero<i32>) -> Result<(), CancelError> {
+        let mut value = Vec::new();
+        value
+            .write_sequence(UNIVERSAL_SEQUENCE, |seq| {
+                seq.push(UNIVERSAL_INTEGER);
+                let mut int_bytes = Vec::new();
+                int_bytes.write_ber_integer_body(message_id.get())?;
+                seq.write_ber_length(int_bytes.len())?;
+                seq.extend(int_bytes);
+                Ok(())
+            })
+            .expect("infallible");
+        match self.extended_operation(CANCEL_OID, Some(&value)).await {
+            Ok(_) => Ok(()),
+            Err(ExtendedOpError::ServerError { code, message }) => Err(match code {
+                ResultCode::NoSuchOperation => CancelError::NoSuchOperation,
+                ResultCode::TooLate => CancelError::TooLate,
+                ResultCode::CannotCancel => CancelError::CannotCancel,
+                code => CancelError::Extended(ExtendedOpError::ServerError { code, message }),
+            }),
+            Err(e) => Err(CancelError::Extended(e)),
+        }
+    }
+}
+
+/// Response to an extended request
+#[derive(Clone, Debug)]
+pub struct ExtendedResponse {
+    pub name: Option<String>,
+    pub value: Option<Vec<u8>>,
+    pub diagnostics_message: String,
+}
+
+#[derive(Debug)]
+pub enum ExtendedOpError {
+    Io(std::io::Error),
+    Encode(EncodeError),
+    InvalidSchema,
+    Disconnected,
+    ServerError { code: ResultCode, message: String },
+}
+impl From<SendMessageError> for ExtendedOpError {
+    fn from(value: SendMessageError) -> Self {
+        match value {
+            SendMessageError::Io(error) => Self::Io(error),
+            SendMessageError::Encode(e) => Self::Encode(e),
+            SendMessageError::ChannelClosed | SendMessageError::ReceiveMessage(_) => Self::Disconnected,
+        }
+    }
+}
+impl From<ReadProtocolOpError> for ExtendedOpError {
+    fn from(value: ReadProtocolOpError) -> Self {
+        match value {
+            ReadProtocolOpError::Io(error) => Self::Io(error),
+            ReadProtocolOpError::ServerError { code, message } => Self::ServerError { code, message },
+            ReadProtocolOpError::Referral(urls) => Self::ServerError {
+                code: ResultCode::Referral,
+                message: urls.join(" "),
+            },
+            ReadProtocolOpError::InvalidSchema => Self::InvalidSchema,
+        }
+    }
+}
+impl std::error::Error for ExtendedOpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(io) => Some(io),
+            Self::Encode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl Display for ExtendedOpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Io(error) => write!(f, "An IO error occured: {error}"),
+            Self::Encode(e) => write!(f, "Failed to encode request: {e}"),
+            Self::InvalidSchema => write!(f, "Server returned an invalid message"),
+            Self::Disconnected => write!(f, "Connection disconnected"),
+            Self::ServerError { code, message } => {
+                write!(f, "Server returned an error. Code: {code} (\"{message}\")",)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CancelError {
+    /// The server doesn't know the operation, possibly because it has already finished
+    NoSuchOperation,
+    /// The operation has progressed too far to be canceled
+    TooLate,
+    /// The operation can't be canceled, e.g. a bind or another cancel
+    CannotCancel,
+    Extended(ExtendedOpError),
+}
+impl std::error::Error for CancelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        if let Self::Extended(e) = self {
+            Some(e)
+        } else {
+            None
+        }
+    }
+}
+impl Display for CancelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::NoSuchOperation => write!(f, "Server doesn't know the operation to cancel"),
+            Self::TooLate => write!(f, "Too late to cancel the operation"),
+            Self::CannotCancel => write!(f, "Operation cannot be canceled"),
+            Self::Extended(e) => write!(f, "Failed to cancel: {e}"),
+        }
+    }
+}
+
+pub(crate) fn write_extended(name: &str, value: Option<&[u8]>) -> Vec<u8> {
+    let mut msg_sequence = Vec::new();
+    msg_sequence.push(REQUEST_NAME);
+    msg_sequence.write_ber_length(name.len()).expect("infallible");
+    msg_sequence.extend_from_slice(name.as_bytes());
+    if let Some(value) = value {
+        msg_sequence.push(REQUEST_VALUE);
+        msg_sequence.write_ber_length(value.len()).expect("infallible");
+        msg_sequence.extend_from_slice(value);
+    }
+    msg_sequence
+}
+
+/// Writes the `PasswdModifyRequestValue` of a Password Modify request
+fn write_password_modify(
+    user: Option<&str>,
+    old_password: Option<&[u8]>,
+    new_password: Option<&[u8]>,
+) -> Vec<u8> {
+    let fields = [user.map(str::as_bytes), old_password, new_password];
+    let mut value = Vec::new();
+    value
+        .write_sequence(UNIVERSAL_SEQUENCE, |seq| {
+            for (tag, field) in (0..).zip(fields) {
+                let Some(field) = field else {
+                    continue;
+                };
+                seq.push(TagClass::ContextSpecific.into_bits() | PrimOrCons::Primitive.into_bit() | tag);
+                seq.write_ber_length(field.len())?;
+                seq.extend_from_slice(field);
+            }
+            Ok(())
+        })
+        .expect("infallible");
+    value
+}
+
+/// Reads the `genPasswd` of a `PasswdModifyResponseValue`, `None` if the value is malformed
+fn read_generated_password(mut value: &[u8]) -> Option<Option<Vec<u8>>> {
+    const GENERATED_PASSWORD: u8 = TagClass::ContextSpecific.into_bits() | PrimOrCons::Primitive.into_bit();
+    if value.read_single_byte().ok()? != UNIVERSAL_SEQUENCE {
+        return None;
+    }
+    let len = read_length(&mut value).ok()?;
+    if value.len() != len {
+        return None;
+    }
+    if value.is_empty() {
+        return Some(None);
+    }
+    if value.read_single_byte().ok()? != GENERATED_PASSWORD {
+        return None;
+    }
+    let len = read_length(&mut value).ok()?;
+    (value.len() == len).then(|| Some(value.to_vec()))
+}
+
+pub(crate) fn read_response<R: Read>(mut r: R) -> Result<ExtendedResponse, ReadExtendedError> {
+    let (tag, code) = r.read_as_tag_integer()?;
+    if tag != UNIVERSAL_ENUMERATED {
+        return Err(ReadExtendedError::InvalidSchema);
+    }
+
+    // LdapResult code
+    let code = code
+        .try_into()
+        .ok()
+        .and_then(ResultCode::from_u16)
+        .ok_or(ReadExtendedError::InvalidSchema)?;
+    let matched_dn_tag = r.read_single_byte()?;
+    if matched_dn_tag != OCTET_STRING {
+        return Err(ReadExtendedError::InvalidSchema);
+    }
+    let matched_dn_len = read_length(&mut r)?;
+    let mut matched_dn = vec![0; matched_dn_len];
+    r.read_exact(&mut matched_dn)?;
+    let Ok(_) = String::from_utf8(matched_dn) else {
+        return Err(ReadExtendedError::InvalidSchema);
+    };
+
+    let diagnostics_tag = r.read_single_byte()?;
+    if diagnostics_tag != OCTET_STRING {
+        return Err(ReadExtendedError::InvalidSchema);
+    }
+    let diagnostics_len = read_length(&mut r)?;
+    let mut message = vec![0; diagnostics_len];
+    r.read_exact(&mut message)?;
+    let diagnostics_message = String::from_utf8_lossy(&message).to_string();
+    if !matches!(code, ResultCode::Success) {
+        return Err(ReadExtendedError::ServerError {
+            code,
+            message: diagnostics_message,
+        });
+    }
+
+    let mut rest = Vec::new();
+    r.read_to_end(&mut rest)?;
+    let mut rest = rest.as_slice();
+    let mut name = None;
+    let mut value = None;
+    while !rest.is_empty() {
+        let tag = rest.read_single_byte()?;
+        let len = read_length(&mut rest)?;
+        let Some((body, remainder)) = rest.split_at_checked(len) else {
+            return Err(ReadExtendedError::InvalidSchema);
+        };
+        rest = remainder;
+        match tag {
+            REFERRAL => {}
+            RESPONSE_NAME => {
+                let Ok(n) = String::from_utf8(body.to_vec()) else {
+                    return Err(ReadExtendedError::InvalidSchema);
+                };
+                name = Some(n);
+            }
+            RESPONSE_VALUE => value = Some(body.to_vec()),
+            _ => return Err(ReadExtendedError::InvalidSchema),
+        }
+    }
+    Ok(ExtendedResponse {
+        name,
+        value,
+        diagnostics_message,
+    })
+}
+
+#[derive(Debug)]
+pub(crate) enum ReadExtendedError {
+    Io(std::io::Error),
+    InvalidSchema,
+    ServerError { code: ResultCode, message: String },
+}
+impl From<std::io::Error> for ReadExtendedError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl From<LengthError> for ReadExtendedError {
+    fn from(value: LengthError) -> Self {
+        match value {
+            LengthError::Io(error) => Self::Io(error),
+            LengthError::Unbounded | LengthError::OutOfRange => Self::InvalidSchema,
+        }
+    }
+}
+impl From<ReadIntegerError> for ReadExtendedError {
+    fn from(value: ReadIntegerError) -> Self {
+        match value {
+            ReadIntegerError::Io(error) => Self::Io(error),
+            ReadIntegerError::Length | ReadIntegerError::OutOfRange => Self::InvalidSchema,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_generated_password, write_password_modify};
+
+    #[test]
+    fn password_modify() {
+        assert_eq!(
+            write_password_modify(Some("u:bob"), None, Some(b"new")),
+            [
+                0x30, 0x0c, 0x80, 0x05, b'u', b':', b'b', b'o', b'b', 0x82, 0x03, b'n', b'e', b'w'
+            ]
+        );
+        assert_eq!(write_password_modify(None, None, None), [0x30, 0x00]);
+
+        assert_eq!(read_generated_password(&[0x30, 0x00]), Some(None));
+        assert_eq!(
+            read_generated_password(&[0x30, 0x04, 0x80, 0x02, b'p', b'w']),
+            Some(Some(b"pw".to_vec()))
+        );
+        assert_eq!(
+            read_generated_password(&[0x30, 0x04, 0x81, 0x02, b'p', b'w']),
+            None
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt::{Formatter, Result as FmtResult};
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Whether a value can be written as is, following the SAFE-STRING rule of RFC 2849
+pub(crate) fn is_safe_string(value: &[u8]) -> bool {
+    let Some((first, rest)) = value.split_first() else {
+        return true;
+    };
+    let is_safe_char = |b: &u8| b.is_ascii() && !matches!(b, b'\0' | b'\n' | b'\r');
+    is_safe_char(first)
+        && !matches!(first, b' ' | b':' | b'<')
+        && rest.iter().all(is_safe_char)
+        && value.last() != Some(&b' ')
+}
+
+/// Writes a single `name: value` line, base64-encoding the value if it's not a safe string
+pub(crate) fn write_line(f: &mut Formatter<'_>, name: &str, value: &[u8]) -> FmtResult {
+    if is_safe_string(value) {
+        // safe strings are ASCII-only
+        writeln!(f, "{name}: {}", String::from_utf8_lossy(value))
+    } else {
+        writeln!(f, "{name}:: {}", base64_encode(value))
+    }
+}
+
+pub(crate) fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, b)| acc | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{base64_encode, is_safe_string};
+
+    #[test]
+    fn base64() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn safe_strings() {
+        assert!(is_safe_string(b"John Doe"));
+        assert!(!is_safe_string(b" leading space"));
+        assert!(!is_safe_string(b"trailing space "));
+        assert!(!is_safe_string(b":colon"));
+        assert!(!is_safe_string("Jürgen".as_bytes()));
+        assert!(!is_safe_string(b"line\nbreak"));
+    }
+}
@@ -5,34 +5,68 @@ use std::{
     num::NonZero,
     sync::{
         Arc,
-        atomic::{AtomicI32, Ordering},
+        atomic::{AtomicI32, AtomicU64, Ordering},
     },
+    time::Duration,
 };
 use tokio::sync::{
     mpsc::{UnboundedReceiver as MReceiver, UnboundedSender as MSender},
     oneshot::{Receiver as OReceiver, Sender as OSender},
 };
 
+#[cfg(feature = "active-directory")]
+pub mod ad;
 pub mod attribute;
 mod auth;
 pub mod bind;
 mod compare;
+pub mod control;
+#[cfg(all(
+    feature = "dangerous-insecure-tls",
+    any(feature = "native-tls", feature = "rustls")
+))]
+mod danger;
+#[cfg(feature = "dns-srv")]
+pub mod discovery;
+pub mod dn;
+mod error;
+pub mod extended;
 mod integer;
+mod ldif;
 mod length;
 mod message;
+#[cfg(feature = "message-log")]
+pub mod message_log;
 pub mod modify;
+#[cfg(feature = "from_octets")]
+pub mod operational;
 mod parse;
+pub mod prelude;
+mod raw;
 mod read;
 mod result;
+pub mod retry;
+pub mod schema;
 pub mod search;
+pub mod shared;
 mod stream;
 mod tag;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+mod tls;
+pub mod transaction;
+pub mod url;
 
 pub const LDAP_PORT: u16 = 389;
 pub const LDAPS_PORT: u16 = 636;
 
+pub use error::LapdogError;
 pub(crate) use message::{RequestMessage, ResponseProtocolOp};
+pub use raw::RawRequestError;
+pub use result::ResultCode;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+pub use tls::TlsConnection;
 use tokio::{
+    io::{AsyncRead, AsyncWrite},
     net::{TcpStream, ToSocketAddrs},
     sync::{
         Mutex, mpsc,
@@ -41,12 +75,32 @@ use tokio::{
 };
 
 use crate::{
-    message::RequestProtocolOp,
-    stream::{Stream, StreamReadHalf, StreamWriteHalf},
+    control::Control,
+    message::{ProtocolOp, RequestProtocolOp},
+    stream::{Socket, Stream, StreamReadHalf, StreamWriteHalf},
 };
 
+// Dependencies whose types are part of lapdog's API, so users don't have to keep their own versions in lockstep
+#[cfg(feature = "dns-srv")]
+pub use hickory_resolver;
+#[cfg(feature = "kerberos")]
+pub use kenobi;
+#[cfg(feature = "native-tls")]
+pub use native_tls;
+#[cfg(feature = "rustls")]
+pub use rustls;
+#[cfg(feature = "uuid")]
+pub use uuid;
+#[cfg(feature = "x509")]
+pub use x509_cert;
+
 const LDAP_VERSION: i32 = 3;
+const DEFAULT_READ_BUFFER_SIZE: usize = 16 * 1024;
 
+/// How to wrap the TCP stream.
+///
+/// The `domain` of the TLS variants is used for SNI and certificate verification. It's independent of the address
+/// passed to [`LdapConnection::new`], so connecting to `10.0.0.5` while verifying `dc01.example.com` works.
 #[derive(Debug, Default)]
 pub enum StreamConfig {
     #[default]
@@ -56,6 +110,8 @@ pub enum StreamConfig {
         connector: native_tls::TlsConnector,
         domain: String,
     },
+    /// Reusing the same `config` for several connections lets them resume earlier TLS sessions,
+    /// as rustls keeps a session cache in [`rustls::ClientConfig::resumption`].
     #[cfg(feature = "rustls")]
     Rustls {
         config: Arc<rustls::ClientConfig>,
@@ -72,116 +128,519 @@ impl StreamConfig {
             Self::Rustls { .. } => true,
         }
     }
+    /// Does the TLS handshake of the config, failing with [`ConnectError::HandshakeTimeout`] if it takes longer than
+    /// `handshake_timeout`
+    async fn wrap(
+        &self,
+        stream: Socket,
+        handshake_timeout: Option<Duration>,
+    ) -> Result<Stream, ConnectError> {
+        let handshake = self.handshake(stream);
+        match handshake_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, handshake)
+                .await
+                .map_err(|_| ConnectError::HandshakeTimeout)?,
+            None => handshake.await,
+        }
+    }
+    async fn handshake(&self, stream: Socket) -> Result<Stream, ConnectError> {
+        match self {
+            StreamConfig::Plain => Ok(Stream::Plain(stream)),
+            #[cfg(feature = "native-tls")]
+            StreamConfig::NativeTls { connector, domain } => {
+                let s = tokio_native_tls::TlsConnector::from(connector.clone())
+                    .connect(domain, stream)
+                    .await
+                    .map_err(ConnectError::Tls)?;
+                Ok(Stream::NativeTls(s))
+            }
+            #[cfg(feature = "rustls")]
+            StreamConfig::Rustls { config, domain } => {
+                let connector: tokio_rustls::client::TlsConnector = config.clone().into();
+                let sname = rustls_server_name(domain)?;
+                let stream = connector.connect(sname, stream).await.map_err(ConnectError::Io)?;
+                Ok(Stream::Rustls(tokio_rustls::TlsStream::Client(stream)))
+            }
+        }
+    }
+}
+
+/// Parses the name the certificate is verified against, which may be an IP address
+#[cfg(feature = "rustls")]
+fn rustls_server_name(domain: &str) -> Result<rustls::pki_types::ServerName<'static>, ConnectError> {
+    rustls::pki_types::ServerName::try_from(domain.to_owned())
+        .map_err(|_| ConnectError::InvalidRustlsDomainName)
+}
+
+/// Negotiated parameters of a TLS session.
+///
+/// Fields the TLS backend can't report are `None`, native-tls doesn't report any of them.
+#[derive(Clone, Debug, Default)]
+pub struct TlsInfo {
+    /// e.g. `TLSv1_3`
+    pub protocol_version: Option<String>,
+    /// e.g. `TLS13_AES_256_GCM_SHA384`
+    pub cipher_suite: Option<String>,
+    pub resumed: bool,
+}
+
+/// Encoding rules used for outgoing messages.
+///
+/// LDAP mandates BER, which is the default. DER is the canonical subset of BER,
+/// which is useful for stable test vectors or picky middleboxes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EncodingRules {
+    #[default]
+    Ber,
+    Der,
+}
+
+/// Socket options for [`LdapConnection::connect_with_options`]
+#[derive(Clone, Debug)]
+pub struct ConnectOptions {
+    /// Idle time after which TCP keepalive probes are sent, `None` disables keepalive.
+    ///
+    /// Keeps idle connections open through stateful firewalls and detects dead peers.
+    pub keepalive: Option<Duration>,
+    /// Time limit for establishing the TCP connection and the TLS handshake
+    pub connect_timeout: Option<Duration>,
+    /// Time limit for the TLS handshake alone, also used by `start_tls` later on, see
+    /// [`LdapConnection::set_handshake_timeout`].
+    ///
+    /// A server that accepts the connection but stalls the handshake fails with [`ConnectError::HandshakeTimeout`]
+    /// then, instead of hanging until `connect_timeout`, if any.
+    pub handshake_timeout: Option<Duration>,
+    /// Initial [`LdapConnection::operation_timeout`]
+    pub operation_timeout: Option<Duration>,
+    /// Disables Nagle's algorithm, so small requests are sent immediately. On by default.
+    ///
+    /// LDAP requests are small and each waits for its response, so Nagle's algorithm only adds latency, e.g. up to
+    /// the peer's delayed ACK timeout (often 40 ms) when a request is split over two writes.
+    pub nodelay: bool,
+    /// Capacity of the buffer responses are read through, 16 KiB by default.
+    ///
+    /// Messages are parsed a few bytes at a time, so the buffer turns those into one read of the socket per buffer
+    /// full. A larger buffer needs fewer reads for big responses like photos or certificates, but every connection
+    /// holds it for its whole lifetime. Messages larger than the buffer are read directly into their final place.
+    pub read_buffer_size: usize,
+}
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            keepalive: None,
+            connect_timeout: None,
+            handshake_timeout: None,
+            operation_timeout: None,
+            nodelay: true,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+        }
+    }
 }
 
 enum InFlightRequestHandler {
     Single(OSender<Result<Vec<u8>, ReceiveMessageError>>),
-    Multi(
-        MSender<Result<Vec<u8>, ReceiveMessageError>>,
-        Option<OReceiver<()>>,
-    ),
+    Multi(MSender<Result<Vec<u8>, ReceiveMessageError>>),
 }
 impl InFlightRequestHandler {
     fn single() -> (Self, OReceiver<Result<Vec<u8>, ReceiveMessageError>>) {
         let (sx, rx) = tokio::sync::oneshot::channel();
         (Self::Single(sx), rx)
     }
-    #[allow(clippy::type_complexity)]
-    fn multi() -> (Self, MReceiver<Result<Vec<u8>, ReceiveMessageError>>, OSender<()>) {
+    fn multi() -> (Self, MReceiver<Result<Vec<u8>, ReceiveMessageError>>) {
         let (sx, rx) = tokio::sync::mpsc::unbounded_channel();
-        let (shutdown_s, shutdown_r) = tokio::sync::oneshot::channel();
-        (Self::Multi(sx, Some(shutdown_r)), rx, shutdown_s)
+        (Self::Multi(sx), rx)
     }
 }
 
 type InFlightRequests = HashMap<NonZero<i32>, InFlightRequestHandler>;
+
+/// Shared with the task reading responses
+#[derive(Default)]
+struct ByteCounters {
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+/// A connection to an LDAP server.
+///
+/// The connection is `Send` and `Sync` with every TLS backend and any stream passed to
+/// [`LdapConnection::from_stream`], which has to be `Send` for that. Responses are routed to their request by message ID
+/// in a background task, so operations taking `&self`, like searches, can run concurrently from several tasks,
+/// e.g. through an `Arc<LdapConnection>`. Operations that change the connection's state, like binds, take
+/// `&mut self`; to mix both, share it as `Arc<tokio::sync::RwLock<LdapConnection>>`. `start_tls` consumes the
+/// connection and returns a `TlsConnection`, so upgrade before sharing it.
+///
+/// Such a lock only covers sending: [`SearchResults`](search::SearchResults) don't borrow the connection, so a
+/// search stays in flight after the read guard it was started under is dropped, and a Kerberos bind started then
+/// fails with `BindError::RequestsInFlight`. [`SyncLdapConnection`](shared::SyncLdapConnection) holds its lock
+/// until searches are over instead, at the cost of running one operation at a time.
 pub struct LdapConnection {
     message_id: Arc<AtomicI32>,
     // only none while setting up channel bind
     tcp: Arc<Mutex<Option<StreamWriteHalf>>>,
     shutdown_sender: Option<Sender<()>>,
+    #[cfg_attr(
+        not(any(feature = "kerberos", feature = "native-tls", feature = "rustls")),
+        allow(dead_code)
+    )]
     yoink_read_half: mpsc::Sender<(Sender<StreamReadHalf>, Receiver<StreamReadHalf>)>,
     inflight_requests: Arc<Mutex<InFlightRequests>>,
+    encoding_rules: EncodingRules,
+    operation_timeout: Option<Duration>,
+    tls_info: Option<TlsInfo>,
+    /// Set by `load_supported_controls`, critical controls not listed are refused before sending
+    supported_controls: Option<Vec<String>>,
+    max_request_size: Option<usize>,
+    allow_empty_base: bool,
+    read_buffer_size: usize,
+    bytes: Arc<ByteCounters>,
+    #[cfg_attr(not(any(feature = "native-tls", feature = "rustls")), allow(dead_code))]
+    handshake_timeout: Option<Duration>,
+    #[cfg(feature = "message-log")]
+    message_log: message_log::SharedMessageLog,
 }
 impl LdapConnection {
+    /// Connects with the default [`ConnectOptions`], i.e. with `TCP_NODELAY` set and no keepalive
     pub async fn new(addr: impl ToSocketAddrs, config: &StreamConfig) -> Result<Self, ConnectError> {
-        let stream = TcpStream::connect(addr).await.map_err(ConnectError::Io)?;
-        let (read, write) = match config {
-            StreamConfig::Plain => Stream::Plain(stream),
-            #[cfg(feature = "native-tls")]
-            StreamConfig::NativeTls { connector, domain } => {
-                let s = tokio_native_tls::TlsConnector::from(connector.clone())
-                    .connect(domain, stream)
-                    .await
-                    .map_err(ConnectError::Tls)?;
-                Stream::NativeTls(s)
-            }
-            #[cfg(feature = "rustls")]
-            StreamConfig::Rustls { config, domain } => {
-                use rustls::pki_types::{DnsName, ServerName};
-
-                let connector: tokio_rustls::client::TlsConnector = config.clone().into();
-                let sname = match DnsName::try_from_str(domain) {
-                    Ok(name) => ServerName::DnsName(name).to_owned(),
-                    Err(_) => return Err(ConnectError::InvalidRustlsDomainName),
-                };
-                let stream = connector.connect(sname, stream).await.map_err(ConnectError::Io)?;
-                Stream::Rustls(tokio_rustls::TlsStream::Client(stream))
+        Self::connect_with_options(addr, config, &ConnectOptions::default()).await
+    }
+    /// Like [`LdapConnection::new`], but configures the socket first, e.g. to enable TCP keepalive
+    pub async fn connect_with_options(
+        addr: impl ToSocketAddrs,
+        config: &StreamConfig,
+        options: &ConnectOptions,
+    ) -> Result<Self, ConnectError> {
+        let connect = async {
+            let stream = TcpStream::connect(addr).await.map_err(ConnectError::Io)?;
+            stream.set_nodelay(options.nodelay).map_err(ConnectError::Io)?;
+            if let Some(time) = options.keepalive {
+                let keepalive = socket2::TcpKeepalive::new().with_time(time);
+                socket2::SockRef::from(&stream)
+                    .set_tcp_keepalive(&keepalive)
+                    .map_err(ConnectError::Io)?;
             }
-        }
-        .split();
+            Self::setup(Box::new(stream), config, options).await
+        };
+        let mut connection = match options.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, connect)
+                .await
+                .map_err(|_| ConnectError::Io(ErrorKind::TimedOut.into()))??,
+            None => connect.await?,
+        };
+        connection.operation_timeout = options.operation_timeout;
+        Ok(connection)
+    }
+    /// Sets up a connection on an already connected stream, e.g. a TCP socket after custom name resolution, an SSH
+    /// forward or a unix socket of a local proxy.
+    ///
+    /// Only the TLS handshake from `config` is done, so this also works for LDAPS over such streams.
+    /// Socket options like `TCP_NODELAY` are left as they are.
+    pub async fn from_stream<S>(stream: S, config: &StreamConfig) -> Result<Self, ConnectError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        Self::setup(Box::new(stream), config, &ConnectOptions::default()).await
+    }
+    async fn setup(
+        stream: Socket,
+        config: &StreamConfig,
+        options: &ConnectOptions,
+    ) -> Result<Self, ConnectError> {
+        let stream = config.wrap(stream, options.handshake_timeout).await?;
+        let tls_info = stream.tls_info();
+        let (read, write) = stream.split(options.read_buffer_size);
         let message_id = Arc::new(AtomicI32::new(1));
         let (shutdown_sender, shutdown) = tokio::sync::oneshot::channel();
         let inflight_requests: Arc<Mutex<InFlightRequests>> = Arc::default();
         let (yoink_read_half, give_read_half) = tokio::sync::mpsc::channel(1);
         let tcp = Arc::new(Mutex::new(Some(write)));
+        let bytes = Arc::<ByteCounters>::default();
+        #[cfg(feature = "message-log")]
+        let message_log = message_log::SharedMessageLog::default();
         let new = LdapConnection {
             message_id,
             tcp,
             shutdown_sender: Some(shutdown_sender),
             yoink_read_half,
             inflight_requests: inflight_requests.clone(),
+            encoding_rules: EncodingRules::default(),
+            operation_timeout: None,
+            tls_info,
+            supported_controls: None,
+            max_request_size: None,
+            allow_empty_base: false,
+            read_buffer_size: options.read_buffer_size,
+            bytes: bytes.clone(),
+            handshake_timeout: options.handshake_timeout,
+            #[cfg(feature = "message-log")]
+            message_log: message_log.clone(),
         };
-        let fut = Self::drive(read, inflight_requests, give_read_half, shutdown);
+        let fut = Self::drive(
+            read,
+            inflight_requests,
+            give_read_half,
+            shutdown,
+            bytes,
+            #[cfg(feature = "message-log")]
+            message_log,
+        );
         tokio::spawn(fut);
         Ok(new)
     }
+    pub fn encoding_rules(&self) -> EncodingRules {
+        self.encoding_rules
+    }
+    /// Sets the encoding rules used for all following requests
+    pub fn set_encoding_rules(&mut self, rules: EncodingRules) {
+        self.encoding_rules = rules;
+    }
+    pub fn operation_timeout(&self) -> Option<Duration> {
+        self.operation_timeout
+    }
+    /// Sets a total time limit for each following operation, no matter how many messages it takes.
+    ///
+    /// Operations that run out of time fail with an IO error of kind [`ErrorKind::TimedOut`],
+    /// searches with [`SearchResultError::Timeout`](search::SearchResultError::Timeout).
+    pub fn set_operation_timeout(&mut self, timeout: Option<Duration>) {
+        self.operation_timeout = timeout;
+    }
+    /// Capacity of the buffer responses are read through, see [`ConnectOptions::read_buffer_size`]
+    pub fn read_buffer_size(&self) -> usize {
+        self.read_buffer_size
+    }
+    pub fn handshake_timeout(&self) -> Option<Duration> {
+        self.handshake_timeout
+    }
+    /// Sets a time limit for the TLS handshake of `start_tls`, which fails with [`ConnectError::HandshakeTimeout`]
+    /// once it runs out. Starts out as [`ConnectOptions::handshake_timeout`].
+    pub fn set_handshake_timeout(&mut self, timeout: Option<Duration>) {
+        self.handshake_timeout = timeout;
+    }
+    /// Bytes of LDAP messages sent since connecting, before any TLS or SASL encryption
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes.sent.load(Ordering::Relaxed)
+    }
+    /// Bytes of LDAP messages received since connecting, after any TLS or SASL decryption.
+    ///
+    /// Responses are read in the background, so this includes responses nobody asked for yet.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes.received.load(Ordering::Relaxed)
+    }
+    pub fn max_request_size(&self) -> Option<usize> {
+        self.max_request_size
+    }
+    /// Limits the size in bytes of each following request message, `None` (the default) for no limit.
+    ///
+    /// Requests are encoded into memory as a whole before they're sent, and nested sequences are copied into their
+    /// parent, so a huge value like a certificate list or photo briefly needs about three times its size:
+    /// the value itself plus two encoded copies.
+    /// Requests whose values alone exceed the limit fail with [`EncodeError::RequestTooLarge`] before anything is
+    /// encoded, others once their encoded size is known; nothing is sent in both cases.
+    pub fn set_max_request_size(&mut self, limit: Option<usize>) {
+        self.max_request_size = limit;
+    }
+    pub fn allows_empty_base(&self) -> bool {
+        self.allow_empty_base
+    }
+    /// Allows one-level and subtree searches from the empty base `""` for all following searches, including the
+    /// shortcuts like `search_all`, `find_all` and `count`.
+    ///
+    /// Such searches cover the whole directory and fail with
+    /// [`BeginSearchError::is_empty_base`](search::BeginSearchError::is_empty_base) by default; earlier versions
+    /// sent them unchecked. See [`SearchRequestBuilder::allow_empty_base`](search::SearchRequestBuilder::allow_empty_base)
+    /// to allow them for a single search.
+    pub fn set_allow_empty_base(&mut self, allow: bool) {
+        self.allow_empty_base = allow;
+    }
+    /// The message ID the next request will get
+    pub fn next_message_id(&self) -> i32 {
+        self.message_id.load(Ordering::Relaxed)
+    }
+    /// Sets the message ID of the next request, which otherwise starts at 1, e.g. to replay captured traffic
+    /// or to continue a session started by another LDAP library on the same socket.
+    ///
+    /// Message IDs must stay unique among requests in flight, so only change this while none are.
+    /// Requests fail with [`EncodeError::MessageIdsExhausted`] if `id` is negative or once the IDs pass `i32::MAX`.
+    pub fn set_next_message_id(&mut self, id: NonZero<i32>) {
+        self.message_id.store(id.get(), Ordering::Relaxed);
+    }
+    /// Whether the TLS handshake resumed an earlier session. Only rustls reports this, it's always false otherwise.
+    pub fn was_resumed(&self) -> bool {
+        self.tls_info.as_ref().is_some_and(|info| info.resumed)
+    }
+    /// The negotiated TLS parameters, e.g. for audit logs. `None` if the connection isn't using TLS.
+    pub fn tls_info(&self) -> Option<&TlsInfo> {
+        self.tls_info.as_ref()
+    }
+    /// Whether the connection is protected by TLS, either from the start or after `start_tls`
+    pub async fn is_tls(&self) -> bool {
+        match self.tcp.lock().await.as_ref() {
+            #[cfg(feature = "native-tls")]
+            Some(StreamWriteHalf::NativeTls(_)) => true,
+            #[cfg(feature = "rustls")]
+            Some(StreamWriteHalf::Rustls(_)) => true,
+            _ => false,
+        }
+    }
+    /// Upgrades a plain connection to TLS with the StartTLS extended operation.
+    ///
+    /// No other requests may be in flight while upgrading. The connection is consumed either way, as it's in an
+    /// unknown state after a failed upgrade.
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    pub async fn start_tls(mut self, config: &StreamConfig) -> Result<TlsConnection, StartTlsError> {
+        if !config.is_tls() {
+            return Err(StartTlsError::NotTls);
+        }
+        if !matches!(self.tcp.lock().await.as_ref(), Some(StreamWriteHalf::Plain(_))) {
+            return Err(StartTlsError::AlreadyUpgraded);
+        }
+        if !self.inflight_requests.lock().await.is_empty() {
+            return Err(StartTlsError::RequestsInFlight);
+        }
+        self.extended_operation(extended::START_TLS_OID, None)
+            .await
+            .map_err(StartTlsError::Extended)?;
+
+        // take both halves, wrap the joined TCP stream, give the read half back
+        let (return_envelope, rec_stream_half) = tokio::sync::oneshot::channel();
+        let (give_back_stream_half, return_return_envelope) = tokio::sync::oneshot::channel();
+        self.yoink_read_half
+            .send((return_envelope, return_return_envelope))
+            .await
+            .map_err(|_| StartTlsError::Disconnected)?;
+        let (mut own_lock, read_half) = tokio::join!(self.tcp.lock(), rec_stream_half);
+        let (Ok(read_half), Some(write_half)) = (read_half, own_lock.take()) else {
+            return Err(StartTlsError::Disconnected);
+        };
+        let Stream::Plain(tcp) = Stream::unsplit(read_half, write_half)
+            .map_err(|e| StartTlsError::Connect(ConnectError::Io(e)))?
+        else {
+            unreachable!("checked to be a plain stream")
+        };
+        let stream = config
+            .wrap(tcp, self.handshake_timeout)
+            .await
+            .map_err(StartTlsError::Connect)?;
+        self.tls_info = stream.tls_info();
+        let (r, w) = stream.split(self.read_buffer_size);
+        *own_lock = Some(w);
+        if give_back_stream_half.send(r).is_err() {
+            return Err(StartTlsError::Disconnected);
+        };
+        drop(own_lock);
+        Ok(TlsConnection(self))
+    }
+    fn abandoner(&self) -> Abandoner {
+        Abandoner {
+            message_id: self.message_id.clone(),
+            tcp: self.tcp.clone(),
+            bytes: self.bytes.clone(),
+            encoding_rules: self.encoding_rules,
+            #[cfg(feature = "message-log")]
+            message_log: self.message_log.clone(),
+        }
+    }
+    async fn write_message(&self, bytes: &[u8]) -> Result<(), SendMessageError> {
+        self.tcp
+            .lock()
+            .await
+            .as_mut()
+            .ok_or(SendMessageError::ChannelClosed)?
+            .write_message(bytes)
+            .await
+            .map_err(SendMessageError::Io)?;
+        self.bytes.sent.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
     async fn send_message(
         &self,
         protocol_op: RequestProtocolOp<'_>,
     ) -> Result<IncomingMessage, SendMessageError> {
+        self.send_message_with_controls(protocol_op, &[]).await
+    }
+    async fn send_message_with_controls(
+        &self,
+        protocol_op: RequestProtocolOp<'_>,
+        controls: &[Control],
+    ) -> Result<IncomingMessage, SendMessageError> {
+        let unsupported = self.supported_controls.as_ref().and_then(|supported| {
+            controls
+                .iter()
+                .find(|c| c.criticality && !supported.contains(&c.oid))
+        });
+        if let Some(control) = unsupported {
+            return Err(SendMessageError::Encode(EncodeError::UnsupportedControl(
+                control.oid.clone(),
+            )));
+        }
+        if let Some(limit) = self.max_request_size {
+            let size = protocol_op.payload_len();
+            if size > limit {
+                return Err(SendMessageError::Encode(EncodeError::RequestTooLarge {
+                    size,
+                    limit,
+                }));
+            }
+        }
         let message_id = self.message_id.fetch_add(1, Ordering::Relaxed);
-        let id = NonZero::new(message_id).unwrap();
-        let is_search = matches!(protocol_op, RequestProtocolOp::Search { .. });
-        let bytes = RequestMessage {
-            message_id: Some(id),
-            protocol_op,
+        let Some(id) = NonZero::new(message_id).filter(|id| id.get() > 0) else {
+            return Err(SendMessageError::Encode(EncodeError::MessageIdsExhausted));
+        };
+        // raw ops are told apart by their tag number, as the typed ops' tags are
+        let is_search = protocol_op.to_tag() == 3;
+        let expects_response = !matches!(protocol_op.to_tag(), 2 | 16);
+        let bytes = message::encode_message(
+            &RequestMessage {
+                message_id: Some(id),
+                protocol_op,
+                controls,
+            },
+            self.encoding_rules,
+        )
+        .map_err(SendMessageError::Encode)?;
+        if let Some(limit) = self.max_request_size.filter(|limit| bytes.len() > *limit) {
+            return Err(SendMessageError::Encode(EncodeError::RequestTooLarge {
+                size: bytes.len(),
+                limit,
+            }));
         }
-        .to_bytes();
-        if is_search {
-            let (sx, rx, shutdown) = InFlightRequestHandler::multi();
+        #[cfg(feature = "message-log")]
+        message_log::record_request(&self.message_log, message_id, &bytes);
+        if !expects_response {
+            self.write_message(&bytes).await?;
+            Ok(IncomingMessage::NoResponse)
+        } else if is_search {
+            let (sx, rx) = InFlightRequestHandler::multi();
+            let (shutdown, finished) = tokio::sync::oneshot::channel();
             self.inflight_requests.lock().await.insert(id, sx);
-            self.tcp
-                .lock()
-                .await
-                .as_mut()
-                .unwrap()
-                .write_message(&bytes)
-                .await
-                .map_err(SendMessageError::Io)?;
-            Ok(IncomingMessage::MessageReceiver(rx, shutdown))
+            // Forget the search once it's done or its results are dropped, e.g. after abandoning it, so the
+            // connection doesn't count it as in flight any longer and drops late responses to it
+            let inflight_requests = self.inflight_requests.clone();
+            let abandoner = self.abandoner();
+            tokio::spawn(async move {
+                let end = finished.await;
+                inflight_requests.lock().await.remove(&id);
+                if let Ok(SearchEnd::Abandon) = end {
+                    abandoner.abandon(id).await;
+                }
+            });
+            self.write_message(&bytes).await?;
+            Ok(IncomingMessage::MessageReceiver(id, rx, shutdown))
         } else {
             let (sx, rx) = InFlightRequestHandler::single();
             self.inflight_requests.lock().await.insert(id, sx);
-            self.tcp
-                .lock()
-                .await
-                .as_mut()
-                .unwrap()
-                .write_message(&bytes)
-                .await
-                .map_err(SendMessageError::Io)?;
-            match rx.await {
+            self.write_message(&bytes).await?;
+            let response = match self.operation_timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, rx).await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        self.inflight_requests.lock().await.remove(&id);
+                        return Err(SendMessageError::Io(ErrorKind::TimedOut.into()));
+                    }
+                },
+                None => rx.await,
+            };
+            match response {
                 Ok(Ok(values)) => Ok(IncomingMessage::Message(values)),
                 Err(_) => Err(SendMessageError::ChannelClosed),
                 Ok(Err(e)) => Err(SendMessageError::ReceiveMessage(e)),
@@ -193,16 +652,19 @@ impl LdapConnection {
         inflight_requests: Arc<Mutex<InFlightRequests>>,
         mut yoink_read_half: mpsc::Receiver<(Sender<StreamReadHalf>, Receiver<StreamReadHalf>)>,
         mut shutdown: Receiver<()>,
+        bytes: Arc<ByteCounters>,
+        #[cfg(feature = "message-log")] message_log: message_log::SharedMessageLog,
     ) {
         // only none while setting up channel bind
         let mut stream_opt = Some(read_half);
         loop {
-            let (message_id, body) = tokio::select! {
+            let (message_id, body, size) = tokio::select! {
                 _ = &mut shutdown => return,
                 b = stream_opt.as_mut().unwrap().get_next_message() => {
                     match b {
                         Ok(values) => values,
-                        Err(e) if e.kind() == ErrorKind::ConnectionReset =>  {
+                        // a clean EOF is the server closing the connection as well
+                        Err(e) if matches!(e.kind(), ErrorKind::ConnectionReset | ErrorKind::UnexpectedEof) =>  {
                             break;
                         },
                         e => panic!("error checking message: {e:?}")
@@ -223,6 +685,9 @@ impl LdapConnection {
                     continue;
                 },
             };
+            bytes.received.fetch_add(size as u64, Ordering::Relaxed);
+            #[cfg(feature = "message-log")]
+            message_log::record(&message_log, message_log::Direction::Received, message_id, &body);
             let Some(id) = NonZero::new(message_id) else {
                 continue;
             };
@@ -235,18 +700,11 @@ impl LdapConnection {
                     }
                 }
 
-                Some(InFlightRequestHandler::Multi(sender, shutdown)) => {
+                Some(InFlightRequestHandler::Multi(sender)) => {
                     if sender.send(Ok(body)).is_err() {
                         continue;
                     }
-                    if let Some(shutdown) = shutdown {
-                        let ifr = inflight_requests.clone();
-                        tokio::spawn(async move {
-                            let _ = shutdown.await;
-                            ifr.lock().await.remove(&id);
-                        });
-                    }
-                    inflight_lock.insert(id, InFlightRequestHandler::Multi(sender, None));
+                    inflight_lock.insert(id, InFlightRequestHandler::Multi(sender));
                 }
             }
         }
@@ -254,7 +712,7 @@ impl LdapConnection {
             InFlightRequestHandler::Single(sender) => {
                 let _ = sender.send(Err(ReceiveMessageError::ConnectionClosed));
             }
-            InFlightRequestHandler::Multi(sender, _) => {
+            InFlightRequestHandler::Multi(sender) => {
                 let _ = sender.send(Err(ReceiveMessageError::ConnectionClosed));
             }
         });
@@ -271,19 +729,27 @@ impl Drop for LdapConnection {
 #[derive(Debug)]
 pub enum ConnectError {
     Io(std::io::Error),
+    /// The TLS handshake took longer than [`ConnectOptions::handshake_timeout`]
+    HandshakeTimeout,
     #[cfg(feature = "rustls")]
     InvalidRustlsDomainName,
     #[cfg(feature = "native-tls")]
     Tls(native_tls::Error),
+    /// A [`TlsConnection`] was asked for with [`StreamConfig::Plain`]
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    NotTls,
 }
 impl std::error::Error for ConnectError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::Io(io) => Some(io),
+            Self::HandshakeTimeout => None,
             #[cfg(feature = "rustls")]
             Self::InvalidRustlsDomainName => None,
             #[cfg(feature = "native-tls")]
             Self::Tls(tls) => Some(tls),
+            #[cfg(any(feature = "native-tls", feature = "rustls"))]
+            Self::NotTls => None,
         }
     }
 }
@@ -291,28 +757,122 @@ impl Display for ConnectError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Io(io) => write!(f, "Failed to connect: {io}"),
+            Self::HandshakeTimeout => write!(f, "TLS handshake timed out"),
             #[cfg(feature = "rustls")]
             Self::InvalidRustlsDomainName => write!(f, "Invalid domain name"),
             #[cfg(feature = "native-tls")]
             Self::Tls(tls) => write!(f, "Failed to setup secure channel: {tls}"),
+            #[cfg(any(feature = "native-tls", feature = "rustls"))]
+            Self::NotTls => write!(f, "Stream config for a TLS connection is not a TLS config"),
+        }
+    }
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+#[derive(Debug)]
+pub enum StartTlsError {
+    /// The given stream config doesn't use TLS
+    NotTls,
+    /// The connection already uses TLS or a SASL security layer
+    AlreadyUpgraded,
+    RequestsInFlight,
+    Disconnected,
+    Extended(extended::ExtendedOpError),
+    Connect(ConnectError),
+}
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+impl std::error::Error for StartTlsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Extended(e) => Some(e),
+            Self::Connect(c) => Some(c),
+            _ => None,
+        }
+    }
+}
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+impl Display for StartTlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotTls => write!(f, "Stream config for StartTLS is not a TLS config"),
+            Self::AlreadyUpgraded => write!(f, "Connection is already secured"),
+            Self::RequestsInFlight => write!(f, "Cannot upgrade with active requests in flight"),
+            Self::Disconnected => write!(f, "Connection disconnected"),
+            Self::Extended(e) => write!(f, "Server rejected StartTLS: {e}"),
+            Self::Connect(c) => write!(f, "TLS handshake failed: {c}"),
+        }
+    }
+}
+
+/// Sent by a search's results once they no longer wait for responses
+pub(crate) enum SearchEnd {
+    Finished,
+    /// The search was given up on, e.g. after its deadline, so the server should stop working on it
+    Abandon,
+}
+
+/// What a search's cleanup task needs to abandon it, as it can't borrow the connection
+struct Abandoner {
+    message_id: Arc<AtomicI32>,
+    tcp: Arc<Mutex<Option<StreamWriteHalf>>>,
+    bytes: Arc<ByteCounters>,
+    encoding_rules: EncodingRules,
+    #[cfg(feature = "message-log")]
+    message_log: message_log::SharedMessageLog,
+}
+impl Abandoner {
+    /// Sends an abandon request for `id`. Failures are ignored like those of the abandoned search's cleanup.
+    async fn abandon(self, id: NonZero<i32>) {
+        let message_id = self.message_id.fetch_add(1, Ordering::Relaxed);
+        let Some(own_id) = NonZero::new(message_id).filter(|id| id.get() > 0) else {
+            return;
+        };
+        let message = RequestMessage {
+            message_id: Some(own_id),
+            protocol_op: RequestProtocolOp::Abandon { message_id: id },
+            controls: &[],
+        };
+        let Ok(bytes) = message::encode_message(&message, self.encoding_rules) else {
+            return;
+        };
+        #[cfg(feature = "message-log")]
+        message_log::record_request(&self.message_log, message_id, &bytes);
+        let mut tcp = self.tcp.lock().await;
+        let Some(tcp) = tcp.as_mut() else {
+            return;
+        };
+        if tcp.write_message(&bytes).await.is_ok() {
+            self.bytes.sent.fetch_add(bytes.len() as u64, Ordering::Relaxed);
         }
     }
 }
 
 enum IncomingMessage {
+    NoResponse,
     Message(Vec<u8>),
-    MessageReceiver(MReceiver<Result<Vec<u8>, ReceiveMessageError>>, OSender<()>),
+    MessageReceiver(
+        NonZero<i32>,
+        MReceiver<Result<Vec<u8>, ReceiveMessageError>>,
+        OSender<SearchEnd>,
+    ),
 }
 impl IncomingMessage {
     fn into_message(self) -> Vec<u8> {
         let Self::Message(vec) = self else { panic!() };
         vec
     }
-    fn into_receiver(self) -> (MReceiver<Result<Vec<u8>, ReceiveMessageError>>, OSender<()>) {
-        let Self::MessageReceiver(recv, shutdown) = self else {
+    #[allow(clippy::type_complexity)]
+    fn into_receiver(
+        self,
+    ) -> (
+        NonZero<i32>,
+        MReceiver<Result<Vec<u8>, ReceiveMessageError>>,
+        OSender<SearchEnd>,
+    ) {
+        let Self::MessageReceiver(id, recv, shutdown) = self else {
             panic!()
         };
-        (recv, shutdown)
+        (id, recv, shutdown)
     }
 }
 
@@ -321,11 +881,13 @@ enum SendMessageError {
     Io(std::io::Error),
     ChannelClosed,
     ReceiveMessage(ReceiveMessageError),
+    Encode(EncodeError),
 }
 impl std::error::Error for SendMessageError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::Io(io) => Some(io),
+            Self::Encode(e) => Some(e),
             _ => None,
         }
     }
@@ -334,12 +896,61 @@ impl Display for SendMessageError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::ChannelClosed => write!(f, "internal channel closed"),
+            Self::Encode(e) => write!(f, "Failed to encode request: {e}"),
             Self::Io(io) => write!(f, "Failed to write to stream: {io}"),
             Self::ReceiveMessage(rcv) => write!(f, "No message received: {rcv:?}"),
         }
     }
 }
 
+/// A request couldn't be encoded, so it was never sent
+#[derive(Debug)]
+pub enum EncodeError {
+    Io(std::io::Error),
+    EmptyAttributeDescription,
+    InvalidOid(String),
+    InvalidFilter,
+    MessageIdsExhausted,
+    /// A raw protocol op doesn't start with an application tag
+    InvalidProtocolOp,
+    /// A critical control isn't in the server's `supportedControl`, see [`LdapConnection::load_supported_controls`]
+    UnsupportedControl(String),
+    /// The request is larger than [`LdapConnection::max_request_size`]. `size` may only be a lower bound.
+    RequestTooLarge {
+        size: usize,
+        limit: usize,
+    },
+}
+impl From<std::io::Error> for EncodeError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl std::error::Error for EncodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        if let Self::Io(io) = self { Some(io) } else { None }
+    }
+}
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(io) => write!(f, "Failed to write message: {io}"),
+            Self::EmptyAttributeDescription => write!(f, "Attribute description is empty"),
+            Self::InvalidOid(oid) => write!(f, "\"{oid}\" is not a numeric OID"),
+            Self::InvalidFilter => write!(f, "Filter is invalid"),
+            Self::MessageIdsExhausted => write!(f, "No message IDs left on this connection"),
+            Self::InvalidProtocolOp => write!(f, "Raw protocol op is not an LDAP protocol op"),
+            Self::RequestTooLarge { size, limit } => {
+                write!(
+                    f,
+                    "Request of at least {size} bytes exceeds the limit of {limit} bytes"
+                )
+            }
+            Self::UnsupportedControl(oid) => write!(f, "Server does not support the critical control {oid}"),
+        }
+    }
+}
+
 #[derive(Debug)]
 enum ReceiveMessageError {
     ConnectionClosed,
@@ -375,7 +986,210 @@ impl<W: Write> WriteExt for W {}
 pub mod test {
     #[cfg(feature = "kerberos")]
     use kenobi::mech::Mechanism;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::{LdapConnection, StreamConfig};
+
+    /// A plain connection to a local socket, with the server's end of it to script the responses
+    pub(crate) async fn mock_connection() -> (LdapConnection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let connection = LdapConnection::from_stream(stream, &StreamConfig::default())
+            .await
+            .unwrap();
+        (connection, server)
+    }
+
+    #[test]
+    fn connection_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<crate::LdapConnection>();
+        assert_send_sync::<crate::search::SearchResults>();
+        assert_send_sync::<crate::shared::SyncLdapConnection>();
+        assert_send_sync::<crate::shared::SyncSearchResults>();
+    }
+
+    #[tokio::test]
+    async fn start_at_message_id() {
+        use std::num::NonZero;
+
+        use tokio::io::AsyncReadExt;
+        let (mut connection, mut server) = mock_connection().await;
+        connection.set_next_message_id(NonZero::new(300).unwrap());
+        connection.abandon(NonZero::new(7).unwrap()).await.unwrap();
+        assert_eq!(connection.next_message_id(), 301);
+        assert_eq!(connection.bytes_sent(), 9);
+
+        let mut abandon = [0; 9];
+        server.read_exact(&mut abandon).await.unwrap();
+        // message ID 300, then the abandon request for 7
+        assert_eq!(abandon, [0x30, 0x07, 0x02, 0x02, 0x01, 0x2c, 0x50, 0x01, 0x07]);
+    }
+
+    #[tokio::test]
+    async fn connect_over_any_stream() {
+        use std::num::NonZero;
+
+        use tokio::io::AsyncReadExt;
+        let (stream, mut server) = tokio::io::duplex(64);
+        let connection = LdapConnection::from_stream(stream, &StreamConfig::default())
+            .await
+            .unwrap();
+        connection.abandon(NonZero::new(7).unwrap()).await.unwrap();
+
+        let mut abandon = [0; 8];
+        server.read_exact(&mut abandon).await.unwrap();
+        assert_eq!(abandon, [0x30, 0x06, 0x02, 0x01, 0x01, 0x50, 0x01, 0x07]);
+    }
+
+    #[tokio::test]
+    async fn dropped_search_is_no_longer_in_flight() {
+        use std::time::Duration;
+
+        use crate::search::{DerefPolicy, Filter, Scope};
+        // never answers
+        let (connection, _server) = mock_connection().await;
+        let results = connection
+            .search_all(
+                "dc=x",
+                Scope::WholeSubtree,
+                DerefPolicy::Never,
+                Filter::Present("cn"),
+            )
+            .await
+            .unwrap();
+        connection.abandon(results.message_id()).await.unwrap();
+        drop(results);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !connection.inflight_requests.lock().await.is_empty() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("search still in flight");
+    }
+
+    #[tokio::test]
+    async fn refuse_unsupported_critical_control() {
+        use crate::{
+            EncodeError, attribute::AttributeValueAssertion, compare::CompareError, control::Control,
+        };
+        let (mut connection, _server) = mock_connection().await;
+        connection.supported_controls = Some(vec!["1.3.6.1.1.22".to_string()]);
+        let result = connection
+            .compare_with_controls(
+                "cn=a,dc=x",
+                AttributeValueAssertion {
+                    attribute_desc: "cn",
+                    assertion_value: b"a",
+                },
+                &[Control::relax_rules()],
+            )
+            .await;
+        assert!(
+            matches!(result, Err(CompareError::Encode(EncodeError::UnsupportedControl(oid))) if oid == crate::control::RELAX_RULES_OID)
+        );
+        // nothing was sent, so the message ID wasn't used
+        assert_eq!(connection.next_message_id(), 1);
+    }
+
+    #[tokio::test]
+    async fn refuse_oversized_request() {
+        use crate::{
+            EncodeError,
+            modify::{Change, ModifyError, Operation},
+        };
+        let (mut connection, _server) = mock_connection().await;
+        connection.set_max_request_size(Some(100));
+
+        let photo = [0; 200];
+        let values: &[&[u8]] = &[&photo];
+        let changes = [Change {
+            operation: Operation::Replace,
+            attribute_type: "jpegPhoto",
+            attribute_values: values,
+        }];
+        let result = connection.modify("cn=a,dc=x", &changes).await;
+        assert!(matches!(
+            result,
+            Err(ModifyError::Encode(EncodeError::RequestTooLarge {
+                size: 218,
+                limit: 100
+            }))
+        ));
+
+        // the values fit, but not with the encoding around them
+        let photo = [0; 80];
+        let values: &[&[u8]] = &[&photo];
+        let changes = [Change {
+            operation: Operation::Replace,
+            attribute_type: "jpegPhoto",
+            attribute_values: values,
+        }];
+        let result = connection.modify("cn=a,dc=x", &changes).await;
+        assert!(matches!(
+            result,
+            Err(ModifyError::Encode(EncodeError::RequestTooLarge {
+                limit: 100,
+                ..
+            }))
+        ));
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[tokio::test]
+    async fn stalled_handshake_times_out() {
+        use std::time::Duration;
 
+        use crate::{ConnectError, ConnectOptions};
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // accepts, but never answers the ClientHello
+        let _server = tokio::spawn(async move { listener.accept().await });
+        let config = StreamConfig::NativeTls {
+            connector: native_tls::TlsConnector::new().unwrap(),
+            domain: "localhost".to_string(),
+        };
+        let options = ConnectOptions {
+            handshake_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let result = LdapConnection::connect_with_options(addr, &config, &options).await;
+        assert!(matches!(result, Err(ConnectError::HandshakeTimeout)));
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    #[tokio::test]
+    async fn plain_connection_is_no_tls_connection() {
+        use crate::{ConnectError, TlsConnection};
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let result = TlsConnection::new(addr, &StreamConfig::Plain).await;
+        assert!(matches!(result, Err(ConnectError::NotTls)));
+
+        let connection = LdapConnection::new(addr, &StreamConfig::Plain).await.unwrap();
+        assert!(TlsConnection::try_from(connection).is_err());
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn rustls_server_name_is_independent_of_address() {
+        use rustls::pki_types::ServerName;
+
+        use crate::rustls_server_name;
+        assert!(matches!(
+            rustls_server_name("dc01.example.com"),
+            Ok(ServerName::DnsName(name)) if name.as_ref() == "dc01.example.com"
+        ));
+        assert!(matches!(
+            rustls_server_name("10.0.0.5"),
+            Ok(ServerName::IpAddress(_))
+        ));
+        assert!(rustls_server_name("not a name").is_err());
+    }
+
+    #[cfg(feature = "kerberos")]
     use crate::{
         LdapConnection,
         search::{DerefPolicy, Filter, Scope, SearchResult},
@@ -454,6 +1268,7 @@ pub mod test {
         test_tls(Mechanism::Spnego).await
     }
 
+    #[cfg(feature = "kerberos")]
     async fn test_search(ldap: &mut LdapConnection) {
         let filter = Filter::Present("userPrincipalName");
         let search_base = std::env::var("LAPDOG_TEST_SEARCH_BASE").unwrap();
@@ -477,7 +1292,7 @@ pub mod test {
                     count += 1
                 }
                 Ok(Some(Err(e))) => println!("Encountered search error: {e:?}"),
-                Ok(Some(Ok(SearchResult::Reference))) => {}
+                Ok(Some(Ok(SearchResult::Reference(_) | SearchResult::Intermediate { .. }))) => {}
                 Ok(Some(Ok(SearchResult::Done { .. }))) | Ok(None) | Err(_) => break,
             };
         }
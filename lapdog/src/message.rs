@@ -4,53 +4,51 @@ use std::{
 };
 
 use crate::{
-    WriteExt, attribute,
+    EncodeError, EncodingRules, WriteExt, attribute,
     auth::Authentication,
     bind::{self, BindStatus},
     compare::{self, ReadCompareError},
+    control::{self, Control},
+    extended::{self, ExtendedResponse, ReadExtendedError},
     length::{LengthError, read_length},
     modify::{self, Change, ReadModifyError},
     read::ReadExt,
     result::ResultCode,
-    search::{self, DerefPolicy, Filter, Scope},
+    search::{self, SearchRequest},
     tag::{
         PrimitiveOrConstructed as PrimOrCons, TagClass, UNIVERSAL_INTEGER, UNIVERSAL_SEQUENCE, get_tag_number,
     },
 };
 
-pub type RequestMessage<'a> = Message<RequestProtocolOp<'a>>;
-
 #[derive(Debug)]
-pub struct Message<ProtocolOp> {
+pub struct RequestMessage<'a> {
     pub(crate) message_id: Option<NonZero<i32>>,
-    pub(crate) protocol_op: ProtocolOp,
+    pub(crate) protocol_op: RequestProtocolOp<'a>,
+    pub(crate) controls: &'a [Control],
 }
-impl RequestMessage<'_> {
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buffer = Vec::new();
-        buffer.push(UNIVERSAL_SEQUENCE);
-
-        let mut ldap_message = Vec::new();
-
+/// Encodes a request into a complete LDAP message.
+///
+/// Input the server could never accept is rejected here instead of panicking.
+pub(crate) fn encode_message(message: &RequestMessage, rules: EncodingRules) -> Result<Vec<u8>, EncodeError> {
+    message.protocol_op.validate()?;
+    let mut buffer = Vec::new();
+    buffer.write_sequence(UNIVERSAL_SEQUENCE, |ldap_message| {
         // Message ID
         ldap_message.push(UNIVERSAL_INTEGER);
 
-        let id = self.message_id.map(Into::into).unwrap_or_default();
+        let id = message.message_id.map(Into::into).unwrap_or_default();
         let mut int_b = Vec::new();
-        int_b.write_ber_integer_body(id).expect("infallible");
+        int_b.write_ber_integer_body(id)?;
 
-        ldap_message.write_ber_length(int_b.len()).expect("infallible");
+        ldap_message.write_ber_length(int_b.len())?;
         ldap_message.extend_from_slice(&int_b);
 
         // Protocol Op
-        self.protocol_op
-            .write_into(&mut ldap_message)
-            .expect("infallible");
+        message.protocol_op.write_into(&mut *ldap_message, rules)?;
 
-        buffer.write_ber_length(ldap_message.len()).expect("infallible");
-        buffer.extend(&ldap_message);
-        buffer
-    }
+        control::write_controls(ldap_message, message.controls)
+    })?;
+    Ok(buffer)
 }
 
 #[derive(Clone, Debug)]
@@ -70,7 +68,7 @@ pub enum ResponseProtocolOp {
     Compare {
         compare: bool,
     },
-    Extended,
+    Extended(ExtendedResponse),
     Intermediate,
 }
 impl ProtocolOp for ResponseProtocolOp {
@@ -85,7 +83,7 @@ impl ProtocolOp for ResponseProtocolOp {
             Self::ModifyDN => 13,
             Self::Compare { .. } => 15,
             Self::SearchResultReference => 19,
-            Self::Extended => 24,
+            Self::Extended(_) => 24,
             Self::Intermediate => 25,
         }
     }
@@ -93,11 +91,8 @@ impl ProtocolOp for ResponseProtocolOp {
         let choice_tag = r.read_single_byte().map_err(ReadProtocolOpError::Io)?;
         let class = TagClass::from_bits(choice_tag);
         let poc = PrimOrCons::from_bit(choice_tag);
-        let TagClass::Application = class else {
-            panic!("Bad choice tag class")
-        };
-        let PrimOrCons::Constructed = poc else {
-            panic!("Bad choice primitive/constructed");
+        let (TagClass::Application, PrimOrCons::Constructed) = (class, poc) else {
+            return Err(ReadProtocolOpError::InvalidSchema);
         };
         let tag = get_tag_number(choice_tag);
         let len = read_length(&mut r)?;
@@ -122,14 +117,24 @@ impl ProtocolOp for ResponseProtocolOp {
                 let compare = compare::read_response(message_body_reader)?;
                 Ok(Self::Compare { compare })
             }
-            _ => todo!(),
+            24 => {
+                let response = extended::read_response(message_body_reader)?;
+                Ok(Self::Extended(response))
+            }
+            // e.g. an IntermediateResponse, or a search response to a request that isn't a search
+            _ => Err(ReadProtocolOpError::InvalidSchema),
         }
     }
 }
 #[derive(Debug)]
 pub enum ReadProtocolOpError {
     Io(std::io::Error),
-    ServerError { code: ResultCode, message: String },
+    ServerError {
+        code: ResultCode,
+        message: String,
+    },
+    /// The server returned these URLs of servers to retry the request with
+    Referral(Vec<String>),
     InvalidSchema,
 }
 impl From<bind::ReadBindError> for ReadProtocolOpError {
@@ -140,6 +145,7 @@ impl From<bind::ReadBindError> for ReadProtocolOpError {
                 Self::InvalidSchema
             }
             bind::ReadBindError::BindError { code, message } => Self::ServerError { code, message },
+            bind::ReadBindError::Referral(urls) => Self::Referral(urls),
         }
     }
 }
@@ -161,6 +167,15 @@ impl From<ReadModifyError> for ReadProtocolOpError {
         }
     }
 }
+impl From<ReadExtendedError> for ReadProtocolOpError {
+    fn from(value: ReadExtendedError) -> Self {
+        match value {
+            ReadExtendedError::Io(error) => Self::Io(error),
+            ReadExtendedError::InvalidSchema => Self::InvalidSchema,
+            ReadExtendedError::ServerError { code, message } => Self::ServerError { code, message },
+        }
+    }
+}
 impl From<LengthError> for ReadProtocolOpError {
     fn from(value: LengthError) -> Self {
         match value {
@@ -175,16 +190,11 @@ impl From<LengthError> for ReadProtocolOpError {
 pub enum RequestProtocolOp<'a> {
     /// Bind-dn usually empty for SASL bind
     Bind {
+        name: &'a str,
         authentication: Authentication<'a>,
     },
     Unbind,
-    Search {
-        entry: &'a str,
-        scope: Scope,
-        deref_policy: DerefPolicy,
-        filter: Filter<'a>,
-        attributes: &'a [&'a str],
-    },
+    Search(SearchRequest<'a>),
     Modify {
         object: &'a str,
         changes: &'a [Change<'a>],
@@ -196,22 +206,30 @@ pub enum RequestProtocolOp<'a> {
         entry: &'a str,
         value_assertion: attribute::AttributeValueAssertion<'a>,
     },
-    Abandon,
-    Extended,
+    Abandon {
+        message_id: NonZero<i32>,
+    },
+    Extended {
+        name: &'a str,
+        value: Option<&'a [u8]>,
+    },
+    /// An already encoded protocol op, including its tag and length
+    Raw(&'a [u8]),
 }
 impl ProtocolOp for RequestProtocolOp<'_> {
     fn to_tag(&self) -> u8 {
         match self {
             Self::Bind { .. } => 0,
             Self::Unbind => 2,
-            Self::Search { .. } => 3,
+            Self::Search(_) => 3,
             Self::Modify { .. } => 6,
             Self::Add => 8,
             Self::Delete => 10,
             Self::ModifyDN => 12,
             Self::Compare { .. } => 14,
-            Self::Abandon => 16,
-            Self::Extended => 23,
+            Self::Abandon { .. } => 16,
+            Self::Extended { .. } => 23,
+            Self::Raw(op) => op.first().copied().map(get_tag_number).unwrap_or_default(),
         }
     }
     fn read_from<R: Read>(_r: R) -> Result<Self, ReadProtocolOpError> {
@@ -219,30 +237,85 @@ impl ProtocolOp for RequestProtocolOp<'_> {
     }
 }
 impl RequestProtocolOp<'_> {
-    fn write_into<W: Write>(&self, mut w: W) -> std::io::Result<()> {
-        // Sequence tag
-        let req_tag = TagClass::Application.into_bits() | PrimOrCons::Constructed.into_bit() | self.to_tag();
+    /// Lower bound of the encoded size: the length of the strings and values the op carries.
+    ///
+    /// This is cheap to compute, so oversized requests can be rejected before they're encoded.
+    pub(crate) fn payload_len(&self) -> usize {
+        match self {
+            Self::Bind { name, authentication } => {
+                name.len()
+                    + match authentication {
+                        Authentication::Simple { password } => password.len(),
+                        Authentication::Sasl { credentials, .. } => {
+                            credentials.as_ref().map_or(0, |c| c.len())
+                        }
+                    }
+            }
+            Self::Search(request) => request.base_object.len(),
+            Self::Modify { object, changes } => {
+                object.len()
+                    + changes
+                        .iter()
+                        .map(|c| {
+                            c.attribute_type.len() + c.attribute_values.iter().map(|v| v.len()).sum::<usize>()
+                        })
+                        .sum::<usize>()
+            }
+            Self::Compare {
+                entry,
+                value_assertion,
+            } => entry.len() + value_assertion.attribute_desc.len() + value_assertion.assertion_value.len(),
+            Self::Extended { name, value } => name.len() + value.map_or(0, <[u8]>::len),
+            Self::Raw(op) => op.len(),
+            Self::Unbind | Self::Add | Self::Delete | Self::ModifyDN | Self::Abandon { .. } => 0,
+        }
+    }
+    fn validate(&self) -> Result<(), EncodeError> {
+        match self {
+            Self::Search(request) => request.filter.validate(),
+            Self::Compare { value_assertion, .. } if value_assertion.attribute_desc.is_empty() => {
+                Err(EncodeError::EmptyAttributeDescription)
+            }
+            Self::Modify { changes, .. } if changes.iter().any(|c| c.attribute_type.is_empty()) => {
+                Err(EncodeError::EmptyAttributeDescription)
+            }
+            Self::Extended { name, .. } if !is_numeric_oid(name) => {
+                Err(EncodeError::InvalidOid(name.to_string()))
+            }
+            Self::Raw(op)
+                if op
+                    .first()
+                    .is_none_or(|t| TagClass::from_bits(*t) != TagClass::Application) =>
+            {
+                Err(EncodeError::InvalidProtocolOp)
+            }
+            _ => Ok(()),
+        }
+    }
+    fn write_into<W: Write>(&self, mut w: W, rules: EncodingRules) -> std::io::Result<()> {
+        if let Self::Raw(op) = self {
+            return w.write_all(op);
+        }
+        let poc = match self {
+            Self::Unbind | Self::Abandon { .. } => PrimOrCons::Primitive,
+            _ => PrimOrCons::Constructed,
+        };
+        let req_tag = TagClass::Application.into_bits() | poc.into_bit() | self.to_tag();
         w.write_single_byte(req_tag)?;
         let proto_op_inner = match self {
-            Self::Bind { authentication } => bind::write_bind(authentication),
+            Self::Bind { name, authentication } => bind::write_bind(name, authentication),
             Self::Compare {
                 entry,
                 value_assertion,
             } => compare::write_compare(entry, value_assertion),
-            Self::Search {
-                entry: base_object,
-                scope,
-                deref_policy,
-                filter,
-                attributes,
-            } => search::write_search(
-                base_object,
-                *scope,
-                *deref_policy,
-                filter,
-                attributes.iter().copied(),
-            ),
-            Self::Modify { object, changes } => modify::write_modify(object, changes),
+            Self::Search(request) => search::write_search(request, rules),
+            Self::Modify { object, changes } => modify::write_modify(object, changes, rules),
+            Self::Extended { name, value } => extended::write_extended(name, *value),
+            Self::Abandon { message_id } => {
+                let mut int_b = Vec::new();
+                int_b.write_ber_integer_body(message_id.get())?;
+                int_b
+            }
             _ => todo!(),
         };
         w.write_ber_length(proto_op_inner.len())?;
@@ -251,7 +324,76 @@ impl RequestProtocolOp<'_> {
     }
 }
 
+/// Checks for the dotted-decimal `numericoid` form of RFC 4512
+pub(crate) fn is_numeric_oid(s: &str) -> bool {
+    let mut components = 0;
+    for component in s.split('.') {
+        if component.is_empty() || !component.bytes().all(|b| b.is_ascii_digit()) {
+            return false;
+        }
+        if component.len() > 1 && component.starts_with('0') {
+            return false;
+        }
+        components += 1;
+    }
+    components >= 2
+}
+
 pub trait ProtocolOp: Sized {
     fn to_tag(&self) -> u8;
     fn read_from<R: Read>(r: R) -> Result<Self, ReadProtocolOpError>;
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        ProtocolOp, ReadProtocolOpError, RequestMessage, RequestProtocolOp, ResponseProtocolOp,
+        encode_message, is_numeric_oid,
+    };
+    use std::num::NonZero;
+
+    use crate::{EncodeError, EncodingRules};
+
+    #[test]
+    fn unexpected_response() {
+        // an empty IntermediateResponse, then a primitive and a universal tag
+        for response in [[0x79, 0x00], [0x59, 0x00], [0x30, 0x00]] {
+            assert!(matches!(
+                ResponseProtocolOp::read_from(response.as_slice()),
+                Err(ReadProtocolOpError::InvalidSchema)
+            ));
+        }
+    }
+
+    #[test]
+    fn numeric_oid() {
+        assert!(is_numeric_oid("1.3.6.1.1.8"));
+        assert!(is_numeric_oid("2.5"));
+        assert!(!is_numeric_oid("1"));
+        assert!(!is_numeric_oid("1..2"));
+        assert!(!is_numeric_oid("1.02"));
+        assert!(!is_numeric_oid("cn"));
+        assert!(!is_numeric_oid(""));
+    }
+
+    #[test]
+    fn raw_protocol_op() {
+        let message = RequestMessage {
+            message_id: NonZero::new(1),
+            protocol_op: RequestProtocolOp::Raw(&[0x42, 0x00]),
+            controls: &[],
+        };
+        assert_eq!(
+            encode_message(&message, EncodingRules::Ber).unwrap(),
+            [0x30, 0x05, 0x02, 0x01, 0x01, 0x42, 0x00]
+        );
+        let message = RequestMessage {
+            protocol_op: RequestProtocolOp::Raw(&[0x04, 0x00]),
+            ..message
+        };
+        assert!(matches!(
+            encode_message(&message, EncodingRules::Ber),
+            Err(EncodeError::InvalidProtocolOp)
+        ));
+    }
+}
@@ -0,0 +1,304 @@
+//! Recording of the messages exchanged with the server, e.g. to attach to a bug report.
+//!
+//! Unlike `tracing` events, the log contains every PDU in a readable form. Passwords in binds, extended operations
+//! like the password modify operation and values of password attributes are redacted. This is best-effort:
+//! check the log for other secrets before sharing it.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::{
+    LdapConnection,
+    tag::{OCTET_STRING, PrimitiveOrConstructed, TagClass, UNIVERSAL_SEQUENCE},
+};
+
+/// Attributes whose values are never recorded
+const PASSWORD_ATTRIBUTES: &[&str] = &["userPassword", "unicodePwd", "authPassword"];
+const BIND_REQUEST: u8 = 0x60;
+const EXTENDED_REQUEST: u8 = 0x77;
+const EXTENDED_REQUEST_VALUE: u8 = 0x81;
+
+impl LdapConnection {
+    /// Starts recording the messages of this connection, keeping the `capacity` most recent ones.
+    ///
+    /// Calling this again changes the capacity without clearing the log.
+    pub fn enable_message_log(&self, capacity: usize) {
+        let mut log = self.message_log.lock().unwrap();
+        log.capacity = capacity;
+        while log.messages.len() > capacity {
+            log.messages.pop_front();
+        }
+    }
+    /// Stops recording, messages recorded so far are kept
+    pub fn disable_message_log(&self) {
+        self.message_log.lock().unwrap().capacity = 0;
+    }
+    /// The recorded messages, oldest first
+    pub fn message_log(&self) -> Vec<LoggedMessage> {
+        self.message_log
+            .lock()
+            .unwrap()
+            .messages
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// One message exchanged with the server
+#[derive(Clone, Debug)]
+pub struct LoggedMessage {
+    pub direction: Direction,
+    pub message_id: i32,
+    pub time: SystemTime,
+    /// The protocol operation and controls as a tree of BER elements,
+    /// e.g. `[APPLICATION 3] { "dc=example,dc=com", ENUMERATED 2, ... }`
+    pub pdu: String,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct MessageLog {
+    /// Zero while disabled
+    capacity: usize,
+    messages: VecDeque<LoggedMessage>,
+}
+pub(crate) type SharedMessageLog = Arc<Mutex<MessageLog>>;
+
+/// Records a message, given everything after the message ID
+pub(crate) fn record(log: &SharedMessageLog, direction: Direction, message_id: i32, body: &[u8]) {
+    let mut log = log.lock().unwrap();
+    if log.capacity == 0 {
+        return;
+    }
+    if log.messages.len() == log.capacity {
+        log.messages.pop_front();
+    }
+    log.messages.push_back(LoggedMessage {
+        direction,
+        message_id,
+        time: SystemTime::now(),
+        pdu: render_elements(body, true),
+    });
+}
+
+/// Records an encoded request, including the LDAPMessage envelope
+pub(crate) fn record_request(log: &SharedMessageLog, message_id: i32, message: &[u8]) {
+    if log.lock().unwrap().capacity == 0 {
+        return;
+    }
+    let body = split_element(message)
+        .and_then(|(_, content, _)| split_element(content))
+        .map_or(message, |(_, _, body)| body);
+    record(log, Direction::Sent, message_id, body);
+}
+
+/// Splits off the first element, returning its tag, its content and the remaining bytes
+fn split_element(bytes: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = bytes.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (length, rest) = match first {
+        0..=0x7F => (first as usize, rest),
+        0x81..=0x88 => {
+            let (length_bytes, rest) = rest.split_at_checked((first & 0x7F) as usize)?;
+            let length = length_bytes.iter().fold(0u64, |acc, b| acc << 8 | u64::from(*b));
+            (usize::try_from(length).ok()?, rest)
+        }
+        _ => return None,
+    };
+    let (content, rest) = rest.split_at_checked(length)?;
+    Some((tag, content, rest))
+}
+
+fn render_elements(mut bytes: &[u8], top_level: bool) -> String {
+    let mut out = String::new();
+    let mut first = true;
+    while !bytes.is_empty() {
+        if !first {
+            out.push_str(", ");
+        }
+        first = false;
+        let Some((tag, content, rest)) = split_element(bytes) else {
+            let _ = write!(out, "<malformed {}>", hex(bytes));
+            break;
+        };
+        render_element(&mut out, tag, content, top_level);
+        bytes = rest;
+    }
+    out
+}
+
+fn render_element(out: &mut String, tag: u8, content: &[u8], top_level: bool) {
+    match TagClass::from_bits(tag) {
+        TagClass::Universal => match tag & 0x1F {
+            0x01 => out.push_str(if content.iter().any(|b| *b != 0) {
+                "TRUE"
+            } else {
+                "FALSE"
+            }),
+            0x02 => {
+                let _ = write!(out, "INTEGER {}", integer(content));
+            }
+            0x0a => {
+                let _ = write!(out, "ENUMERATED {}", integer(content));
+            }
+            0x04 => render_octets(out, content),
+            0x05 => out.push_str("NULL"),
+            0x10 => out.push_str("SEQUENCE"),
+            0x11 => out.push_str("SET"),
+            number => {
+                let _ = write!(out, "[UNIVERSAL {number}]");
+            }
+        },
+        class => {
+            let class = match class {
+                TagClass::Application => "APPLICATION ",
+                TagClass::Private => "PRIVATE ",
+                _ => "",
+            };
+            let _ = write!(out, "[{class}{}]", tag & 0x1F);
+        }
+    }
+    if PrimitiveOrConstructed::from_bit(tag) == PrimitiveOrConstructed::Primitive {
+        // universal primitives were rendered with their value above
+        if TagClass::from_bits(tag) == TagClass::Universal {
+            return;
+        }
+        out.push(' ');
+        render_octets(out, content);
+        return;
+    }
+    out.push_str(" { ");
+    if top_level && tag == BIND_REQUEST {
+        // version, name and the authentication choice, which holds the password or SASL credentials
+        let mut rest = content;
+        for i in 0..3 {
+            let Some((tag, content, tail)) = split_element(rest) else {
+                break;
+            };
+            if i > 0 {
+                out.push_str(", ");
+            }
+            if i == 2 {
+                out.push_str("<redacted>");
+            } else {
+                render_element(out, tag, content, false);
+            }
+            rest = tail;
+        }
+    } else if top_level && tag == EXTENDED_REQUEST {
+        let mut rest = content;
+        let mut first = true;
+        while let Some((tag, content, tail)) = split_element(rest) {
+            if !first {
+                out.push_str(", ");
+            }
+            first = false;
+            if tag == EXTENDED_REQUEST_VALUE {
+                out.push_str("[1] <redacted>");
+            } else {
+                render_element(out, tag, content, false);
+            }
+            rest = tail;
+        }
+    } else if tag == UNIVERSAL_SEQUENCE
+        && let Some((OCTET_STRING, name, _)) = split_element(content)
+        && PASSWORD_ATTRIBUTES.iter().any(|a| {
+            // attribute options like ;binary don't matter
+            let name = name.split(|b| *b == b';').next().unwrap_or_default();
+            a.as_bytes().eq_ignore_ascii_case(name)
+        })
+    {
+        render_octets(out, name);
+        out.push_str(", <redacted>");
+    } else {
+        out.push_str(&render_elements(content, false));
+    }
+    out.push_str(" }");
+}
+
+fn render_octets(out: &mut String, bytes: &[u8]) {
+    match std::str::from_utf8(bytes) {
+        Ok(s) if !s.chars().any(char::is_control) => {
+            let _ = write!(out, "{s:?}");
+        }
+        _ => {
+            let _ = write!(out, "0x{}", hex(bytes));
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+            let _ = write!(s, "{b:02x}");
+            s
+        })
+}
+
+fn integer(bytes: &[u8]) -> i64 {
+    if bytes.len() > 8 {
+        return 0;
+    }
+    let sign = if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+        -1
+    } else {
+        0
+    };
+    bytes.iter().fold(sign, |acc, b| acc << 8 | i64::from(*b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Direction, SharedMessageLog, record, record_request};
+
+    #[test]
+    fn redacts_credentials() {
+        let log = SharedMessageLog::default();
+        log.lock().unwrap().capacity = 2;
+        // simple bind of cn=a with password "secret"
+        let bind = [
+            0x30, 0x16, 0x02, 0x01, 0x01, 0x60, 0x11, 0x02, 0x01, 0x03, 0x04, 0x04, b'c', b'n', b'=', b'a',
+            0x80, 0x06, b's', b'e', b'c', b'r', b'e', b't',
+        ];
+        record_request(&log, 1, &bind);
+        // modify replacing userPassword
+        let modify = [
+            0x66, 0x24, 0x04, 0x04, b'c', b'n', b'=', b'a', 0x30, 0x1c, 0x30, 0x1a, 0x0a, 0x01, 0x02, 0x30,
+            0x15, 0x04, 0x0c, b'u', b's', b'e', b'r', b'P', b'a', b's', b's', b'w', b'o', b'r', b'd', 0x31,
+            0x05, 0x04, 0x03, b'p', b'w', b'd',
+        ];
+        record(&log, Direction::Sent, 2, &modify);
+        // evicts the bind
+        record(
+            &log,
+            Direction::Received,
+            2,
+            &[0x67, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00],
+        );
+
+        let messages: Vec<_> = log.lock().unwrap().messages.iter().cloned().collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].pdu,
+            r#"[APPLICATION 6] { "cn=a", SEQUENCE { SEQUENCE { ENUMERATED 2, SEQUENCE { "userPassword", <redacted> } } } }"#
+        );
+        assert_eq!(messages[1].pdu, r#"[APPLICATION 7] { ENUMERATED 0, "", "" }"#);
+
+        let log = SharedMessageLog::default();
+        log.lock().unwrap().capacity = 1;
+        record_request(&log, 1, &bind);
+        let pdu = &log.lock().unwrap().messages[0].pdu;
+        assert_eq!(pdu, r#"[APPLICATION 0] { INTEGER 3, "cn=a", <redacted> }"#);
+    }
+}
@@ -1,10 +1,12 @@
 use std::{
     fmt::{Display, Formatter, Result as FmtResult},
     io::Read,
+    num::{NonZeroI8, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64},
 };
 
 use crate::{
-    LdapConnection, ResponseProtocolOp, WriteExt,
+    EncodeError, EncodingRules, LdapConnection, ResponseProtocolOp, SendMessageError, WriteExt,
+    control::Control,
     length::{LengthError, read_length},
     message::{ProtocolOp, ReadProtocolOpError, RequestProtocolOp},
     parse::{ParseLdap, ReadIntegerError},
@@ -15,10 +17,19 @@ use crate::{
 
 impl LdapConnection {
     pub async fn modify(&mut self, object: &str, changes: &[Change<'_>]) -> Result<(), ModifyError> {
+        self.modify_with_controls(object, changes, &[]).await
+    }
+    /// Like [`LdapConnection::modify`], but attaches request controls, e.g. [`Control::relax_rules`],
+    /// or [`Control::no_op`] for a dry run.
+    pub async fn modify_with_controls(
+        &mut self,
+        object: &str,
+        changes: &[Change<'_>],
+        controls: &[Control],
+    ) -> Result<(), ModifyError> {
         let response = self
-            .send_message(RequestProtocolOp::Modify { object, changes })
-            .await
-            .unwrap()
+            .send_message_with_controls(RequestProtocolOp::Modify { object, changes }, controls)
+            .await?
             .into_message();
         let ResponseProtocolOp::Modify = ResponseProtocolOp::read_from(&mut response.as_slice())? else {
             return Err(ModifyError::InvalidSchema);
@@ -26,7 +37,7 @@ impl LdapConnection {
         read_response(&mut response.as_slice()).map_err(|err| match err {
             ReadModifyError::InvalidSchema => ModifyError::InvalidSchema,
             ReadModifyError::Io(error) => ModifyError::Io(error),
-            ReadModifyError::ServerError { code, message } => ModifyError::ServerError { code, message },
+            ReadModifyError::ServerError { code, message } => ModifyError::server_error(code, message),
         })?;
         Ok(())
     }
@@ -35,15 +46,42 @@ impl LdapConnection {
 #[derive(Debug)]
 pub enum ModifyError {
     Io(std::io::Error),
+    Encode(EncodeError),
     InvalidSchema,
     Disconnected,
-    ServerError { code: ResultCode, message: String },
+    ServerError {
+        code: ResultCode,
+        message: String,
+    },
+    /// The server doesn't support a critical control of the request
+    UnavailableCriticalExtension(String),
+}
+impl ModifyError {
+    fn server_error(code: ResultCode, message: String) -> Self {
+        match code {
+            ResultCode::UnavailableCriticalExtension => Self::UnavailableCriticalExtension(message),
+            code => Self::ServerError { code, message },
+        }
+    }
+}
+impl From<SendMessageError> for ModifyError {
+    fn from(value: SendMessageError) -> Self {
+        match value {
+            SendMessageError::Io(error) => Self::Io(error),
+            SendMessageError::Encode(e) => Self::Encode(e),
+            SendMessageError::ChannelClosed | SendMessageError::ReceiveMessage(_) => Self::Disconnected,
+        }
+    }
 }
 impl From<ReadProtocolOpError> for ModifyError {
     fn from(value: ReadProtocolOpError) -> Self {
         match value {
             ReadProtocolOpError::Io(error) => Self::Io(error),
-            ReadProtocolOpError::ServerError { code, message } => Self::ServerError { code, message },
+            ReadProtocolOpError::ServerError { code, message } => Self::server_error(code, message),
+            ReadProtocolOpError::Referral(urls) => Self::ServerError {
+                code: ResultCode::Referral,
+                message: urls.join(" "),
+            },
             ReadProtocolOpError::InvalidSchema => Self::InvalidSchema,
         }
     }
@@ -52,7 +90,11 @@ impl std::error::Error for ModifyError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::Io(io) => Some(io),
-            Self::Disconnected | Self::InvalidSchema | Self::ServerError { .. } => None,
+            Self::Encode(e) => Some(e),
+            Self::Disconnected
+            | Self::InvalidSchema
+            | Self::ServerError { .. }
+            | Self::UnavailableCriticalExtension(_) => None,
         }
     }
 }
@@ -60,21 +102,37 @@ impl Display for ModifyError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match self {
             Self::Io(error) => write!(f, "An IO error occured: {error}"),
+            Self::Encode(e) => write!(f, "Failed to encode request: {e}"),
             Self::InvalidSchema => write!(f, "Server returned an invalid message"),
             Self::Disconnected => write!(f, "Connection disconnected"),
             Self::ServerError { code, message } => {
-                write!(f, "Server returned an error. Code: {code:?} (\"{message}\")",)
+                write!(f, "Server returned an error. Code: {code} (\"{message}\")",)
+            }
+            Self::UnavailableCriticalExtension(message) => {
+                write!(
+                    f,
+                    "Server does not support a critical control of the request (\"{message}\")"
+                )
             }
         }
     }
 }
 
+/// Feature OID servers supporting [`Operation::Increment`] list in the root DSE's `supportedFeatures`
+pub const MODIFY_INCREMENT_OID: &str = "1.3.6.1.1.14";
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum Operation {
     Add,
     Delete,
     Replace,
+    /// Adds the single value of the change to the attribute's integer value (RFC 4525), e.g. to allocate the next
+    /// `uidNumber` without a race between reading and writing it. Negative values decrement.
+    ///
+    /// Servers without support reject it, usually with `ProtocolError` or `UnwillingToPerform`, check
+    /// [`MODIFY_INCREMENT_OID`] beforehand. Attributes that aren't integers fail with `ConstraintViolation`.
+    Increment,
 }
 impl Operation {
     pub fn to_int(self) -> u8 {
@@ -82,6 +140,7 @@ impl Operation {
             Operation::Add => 0,
             Operation::Delete => 1,
             Operation::Replace => 2,
+            Operation::Increment => 3,
         }
     }
     pub fn from_int(u: u8) -> Option<Self> {
@@ -89,6 +148,7 @@ impl Operation {
             0 => Some(Self::Add),
             1 => Some(Self::Delete),
             2 => Some(Self::Replace),
+            3 => Some(Self::Increment),
             _ => None,
         }
     }
@@ -108,7 +168,106 @@ pub struct Change<'c> {
     pub attribute_values: &'c [&'c [u8]],
 }
 
-pub(crate) fn write_modify(object: &str, changes: &[Change]) -> Vec<u8> {
+/// Encodes a value for an attribute, the counterpart of [`FromOctetString`](crate::search::FromOctetString)
+/// for writing entries.
+///
+/// Integers are written in decimal and booleans as `TRUE`/`FALSE`, like LDAP's `INTEGER` and `Boolean` syntaxes.
+pub trait ToOctetString {
+    fn to_octet_string(&self) -> Vec<u8>;
+}
+impl<T: ToOctetString + ?Sized> ToOctetString for &T {
+    fn to_octet_string(&self) -> Vec<u8> {
+        T::to_octet_string(self)
+    }
+}
+impl ToOctetString for str {
+    fn to_octet_string(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+impl ToOctetString for String {
+    fn to_octet_string(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+impl ToOctetString for [u8] {
+    fn to_octet_string(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+impl<const N: usize> ToOctetString for [u8; N] {
+    fn to_octet_string(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+impl ToOctetString for Vec<u8> {
+    fn to_octet_string(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+impl ToOctetString for bool {
+    fn to_octet_string(&self) -> Vec<u8> {
+        match self {
+            true => b"TRUE".to_vec(),
+            false => b"FALSE".to_vec(),
+        }
+    }
+}
+macro_rules! to_octet_for_integer {
+    ($($t:ty),*) => {
+        $(
+            impl ToOctetString for $t {
+                fn to_octet_string(&self) -> Vec<u8> {
+                    self.to_string().into_bytes()
+                }
+            }
+        )*
+    };
+}
+to_octet_for_integer!(u8, u16, u32, u64, i8, i16, i32, i64);
+to_octet_for_integer!(
+    NonZeroI8, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64
+);
+
+/// Builds the values of a [`Change`] from typed values:
+///
+/// ```
+/// use lapdog::modify::{AttributeValues, Change, Operation};
+///
+/// let values = AttributeValues::new().push(42u32).push("foo").push(true);
+/// let slices = values.as_slices();
+/// let change = Change {
+///     operation: Operation::Replace,
+///     attribute_type: "description",
+///     attribute_values: &slices,
+/// };
+/// assert_eq!(change.attribute_values, [&b"42"[..], b"foo", b"TRUE"]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttributeValues(Vec<Vec<u8>>);
+impl AttributeValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn push(mut self, value: impl ToOctetString) -> Self {
+        self.0.push(value.to_octet_string());
+        self
+    }
+    /// The values borrowed as [`Change::attribute_values`] expects them
+    pub fn as_slices(&self) -> Vec<&[u8]> {
+        self.0.iter().map(Vec::as_slice).collect()
+    }
+    pub fn into_vec(self) -> Vec<Vec<u8>> {
+        self.0
+    }
+}
+impl<T: ToOctetString> FromIterator<T> for AttributeValues {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self(iter.into_iter().map(|v| v.to_octet_string()).collect())
+    }
+}
+
+pub(crate) fn write_modify(object: &str, changes: &[Change], rules: EncodingRules) -> Vec<u8> {
     let mut msg_sequence = Vec::new();
     msg_sequence.push(OCTET_STRING);
     msg_sequence.write_ber_length(object.len()).expect("infallible");
@@ -127,13 +286,21 @@ pub(crate) fn write_modify(object: &str, changes: &[Change]) -> Vec<u8> {
                         modification.extend_from_slice(change.attribute_type.as_bytes());
 
                         modification.write_sequence(UNIVERSAL_SET, |partial_attr_values| {
-                            for val in change.attribute_values {
-                                partial_attr_values.push(OCTET_STRING);
-                                partial_attr_values
-                                    .write_ber_length(val.len())
-                                    .expect("infallible");
-                                partial_attr_values.extend_from_slice(val);
+                            let mut values: Vec<Vec<u8>> = change
+                                .attribute_values
+                                .iter()
+                                .map(|val| {
+                                    let mut encoded = vec![OCTET_STRING];
+                                    encoded.write_ber_length(val.len()).expect("infallible");
+                                    encoded.extend_from_slice(val);
+                                    encoded
+                                })
+                                .collect();
+                            // DER requires SET OF elements in ascending order of their encodings
+                            if rules == EncodingRules::Der {
+                                values.sort();
                             }
+                            values.into_iter().for_each(|v| partial_attr_values.extend(v));
                             Ok(())
                         })?;
                         Ok(())
@@ -156,7 +323,7 @@ pub(crate) fn read_response<R: Read>(mut r: R) -> Result<(), ReadModifyError> {
     let code = code
         .try_into()
         .ok()
-        .and_then(ResultCode::from_code)
+        .and_then(ResultCode::from_u16)
         .ok_or(ReadModifyError::InvalidSchema)?;
     if let ResultCode::Success = code {
         return Ok(());
@@ -209,7 +376,51 @@ impl From<ReadIntegerError> for ReadModifyError {
     fn from(value: ReadIntegerError) -> Self {
         match value {
             ReadIntegerError::Io(error) => Self::Io(error),
-            ReadIntegerError::Length(_) | ReadIntegerError::OutOfRange => Self::InvalidSchema,
+            ReadIntegerError::Length | ReadIntegerError::OutOfRange => Self::InvalidSchema,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        EncodingRules,
+        modify::{AttributeValues, Change, Operation, write_modify},
+    };
+
+    #[test]
+    fn der_sorts_values() {
+        let changes = [Change {
+            operation: Operation::Replace,
+            attribute_type: "cn",
+            attribute_values: &[b"bb", b"a", b"ab"],
+        }];
+        let ber = write_modify("", &changes, EncodingRules::Ber);
+        let der = write_modify("", &changes, EncodingRules::Der);
+        assert!(ber.ends_with(&[0x04, 0x02, b'b', b'b', 0x04, 0x01, b'a', 0x04, 0x02, b'a', b'b']));
+        assert!(der.ends_with(&[0x04, 0x01, b'a', 0x04, 0x02, b'a', b'b', 0x04, 0x02, b'b', b'b']));
+    }
+
+    #[test]
+    fn increment() {
+        let changes = [Change {
+            operation: Operation::Increment,
+            attribute_type: "uidNumber",
+            attribute_values: &[b"1"],
+        }];
+        let encoded = write_modify("", &changes, EncodingRules::Ber);
+        assert!(encoded.windows(3).any(|w| w == [0x0a, 0x01, 0x03]));
+        assert_eq!(Operation::from_int(3), Some(Operation::Increment));
+    }
+
+    #[test]
+    fn typed_values() {
+        let values: AttributeValues = [-1i32, 7].into_iter().collect();
+        assert_eq!(values.into_vec(), [b"-1".to_vec(), b"7".to_vec()]);
+        let values = AttributeValues::new()
+            .push(false)
+            .push(String::from("bob"))
+            .push(&[0u8, 1][..]);
+        assert_eq!(values.as_slices(), [&b"FALSE"[..], b"bob", &[0, 1]]);
+    }
+}
@@ -0,0 +1,157 @@
+//! Typed operational attributes, which the server maintains for every entry.
+//!
+//! Servers only return operational attributes that are requested by name, or all of them for the special
+//! attribute `+` (RFC 3673); `*` and an empty attribute list only return user attributes.
+//! A struct deriving `Entry` requests its fields by name, so a field like
+//! `#[lapdog(rename = "entryUUID")] entry_uuid: String` just works.
+//!
+//! To read them next to another type, search for [`RawEntry`] with that type's attributes and
+//! [`OperationalAttrs::ATTRIBUTES`], then parse the entry twice:
+//!
+//! ```no_run
+//! # async fn run(connection: &lapdog::LdapConnection) -> Result<(), lapdog::LapdogError> {
+//! use lapdog::operational::OperationalAttrs;
+//! use lapdog::search::{DerefPolicy, Filter, FromEntry, FromEntryRef, RawEntry, Scope, SearchResult};
+//!
+//! let mut results = connection
+//!     .search(
+//!         "dc=example,dc=com",
+//!         Scope::WholeSubtree,
+//!         DerefPolicy::Never,
+//!         Filter::equal("uid", "bob"),
+//!         ["cn", "mail"].into_iter().chain(OperationalAttrs::ATTRIBUTES.iter().copied()),
+//!     )
+//!     .await?;
+//! while let Some(result) = results.next().await {
+//!     if let SearchResult::Entry(entry) = result? {
+//!         let operational = OperationalAttrs::from_entry_ref(&entry)?;
+//!         // or a derived struct
+//!         let entry = RawEntry::from_entry(entry)?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::error::Error;
+
+use crate::search::{FailedToGetFromEntry, FromEntry, FromEntryRef, FromOctetString, RawEntry};
+
+/// Common operational attributes. Each is `None` if the server didn't return it, e.g. because it doesn't support it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OperationalAttrs {
+    /// `entryDN` (RFC 5020), the entry's own DN, useful in filters and for servers that rewrite the object name
+    pub entry_dn: Option<String>,
+    /// `hasSubordinates`, whether the entry has children
+    pub has_subordinates: Option<bool>,
+    /// `subordinateCount`, or `numSubordinates` on 389 Directory Server and others: the number of direct children
+    pub subordinate_count: Option<u64>,
+    /// `entryUUID` (RFC 4530) in its text form. Parse it into `uuid::Uuid` with the `uuid` feature.
+    /// Active Directory has the binary `objectGUID` instead.
+    pub entry_uuid: Option<String>,
+    /// `creatorsName`, the DN that created the entry
+    pub creators_name: Option<String>,
+    /// `modifiersName`, the DN that last modified the entry
+    pub modifiers_name: Option<String>,
+    /// `createTimestamp` as GeneralizedTime, e.g. `20240131120000Z`
+    pub create_timestamp: Option<String>,
+    /// `modifyTimestamp` as GeneralizedTime
+    pub modify_timestamp: Option<String>,
+}
+impl OperationalAttrs {
+    /// The attributes to request to fill all fields
+    pub const ATTRIBUTES: &[&str] = &[
+        "entryDN",
+        "hasSubordinates",
+        "subordinateCount",
+        "numSubordinates",
+        "entryUUID",
+        "creatorsName",
+        "modifiersName",
+        "createTimestamp",
+        "modifyTimestamp",
+    ];
+}
+impl<'a> FromEntryRef<'a> for OperationalAttrs {
+    fn from_entry_ref(entry: &'a RawEntry) -> Result<Self, FailedToGetFromEntry> {
+        Ok(Self {
+            entry_dn: parse(entry, "entryDN")?,
+            has_subordinates: parse(entry, "hasSubordinates")?,
+            subordinate_count: match parse(entry, "subordinateCount")? {
+                Some(count) => Some(count),
+                None => parse(entry, "numSubordinates")?,
+            },
+            entry_uuid: parse(entry, "entryUUID")?,
+            creators_name: parse(entry, "creatorsName")?,
+            modifiers_name: parse(entry, "modifiersName")?,
+            create_timestamp: parse(entry, "createTimestamp")?,
+            modify_timestamp: parse(entry, "modifyTimestamp")?,
+        })
+    }
+    fn attributes() -> Option<impl Iterator<Item = &'static str>> {
+        Some(Self::ATTRIBUTES.iter().copied())
+    }
+}
+impl FromEntry for OperationalAttrs {
+    fn from_entry(entry: RawEntry) -> Result<Self, FailedToGetFromEntry> {
+        Self::from_entry_ref(&entry)
+    }
+    fn attributes() -> Option<impl Iterator<Item = &'static str>> {
+        Some(Self::ATTRIBUTES.iter().copied())
+    }
+}
+
+fn parse<T>(entry: &RawEntry, name: &'static str) -> Result<Option<T>, FailedToGetFromEntry>
+where
+    T: FromOctetString,
+    T::Err: Send + 'static,
+{
+    match entry.get_all(name) {
+        [] => Ok(None),
+        [value] => T::from_octet_string(value).map(Some).map_err(|e| {
+            FailedToGetFromEntry::FailedToParseField(name, Box::new(e) as Box<dyn Error + Send>)
+        }),
+        _ => Err(FailedToGetFromEntry::TooManyValues(name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OperationalAttrs;
+    use crate::search::{Attribute, FailedToGetFromEntry, FromEntry, RawEntry};
+
+    fn entry(attributes: &[(&str, &str)]) -> RawEntry {
+        RawEntry {
+            object_name: "cn=a,dc=x".to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| Attribute {
+                    r#type: name.to_string(),
+                    values: vec![value.as_bytes().to_vec()],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn parse_operational() {
+        let attrs = OperationalAttrs::from_entry(entry(&[
+            ("entryDN", "cn=a,dc=x"),
+            ("hasSubordinates", "TRUE"),
+            ("numSubordinates", "3"),
+            ("entryuuid", "597ae2f6-16a6-1027-98f4-d28b5365dc14"),
+        ]))
+        .unwrap();
+        assert_eq!(attrs.entry_dn.as_deref(), Some("cn=a,dc=x"));
+        assert_eq!(attrs.has_subordinates, Some(true));
+        assert_eq!(attrs.subordinate_count, Some(3));
+        assert!(attrs.entry_uuid.is_some());
+        assert_eq!(attrs.creators_name, None);
+
+        let invalid = OperationalAttrs::from_entry(entry(&[("hasSubordinates", "yes")]));
+        assert!(matches!(
+            invalid,
+            Err(FailedToGetFromEntry::FailedToParseField("hasSubordinates", _))
+        ));
+    }
+}
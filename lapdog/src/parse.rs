@@ -7,7 +7,10 @@ mod integer;
 pub trait ParseLdap: ReadExt {
     fn read_as_tag_integer(&mut self) -> Result<(u8, i32), ReadIntegerError> {
         let tag = self.read_single_byte().map_err(ReadIntegerError::Io)?;
-        let length = self.read_ber_length().map_err(ReadIntegerError::Length)?;
+        let length = self.read_ber_length().map_err(|e| match e {
+            LengthError::Io(e) => ReadIntegerError::Io(e),
+            LengthError::OutOfRange | LengthError::Unbounded => ReadIntegerError::Length,
+        })?;
         if length > 6 {
             return Err(ReadIntegerError::OutOfRange);
         }
@@ -41,6 +44,7 @@ impl<T: Read> ParseLdap for T {}
 #[derive(Debug)]
 pub enum ReadIntegerError {
     Io(std::io::Error),
-    Length(LengthError),
+    /// The length of the integer couldn't be read
+    Length,
     OutOfRange,
 }
@@ -0,0 +1,31 @@
+//! The types most programs need, to import them all at once:
+//!
+//! ```no_run
+//! use lapdog::prelude::*;
+//!
+//! # async fn run() -> Result<(), LapdogError> {
+//! let connection = LdapConnection::new(("ldap.example.com", LDAP_PORT), &StreamConfig::default()).await?;
+//! let entry = connection
+//!     .search_one::<RawEntry>(
+//!         "dc=example,dc=com",
+//!         Scope::WholeSubtree,
+//!         DerefPolicy::Never,
+//!         Filter::equal("uid", "bob"),
+//!     )
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only what's needed to connect, search, compare and modify is included; the more specialized types stay in their
+//! modules.
+
+#[cfg(feature = "derive")]
+pub use crate::search::Entry;
+pub use crate::{
+    LDAP_PORT, LDAPS_PORT, LapdogError, LdapConnection, ResultCode, StreamConfig,
+    attribute::AttributeValueAssertion,
+    control::Control,
+    modify::{Change, Operation},
+    search::{DerefPolicy, Filter, FromEntry, RawEntry, Scope, SearchResult},
+};
@@ -0,0 +1,76 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::{
+    EncodeError, IncomingMessage, LdapConnection, SearchEnd, SendMessageError, control::Control,
+    message::RequestProtocolOp, tag::get_tag_number,
+};
+
+impl LdapConnection {
+    /// Sends an already encoded protocol op, e.g. `[0x42, 0x00]` for an unbind, and returns the raw responses.
+    ///
+    /// This is the escape hatch for operations lapdog doesn't model and for protocol debugging, prefer the typed
+    /// methods otherwise. Each response is the BER encoded protocol op, followed by the `[0]` controls if the server
+    /// sent any. Searches return all responses up to and including the `SearchResultDone`, abandon and unbind
+    /// return none. Result codes are not checked.
+    pub async fn request_raw(
+        &self,
+        protocol_op: &[u8],
+        controls: &[Control],
+    ) -> Result<Vec<Vec<u8>>, RawRequestError> {
+        let incoming = self
+            .send_message_with_controls(RequestProtocolOp::Raw(protocol_op), controls)
+            .await?;
+        match incoming {
+            IncomingMessage::NoResponse => Ok(Vec::new()),
+            IncomingMessage::Message(response) => Ok(vec![response]),
+            IncomingMessage::MessageReceiver(_, mut rx, done) => {
+                let mut responses = Vec::new();
+                loop {
+                    let Some(Ok(response)) = rx.recv().await else {
+                        return Err(RawRequestError::Disconnected);
+                    };
+                    let is_done = response.first().copied().map(get_tag_number) == Some(5);
+                    responses.push(response);
+                    if is_done {
+                        let _ = done.send(SearchEnd::Finished);
+                        return Ok(responses);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RawRequestError {
+    Io(std::io::Error),
+    Encode(EncodeError),
+    Disconnected,
+}
+impl From<SendMessageError> for RawRequestError {
+    fn from(value: SendMessageError) -> Self {
+        match value {
+            SendMessageError::Io(error) => Self::Io(error),
+            SendMessageError::Encode(e) => Self::Encode(e),
+            SendMessageError::ChannelClosed | SendMessageError::ReceiveMessage(_) => Self::Disconnected,
+        }
+    }
+}
+impl std::error::Error for RawRequestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(io) => Some(io),
+            Self::Encode(e) => Some(e),
+            Self::Disconnected => None,
+        }
+    }
+}
+impl Display for RawRequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Io(error) => write!(f, "An IO error occured: {error}"),
+            Self::Encode(e) => write!(f, "Failed to encode request: {e}"),
+            Self::Disconnected => write!(f, "Connection disconnected"),
+        }
+    }
+}
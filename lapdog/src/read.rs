@@ -11,6 +11,7 @@ pub(crate) trait ReadExt: Read {
 }
 impl<R: Read> ReadExt for R {}
 
+#[cfg(feature = "kerberos")]
 pub(crate) trait ReadLdap: ReadExt {
     /// Returns logical length of object, and then number of read bytes
     fn read_length(&mut self) -> std::io::Result<(Option<usize>, usize)> {
@@ -30,6 +31,7 @@ pub(crate) trait ReadLdap: ReadExt {
         }
     }
 }
+#[cfg(feature = "kerberos")]
 impl<T: Read> ReadLdap for T {}
 
 pub(crate) trait AsyncReadLdap: AsyncReadExt + Unpin {
@@ -1,4 +1,10 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// The result code of an operation.
+///
+/// Servers and extensions define further codes, so new variants may be added in any release.
 #[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
 pub enum ResultCode {
     Success,
     OperationsError,
@@ -39,10 +45,22 @@ pub enum ResultCode {
     ObjectClassModsProhibited,
     AffectsMultipleDSAs,
     Other,
+    Canceled,
+    NoSuchOperation,
+    TooLate,
+    CannotCancel,
+    /// OpenLDAP's `x-noOperation`: the operation would have succeeded, but wasn't applied because of
+    /// [`Control::no_op`](crate::control::Control::no_op)
+    NoOperation,
 }
 
 impl ResultCode {
+    /// The result code with the number `code`, `None` for unknown ones. See `TryFrom<u16>` for codes that don't
+    /// fit into a `u8`, like [`ResultCode::NoOperation`].
     pub fn from_code(code: u8) -> Option<Self> {
+        Self::from_u16(code.into())
+    }
+    pub(crate) fn from_u16(code: u16) -> Option<Self> {
         match code {
             0 => Some(Self::Success),
             1 => Some(Self::OperationsError),
@@ -92,7 +110,150 @@ impl ResultCode {
 
             80 => Some(Self::Other),
 
+            118 => Some(Self::Canceled),
+            119 => Some(Self::NoSuchOperation),
+            120 => Some(Self::TooLate),
+            121 => Some(Self::CannotCancel),
+
+            0x410e => Some(Self::NoOperation),
+
             _ => None,
         }
     }
+    pub fn code(self) -> u16 {
+        match self {
+            Self::Success => 0,
+            Self::OperationsError => 1,
+            Self::ProtocolError => 2,
+            Self::TimeLimitExceeded => 3,
+            Self::SizeLimitExceeded => 4,
+            Self::CompareFalse => 5,
+            Self::CompareTrue => 6,
+            Self::AuthMethodNotSupported => 7,
+            Self::StrongerAuthRequired => 8,
+            Self::Referral => 10,
+            Self::AdminLimitExceeded => 11,
+            Self::UnavailableCriticalExtension => 12,
+            Self::ConfidentialityRequired => 13,
+            Self::SaslBindInProgress => 14,
+            Self::NoSuchAttribute => 16,
+            Self::UndefinedAttributeType => 17,
+            Self::InappropriateMatching => 18,
+            Self::ConstraintViolation => 19,
+            Self::AttributeOrValueExists => 20,
+            Self::InvalidAttributeSyntax => 21,
+            Self::NoSuchObject => 32,
+            Self::AliasProblem => 33,
+            Self::InvalidDNSyntax => 34,
+            Self::AliasDereferencingProblem => 36,
+            Self::InappropriateAuthentication => 48,
+            Self::InvalidCredentials => 49,
+            Self::InsufficientAccessRights => 50,
+            Self::Busy => 51,
+            Self::Unavailable => 52,
+            Self::UnwillingToPerform => 53,
+            Self::LoopDetect => 54,
+            Self::NamingViolation => 64,
+            Self::ObjectClassViolation => 65,
+            Self::NotAllowedOnNonLeaf => 66,
+            Self::NotAllowedOnRDN => 67,
+            Self::EntryAlreadyExists => 68,
+            Self::ObjectClassModsProhibited => 69,
+            Self::AffectsMultipleDSAs => 71,
+            Self::Other => 80,
+            Self::Canceled => 118,
+            Self::NoSuchOperation => 119,
+            Self::TooLate => 120,
+            Self::CannotCancel => 121,
+            Self::NoOperation => 0x410e,
+        }
+    }
+    /// The name RFC 4511 and its extensions give the code, e.g. `unwillingToPerform`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::OperationsError => "operationsError",
+            Self::ProtocolError => "protocolError",
+            Self::TimeLimitExceeded => "timeLimitExceeded",
+            Self::SizeLimitExceeded => "sizeLimitExceeded",
+            Self::CompareFalse => "compareFalse",
+            Self::CompareTrue => "compareTrue",
+            Self::AuthMethodNotSupported => "authMethodNotSupported",
+            Self::StrongerAuthRequired => "strongerAuthRequired",
+            Self::Referral => "referral",
+            Self::AdminLimitExceeded => "adminLimitExceeded",
+            Self::UnavailableCriticalExtension => "unavailableCriticalExtension",
+            Self::ConfidentialityRequired => "confidentialityRequired",
+            Self::SaslBindInProgress => "saslBindInProgress",
+            Self::NoSuchAttribute => "noSuchAttribute",
+            Self::UndefinedAttributeType => "undefinedAttributeType",
+            Self::InappropriateMatching => "inappropriateMatching",
+            Self::ConstraintViolation => "constraintViolation",
+            Self::AttributeOrValueExists => "attributeOrValueExists",
+            Self::InvalidAttributeSyntax => "invalidAttributeSyntax",
+            Self::NoSuchObject => "noSuchObject",
+            Self::AliasProblem => "aliasProblem",
+            Self::InvalidDNSyntax => "invalidDNSyntax",
+            Self::AliasDereferencingProblem => "aliasDereferencingProblem",
+            Self::InappropriateAuthentication => "inappropriateAuthentication",
+            Self::InvalidCredentials => "invalidCredentials",
+            Self::InsufficientAccessRights => "insufficientAccessRights",
+            Self::Busy => "busy",
+            Self::Unavailable => "unavailable",
+            Self::UnwillingToPerform => "unwillingToPerform",
+            Self::LoopDetect => "loopDetect",
+            Self::NamingViolation => "namingViolation",
+            Self::ObjectClassViolation => "objectClassViolation",
+            Self::NotAllowedOnNonLeaf => "notAllowedOnNonLeaf",
+            Self::NotAllowedOnRDN => "notAllowedOnRDN",
+            Self::EntryAlreadyExists => "entryAlreadyExists",
+            Self::ObjectClassModsProhibited => "objectClassModsProhibited",
+            Self::AffectsMultipleDSAs => "affectsMultipleDSAs",
+            Self::Other => "other",
+            Self::Canceled => "canceled",
+            Self::NoSuchOperation => "noSuchOperation",
+            Self::TooLate => "tooLate",
+            Self::CannotCancel => "cannotCancel",
+            Self::NoOperation => "x-noOperation",
+        }
+    }
+}
+/// Looks up any result code, including vendor codes above 255. Unknown codes are handed back.
+impl TryFrom<u16> for ResultCode {
+    type Error = u16;
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        Self::from_u16(code).ok_or(code)
+    }
+}
+/// The numeric code followed by its name, e.g. `53 (unwillingToPerform)`
+impl Display for ResultCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{} ({})", self.code(), self.name())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ResultCode;
+
+    #[test]
+    fn codes_round_trip() {
+        for code in 0..=u16::MAX {
+            if let Ok(result_code) = ResultCode::try_from(code) {
+                assert_eq!(result_code.code(), code);
+            }
+        }
+        for code in 0..=u8::MAX {
+            assert_eq!(
+                ResultCode::from_code(code).map(ResultCode::code),
+                ResultCode::try_from(u16::from(code)).ok().map(ResultCode::code)
+            );
+        }
+        assert_eq!(
+            ResultCode::UnwillingToPerform.to_string(),
+            "53 (unwillingToPerform)"
+        );
+        assert!(matches!(ResultCode::try_from(16654), Ok(ResultCode::NoOperation)));
+        assert!(matches!(ResultCode::try_from(9999), Err(9999)));
+    }
 }
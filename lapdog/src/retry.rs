@@ -0,0 +1,364 @@
+//! Retrying reads after transient failures, like a server restarting or a load balancer resetting the connection.
+//!
+//! Only reads are retried: searches and compares can run any number of times with the same outcome.
+//! Writes like modifies are not, as a request that failed on the way back may well have been applied already,
+//! so retrying it could apply it twice. Use [`RetryingConnection::connection`] for them and handle errors yourself.
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::ErrorKind,
+    time::Duration,
+};
+
+use crate::{
+    LapdogError, LdapConnection,
+    attribute::AttributeValueAssertion,
+    compare::CompareError,
+    result::ResultCode,
+    search::{
+        BeginSearchError, CollectError, CountError, DerefPolicy, Filter, FromEntry, Scope, SearchOneError,
+        SearchResultError, collect,
+    },
+};
+
+/// How often and how fast to retry
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one, so 1 disables retrying
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for every further one
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+impl RetryPolicy {
+    /// Wait before retry number `retry`, starting at 0
+    fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+/// A connection that reconnects and retries reads which failed with a transient error.
+///
+/// `connect` opens and binds a new connection. It's called once on creation and again before every retry,
+/// so it should hold everything needed to reach the same identity, like the address, TLS config and credentials:
+///
+#[cfg_attr(any(feature = "native-tls", feature = "rustls"), doc = "```no_run")]
+#[cfg_attr(not(any(feature = "native-tls", feature = "rustls")), doc = "```ignore")]
+/// # async fn run(config: &lapdog::StreamConfig) -> Result<(), lapdog::LapdogError> {
+/// use lapdog::{LDAPS_PORT, TlsConnection, retry::{RetryPolicy, RetryingConnection}};
+/// use lapdog::search::{DerefPolicy, Filter, RawEntry, Scope};
+///
+/// // config is a TLS config, e.g. from StreamConfig::native_tls("ldap.example.com")
+/// let mut connection = RetryingConnection::new(
+///     move || async move {
+///         let mut connection = TlsConnection::new(("ldap.example.com", LDAPS_PORT), config).await?;
+///         connection.bind_simple("cn=reader,dc=example,dc=com", b"secret").await?;
+///         Ok(connection.into_inner())
+///     },
+///     RetryPolicy::default(),
+/// )
+/// .await?;
+/// let user = connection
+///     .search_one::<RawEntry>("dc=example,dc=com", Scope::WholeSubtree, DerefPolicy::Never, &Filter::equal("uid", "bob"))
+///     .await;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Retried are results `busy` and `unavailable` as well as lost connections.
+pub struct RetryingConnection<C> {
+    connection: LdapConnection,
+    connect: C,
+    policy: RetryPolicy,
+}
+impl<C, F> RetryingConnection<C>
+where
+    C: FnMut() -> F,
+    F: Future<Output = Result<LdapConnection, LapdogError>>,
+{
+    pub async fn new(mut connect: C, policy: RetryPolicy) -> Result<Self, LapdogError> {
+        let connection = connect().await?;
+        Ok(Self {
+            connection,
+            connect,
+            policy,
+        })
+    }
+    /// The current connection, e.g. for writes, which are never retried
+    pub fn connection(&mut self) -> &mut LdapConnection {
+        &mut self.connection
+    }
+    /// [`LdapConnection::search_one`], retried
+    pub async fn search_one<Output: FromEntry>(
+        &mut self,
+        base_object: &str,
+        scope: Scope,
+        deref_policy: DerefPolicy,
+        filter: &Filter<'_>,
+    ) -> Result<Option<Output>, RetryError<SearchOneError>> {
+        let mut retry = 0;
+        loop {
+            let result = self
+                .connection
+                .search_one(base_object, scope, deref_policy, filter.clone())
+                .await;
+            match result {
+                Ok(entry) => return Ok(entry),
+                Err(e) => self.recover(e, &mut retry).await?,
+            }
+        }
+    }
+    /// Reads all entries of a search, retrying the whole search if it fails midway.
+    ///
+    /// Entries are only returned once the search completed, so a retry never hands out an entry twice.
+    /// A search the server truncated because of a limit returns the entries up to there, see
+    /// [`SearchResults::was_truncated`](crate::search::SearchResults::was_truncated).
+    pub async fn search_collect<Output: FromEntry>(
+        &mut self,
+        base_object: &str,
+        scope: Scope,
+        deref_policy: DerefPolicy,
+        filter: &Filter<'_>,
+    ) -> Result<Vec<Output>, RetryError<CollectError>> {
+        let mut retry = 0;
+        loop {
+            let result = collect(&self.connection, base_object, scope, deref_policy, filter.clone()).await;
+            match result {
+                Ok(entries) => return Ok(entries),
+                Err(e) => self.recover(e, &mut retry).await?,
+            }
+        }
+    }
+    /// [`LdapConnection::count`], retried
+    pub async fn count(
+        &mut self,
+        base_object: &str,
+        scope: Scope,
+        deref_policy: DerefPolicy,
+        filter: &Filter<'_>,
+    ) -> Result<usize, RetryError<CountError>> {
+        let mut retry = 0;
+        loop {
+            let result = self
+                .connection
+                .count(base_object, scope, deref_policy, filter.clone())
+                .await;
+            match result {
+                Ok(count) => return Ok(count),
+                Err(e) => self.recover(e, &mut retry).await?,
+            }
+        }
+    }
+    /// [`LdapConnection::compare`], retried
+    pub async fn compare(
+        &mut self,
+        entry: &str,
+        value_assertion: AttributeValueAssertion<'_>,
+    ) -> Result<bool, RetryError<CompareError>> {
+        let mut retry = 0;
+        loop {
+            match self.connection.compare(entry, value_assertion).await {
+                Ok(matched) => return Ok(matched),
+                Err(e) => self.recover(e, &mut retry).await?,
+            }
+        }
+    }
+    /// Waits and reconnects if `error` is worth another attempt, otherwise gives it back
+    async fn recover<E: Transient>(&mut self, error: E, retry: &mut u32) -> Result<(), RetryError<E>> {
+        if !error.is_transient() || *retry + 1 >= self.policy.attempts {
+            return Err(RetryError::Operation(error));
+        }
+        tokio::time::sleep(self.policy.delay(*retry)).await;
+        *retry += 1;
+        match (self.connect)().await {
+            Ok(connection) => {
+                self.connection = connection;
+                Ok(())
+            }
+            Err(reconnect) => Err(RetryError::Reconnect {
+                operation: error,
+                reconnect: Box::new(reconnect),
+            }),
+        }
+    }
+}
+
+/// Whether an error may go away by retrying on a new connection
+trait Transient {
+    fn is_transient(&self) -> bool;
+}
+fn is_transient_code(code: ResultCode) -> bool {
+    matches!(code, ResultCode::Busy | ResultCode::Unavailable)
+}
+fn is_transient_io(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::UnexpectedEof
+    )
+}
+impl Transient for BeginSearchError {
+    fn is_transient(&self) -> bool {
+        self.is_disconnect()
+    }
+}
+impl Transient for SearchResultError {
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Io(error) => is_transient_io(error),
+            Self::ConnectionClosed => true,
+            _ => false,
+        }
+    }
+}
+impl Transient for SearchOneError {
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Begin(e) => e.is_transient(),
+            Self::Search(e) => e.is_transient(),
+            Self::Disconnected => true,
+            Self::ServerError { code, .. } => is_transient_code(*code),
+            Self::TooManyResults => false,
+        }
+    }
+}
+impl Transient for CountError {
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Begin(e) => e.is_transient(),
+            Self::Search(e) => e.is_transient(),
+            Self::Disconnected => true,
+            Self::ServerError { code, .. } => is_transient_code(*code),
+        }
+    }
+}
+impl Transient for CollectError {
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Begin(e) => e.is_transient(),
+            Self::Search(e) => e.is_transient(),
+            Self::Disconnected => true,
+            Self::ServerError { code, .. } => is_transient_code(*code),
+        }
+    }
+}
+impl Transient for CompareError {
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Io(error) => is_transient_io(error),
+            Self::Disconnected => true,
+            Self::ServerError { code, .. } => is_transient_code(*code),
+            Self::Encode(_) | Self::InvalidSchema | Self::UnavailableCriticalExtension(_) => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RetryError<E> {
+    /// The operation failed with an error that isn't transient, or on the last attempt
+    Operation(E),
+    /// Reconnecting after a transient error failed
+    Reconnect {
+        /// The error that caused the retry
+        operation: E,
+        reconnect: Box<LapdogError>,
+    },
+}
+impl<E: Error + 'static> Error for RetryError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Operation(e) => e.source(),
+            Self::Reconnect { reconnect, .. } => Some(reconnect),
+        }
+    }
+}
+impl<E: Display> Display for RetryError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Operation(e) => e.fmt(f),
+            Self::Reconnect { operation, .. } => write!(f, "Failed to reconnect after: {operation}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{RetryPolicy, RetryingConnection};
+    use crate::{
+        LdapConnection, StreamConfig,
+        search::{DerefPolicy, Filter, RawEntry, Scope},
+    };
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy {
+            attempts: 5,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(2), Duration::from_millis(300));
+        assert_eq!(policy.delay(40), Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_reset() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            // the first connection drops the search, the second one answers it
+            let (mut first, _) = listener.accept().await.unwrap();
+            assert!(first.read(&mut [0; 256]).await.unwrap() > 0);
+            drop(first);
+            let (mut second, _) = listener.accept().await.unwrap();
+            assert!(second.read(&mut [0; 256]).await.unwrap() > 0);
+            let done = [
+                0x30, 0x0c, 0x02, 0x01, 0x01, 0x65, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00,
+            ];
+            second.write_all(&done).await.unwrap();
+        });
+        let policy = RetryPolicy {
+            backoff: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut connection = RetryingConnection::new(
+            move || async move { Ok(LdapConnection::new(address, &StreamConfig::Plain).await?) },
+            policy,
+        )
+        .await
+        .unwrap();
+        let entry = connection
+            .search_one::<RawEntry>(
+                "dc=x",
+                Scope::Base,
+                DerefPolicy::Never,
+                &Filter::Present("objectClass"),
+            )
+            .await
+            .unwrap();
+        assert!(entry.is_none());
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,599 @@
+//! Reading the server's schema (RFC 4512)
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    str::FromStr,
+};
+
+use crate::{
+    LdapConnection,
+    search::{DerefPolicy, FailedToGetFromEntry, Filter, FromEntry, RawEntry, Scope, SearchOneError},
+};
+
+impl LdapConnection {
+    /// Reads the object classes and attribute types from the subschema entry the root DSE points to
+    pub async fn read_schema(&self) -> Result<Schema, ReadSchemaError> {
+        let root_dse = self
+            .search_one::<RootDse>(
+                "",
+                Scope::Base,
+                DerefPolicy::Never,
+                Filter::Present("objectClass"),
+            )
+            .await
+            .map_err(ReadSchemaError::Search)?;
+        let Some(RootDse(Some(subschema_dn))) = root_dse else {
+            return Err(ReadSchemaError::NoSubschemaSubentry);
+        };
+        let Some(subschema) = self
+            .search_one::<SubschemaEntry>(
+                &subschema_dn,
+                Scope::Base,
+                DerefPolicy::Never,
+                Filter::equal("objectClass", b"subschema"),
+            )
+            .await
+            .map_err(ReadSchemaError::Search)?
+        else {
+            return Err(ReadSchemaError::NoSubschemaSubentry);
+        };
+        Ok(Schema {
+            object_classes: subschema
+                .object_classes
+                .iter()
+                .map(|d| d.parse())
+                .collect::<Result<_, _>>()
+                .map_err(ReadSchemaError::Parse)?,
+            attribute_types: subschema
+                .attribute_types
+                .iter()
+                .map(|d| d.parse())
+                .collect::<Result<_, _>>()
+                .map_err(ReadSchemaError::Parse)?,
+        })
+    }
+}
+
+struct RootDse(Option<String>);
+impl FromEntry for RootDse {
+    fn from_entry(mut entry: RawEntry) -> Result<Self, FailedToGetFromEntry> {
+        let subschema = entry
+            .take_attribute("subschemaSubentry")
+            .and_then(|values| values.into_iter().next())
+            .map(|dn| {
+                String::from_utf8(dn)
+                    .map_err(|e| FailedToGetFromEntry::FailedToParseField("subschemaSubentry", Box::new(e)))
+            })
+            .transpose()?;
+        Ok(Self(subschema))
+    }
+    fn attributes() -> Option<impl Iterator<Item = &'static str>> {
+        Some(["subschemaSubentry"].into_iter())
+    }
+}
+
+struct SubschemaEntry {
+    object_classes: Vec<String>,
+    attribute_types: Vec<String>,
+}
+impl FromEntry for SubschemaEntry {
+    fn from_entry(mut entry: RawEntry) -> Result<Self, FailedToGetFromEntry> {
+        let mut take = |name: &'static str| {
+            entry
+                .take_attribute(name)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|v| {
+                    String::from_utf8(v)
+                        .map_err(|e| FailedToGetFromEntry::FailedToParseField(name, Box::new(e)))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            object_classes: take("objectClasses")?,
+            attribute_types: take("attributeTypes")?,
+        })
+    }
+    fn attributes() -> Option<impl Iterator<Item = &'static str>> {
+        Some(["objectClasses", "attributeTypes"].into_iter())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Schema {
+    pub object_classes: Vec<ObjectClassDefinition>,
+    pub attribute_types: Vec<AttributeTypeDefinition>,
+}
+impl Schema {
+    /// Looks up an object class by one of its names or its OID, case-insensitively
+    pub fn object_class(&self, name: &str) -> Option<&ObjectClassDefinition> {
+        self.object_classes
+            .iter()
+            .find(|oc| oc.oid == name || oc.names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+    }
+    /// Looks up an attribute type by one of its names or its OID, case-insensitively
+    pub fn attribute_type(&self, name: &str) -> Option<&AttributeTypeDefinition> {
+        self.attribute_types
+            .iter()
+            .find(|at| at.oid == name || at.names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+    }
+    /// Checks an entry against the schema before sending it to the server, e.g. for an add.
+    ///
+    /// Superior classes are taken into account, attribute options like `;lang-de` are ignored.
+    /// `objectClass` is taken from `object_classes` and doesn't need to be in `attributes`.
+    pub fn validate_entry(
+        &self,
+        object_classes: &[&str],
+        attributes: &[(&str, &[&[u8]])],
+    ) -> Result<(), Vec<SchemaViolation>> {
+        let mut violations = Vec::new();
+        // the object classes with all their superiors
+        let mut classes: Vec<&ObjectClassDefinition> = Vec::new();
+        let mut pending: Vec<&str> = object_classes.to_vec();
+        while let Some(name) = pending.pop() {
+            let Some(class) = self.object_class(name) else {
+                violations.push(SchemaViolation::UnknownObjectClass(name.to_string()));
+                continue;
+            };
+            if classes.iter().any(|c| c.oid == class.oid) {
+                continue;
+            }
+            pending.extend(class.superiors.iter().map(String::as_str));
+            classes.push(class);
+        }
+        let extensible = classes.iter().any(|c| c.oid == EXTENSIBLE_OBJECT_OID);
+
+        // attribute types are compared by OID, so aliases like `uid`/`userid` match
+        let key = |name: &str| {
+            let name = name.split(';').next().unwrap_or_default();
+            self.attribute_type(name)
+                .map_or_else(|| name.to_ascii_lowercase(), |at| at.oid.clone())
+        };
+        let mut present: Vec<String> = attributes.iter().map(|(name, _)| key(name)).collect();
+        present.push(key("objectClass"));
+        let mut allowed: Vec<String> = Vec::new();
+        for class in &classes {
+            for attribute in &class.must {
+                let k = key(attribute);
+                if !present.contains(&k) {
+                    violations.push(SchemaViolation::MissingAttribute {
+                        object_class: class.names.first().unwrap_or(&class.oid).clone(),
+                        attribute: attribute.clone(),
+                    });
+                    // report every attribute only once
+                    present.push(k.clone());
+                }
+                allowed.push(k);
+            }
+            allowed.extend(class.may.iter().map(|a| key(a)));
+        }
+        for (name, values) in attributes {
+            let k = key(name);
+            if !extensible && !allowed.contains(&k) {
+                violations.push(SchemaViolation::DisallowedAttribute(name.to_string()));
+            }
+            let single_value = self.attribute_type(&k).is_some_and(|at| at.single_value);
+            if single_value && values.len() > 1 {
+                violations.push(SchemaViolation::MultipleValues(name.to_string()));
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+/// `extensibleObject` permits any attribute
+const EXTENSIBLE_OBJECT_OID: &str = "1.3.6.1.4.1.1466.101.120.111";
+
+/// A reason an entry doesn't conform to the [`Schema`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemaViolation {
+    UnknownObjectClass(String),
+    /// An attribute required by `object_class` is missing
+    MissingAttribute {
+        object_class: String,
+        attribute: String,
+    },
+    /// The attribute is in neither `MUST` nor `MAY` of any of the object classes
+    DisallowedAttribute(String),
+    /// A `SINGLE-VALUE` attribute has more than one value
+    MultipleValues(String),
+}
+impl Display for SchemaViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::UnknownObjectClass(name) => write!(f, "Unknown object class {name}"),
+            Self::MissingAttribute {
+                object_class,
+                attribute,
+            } => write!(f, "Object class {object_class} requires attribute {attribute}"),
+            Self::DisallowedAttribute(name) => {
+                write!(f, "Attribute {name} isn't allowed by any object class")
+            }
+            Self::MultipleValues(name) => {
+                write!(f, "Attribute {name} is single-valued but has multiple values")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ObjectClassKind {
+    Abstract,
+    #[default]
+    Structural,
+    Auxiliary,
+}
+
+/// An entry of `objectClasses`, e.g. `( 2.5.6.6 NAME 'person' SUP top STRUCTURAL MUST ( sn $ cn ) )`
+#[derive(Clone, Debug, Default)]
+pub struct ObjectClassDefinition {
+    pub oid: String,
+    pub names: Vec<String>,
+    pub description: Option<String>,
+    pub obsolete: bool,
+    pub superiors: Vec<String>,
+    pub kind: ObjectClassKind,
+    pub must: Vec<String>,
+    pub may: Vec<String>,
+}
+impl FromStr for ObjectClassDefinition {
+    type Err = ParseSchemaError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (oid, fields) = parse_definition(s)?;
+        let mut definition = Self {
+            oid,
+            ..Default::default()
+        };
+        for (keyword, values) in fields {
+            match keyword.as_str() {
+                "NAME" => definition.names = values,
+                "DESC" => definition.description = values.into_iter().next(),
+                "OBSOLETE" => definition.obsolete = true,
+                "SUP" => definition.superiors = values,
+                "ABSTRACT" => definition.kind = ObjectClassKind::Abstract,
+                "STRUCTURAL" => definition.kind = ObjectClassKind::Structural,
+                "AUXILIARY" => definition.kind = ObjectClassKind::Auxiliary,
+                "MUST" => definition.must = values,
+                "MAY" => definition.may = values,
+                _ => {}
+            }
+        }
+        Ok(definition)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AttributeUsage {
+    #[default]
+    UserApplications,
+    DirectoryOperation,
+    DistributedOperation,
+    DsaOperation,
+}
+
+/// An entry of `attributeTypes`, e.g. `( 2.5.4.3 NAME 'cn' SUP name )`
+#[derive(Clone, Debug, Default)]
+pub struct AttributeTypeDefinition {
+    pub oid: String,
+    pub names: Vec<String>,
+    pub description: Option<String>,
+    pub obsolete: bool,
+    pub superior: Option<String>,
+    pub equality: Option<String>,
+    pub ordering: Option<String>,
+    pub substring: Option<String>,
+    /// OID of the syntax, without the length bound
+    pub syntax: Option<String>,
+    /// Suggested maximum length from `SYNTAX oid{len}`
+    pub syntax_length: Option<u32>,
+    pub single_value: bool,
+    pub collective: bool,
+    pub no_user_modification: bool,
+    pub usage: AttributeUsage,
+}
+impl FromStr for AttributeTypeDefinition {
+    type Err = ParseSchemaError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (oid, fields) = parse_definition(s)?;
+        let mut definition = Self {
+            oid,
+            ..Default::default()
+        };
+        for (keyword, values) in fields {
+            if keyword == "NAME" {
+                definition.names = values;
+                continue;
+            }
+            let first = values.into_iter().next();
+            match keyword.as_str() {
+                "DESC" => definition.description = first,
+                "OBSOLETE" => definition.obsolete = true,
+                "SUP" => definition.superior = first,
+                "EQUALITY" => definition.equality = first,
+                "ORDERING" => definition.ordering = first,
+                "SUBSTR" => definition.substring = first,
+                "SYNTAX" => {
+                    let syntax = first.ok_or(ParseSchemaError::UnexpectedEnd)?;
+                    match syntax.split_once('{') {
+                        Some((oid, len)) => {
+                            let len = len
+                                .strip_suffix('}')
+                                .and_then(|l| l.parse().ok())
+                                .ok_or_else(|| ParseSchemaError::UnexpectedToken(syntax.clone()))?;
+                            definition.syntax = Some(oid.to_string());
+                            definition.syntax_length = Some(len);
+                        }
+                        None => definition.syntax = Some(syntax),
+                    }
+                }
+                "SINGLE-VALUE" => definition.single_value = true,
+                "COLLECTIVE" => definition.collective = true,
+                "NO-USER-MODIFICATION" => definition.no_user_modification = true,
+                "USAGE" => {
+                    definition.usage = match first.as_deref() {
+                        Some("userApplications") => AttributeUsage::UserApplications,
+                        Some("directoryOperation") => AttributeUsage::DirectoryOperation,
+                        Some("distributedOperation") => AttributeUsage::DistributedOperation,
+                        Some("dSAOperation") => AttributeUsage::DsaOperation,
+                        _ => return Err(ParseSchemaError::UnexpectedToken(first.unwrap_or_default())),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(definition)
+    }
+}
+
+const FLAGS: [&str; 7] = [
+    "OBSOLETE",
+    "SINGLE-VALUE",
+    "COLLECTIVE",
+    "NO-USER-MODIFICATION",
+    "ABSTRACT",
+    "STRUCTURAL",
+    "AUXILIARY",
+];
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Dollar,
+    Quoted(String),
+    Word(String),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, ParseSchemaError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => tokens.push(Token::Dollar),
+            '\'' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(ParseSchemaError::UnexpectedEnd),
+                        Some('\'') => break,
+                        // RFC 4512 escapes ' as \27 and \ as \5C
+                        Some('\\') => {
+                            let escape: String = chars.by_ref().take(2).collect();
+                            match escape.to_ascii_uppercase().as_str() {
+                                "27" => quoted.push('\''),
+                                "5C" => quoted.push('\\'),
+                                _ => return Err(ParseSchemaError::UnexpectedToken(format!("\\{escape}"))),
+                            }
+                        }
+                        Some(c) => quoted.push(c),
+                    }
+                }
+                tokens.push(Token::Quoted(quoted));
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '$' | '\'') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Keyword and values of a definition, flags have no values
+type Field = (String, Vec<String>);
+
+/// Splits a definition into its OID and its fields
+fn parse_definition(s: &str) -> Result<(String, Vec<Field>), ParseSchemaError> {
+    let mut tokens = tokenize(s)?.into_iter();
+    let mut next = || tokens.next().ok_or(ParseSchemaError::UnexpectedEnd);
+    let unexpected = |t: Token| ParseSchemaError::UnexpectedToken(format!("{t:?}"));
+    match next()? {
+        Token::Open => {}
+        t => return Err(unexpected(t)),
+    }
+    let oid = match next()? {
+        Token::Word(oid) | Token::Quoted(oid) => oid,
+        t => return Err(unexpected(t)),
+    };
+    let mut fields = Vec::new();
+    loop {
+        let keyword = match next()? {
+            Token::Close => break,
+            Token::Word(keyword) => keyword,
+            t => return Err(unexpected(t)),
+        };
+        if FLAGS.contains(&keyword.as_str()) {
+            fields.push((keyword, Vec::new()));
+            continue;
+        }
+        let values = match next()? {
+            Token::Word(v) | Token::Quoted(v) => vec![v],
+            Token::Open => {
+                let mut values = Vec::new();
+                loop {
+                    match next()? {
+                        Token::Close => break,
+                        Token::Dollar => {}
+                        Token::Word(v) | Token::Quoted(v) => values.push(v),
+                        t => return Err(unexpected(t)),
+                    }
+                }
+                values
+            }
+            t => return Err(unexpected(t)),
+        };
+        fields.push((keyword, values));
+    }
+    Ok((oid, fields))
+}
+
+#[derive(Debug)]
+pub enum ParseSchemaError {
+    UnexpectedEnd,
+    UnexpectedToken(String),
+}
+impl Error for ParseSchemaError {}
+impl Display for ParseSchemaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::UnexpectedEnd => write!(f, "schema definition ended unexpectedly"),
+            Self::UnexpectedToken(t) => write!(f, "unexpected {t} in schema definition"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadSchemaError {
+    Search(SearchOneError),
+    /// The root DSE doesn't point to a readable subschema entry
+    NoSubschemaSubentry,
+    Parse(ParseSchemaError),
+}
+impl Error for ReadSchemaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Search(s) => Some(s),
+            Self::Parse(p) => Some(p),
+            Self::NoSubschemaSubentry => None,
+        }
+    }
+}
+impl Display for ReadSchemaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Search(s) => write!(f, "Failed to read schema: {s}"),
+            Self::NoSubschemaSubentry => write!(f, "Server did not provide a subschema entry"),
+            Self::Parse(p) => write!(f, "Failed to parse schema: {p}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        AttributeTypeDefinition, AttributeUsage, ObjectClassDefinition, ObjectClassKind, Schema,
+        SchemaViolation,
+    };
+
+    #[test]
+    fn parse_object_class() {
+        let person: ObjectClassDefinition =
+            "( 2.5.6.6 NAME 'person' DESC 'RFC2256: a person' SUP top STRUCTURAL MUST ( sn $ cn ) MAY ( userPassword $ telephoneNumber ) X-ORIGIN 'RFC 4519' )"
+                .parse()
+                .unwrap();
+        assert_eq!(person.oid, "2.5.6.6");
+        assert_eq!(person.names, ["person"]);
+        assert_eq!(person.description.as_deref(), Some("RFC2256: a person"));
+        assert_eq!(person.superiors, ["top"]);
+        assert_eq!(person.kind, ObjectClassKind::Structural);
+        assert_eq!(person.must, ["sn", "cn"]);
+        assert_eq!(person.may, ["userPassword", "telephoneNumber"]);
+    }
+
+    #[test]
+    fn parse_attribute_type() {
+        let uid: AttributeTypeDefinition = "( 0.9.2342.19200300.100.1.1 NAME ( 'uid' 'userid' ) EQUALITY caseIgnoreMatch SUBSTR caseIgnoreSubstringsMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.15{256} SINGLE-VALUE )"
+            .parse()
+            .unwrap();
+        assert_eq!(uid.names, ["uid", "userid"]);
+        assert_eq!(uid.equality.as_deref(), Some("caseIgnoreMatch"));
+        assert_eq!(uid.syntax.as_deref(), Some("1.3.6.1.4.1.1466.115.121.1.15"));
+        assert_eq!(uid.syntax_length, Some(256));
+        assert!(uid.single_value);
+        assert_eq!(uid.usage, AttributeUsage::UserApplications);
+
+        let modify_time: AttributeTypeDefinition = "( 2.5.18.2 NAME 'modifyTimestamp' SYNTAX '1.3.6.1.4.1.1466.115.121.1.24' NO-USER-MODIFICATION USAGE directoryOperation )"
+            .parse()
+            .unwrap();
+        assert!(modify_time.no_user_modification);
+        assert_eq!(modify_time.usage, AttributeUsage::DirectoryOperation);
+    }
+
+    #[test]
+    fn validate_entry() {
+        let schema = Schema {
+            object_classes: vec![
+                "( 2.5.6.0 NAME 'top' ABSTRACT MUST objectClass )"
+                    .parse()
+                    .unwrap(),
+                "( 2.5.6.6 NAME 'person' SUP top STRUCTURAL MUST ( sn $ cn ) MAY description )"
+                    .parse()
+                    .unwrap(),
+            ],
+            attribute_types: vec![
+                "( 2.5.4.0 NAME 'objectClass' )".parse().unwrap(),
+                "( 2.5.4.3 NAME ( 'cn' 'commonName' ) )".parse().unwrap(),
+                "( 2.5.4.4 NAME ( 'sn' 'surname' ) SINGLE-VALUE )"
+                    .parse()
+                    .unwrap(),
+                "( 2.5.4.13 NAME 'description' )".parse().unwrap(),
+            ],
+        };
+        let valid: &[(&str, &[&[u8]])] = &[("commonName", &[b"Jane"]), ("sn;lang-de", &[b"Doe"])];
+        assert_eq!(schema.validate_entry(&["person"], valid), Ok(()));
+
+        let invalid: &[(&str, &[&[u8]])] = &[("sn", &[b"Doe", b"Roe"]), ("mail", &[b"jane@example.com"])];
+        assert_eq!(
+            schema.validate_entry(&["person", "inetOrgPerson"], invalid),
+            Err(vec![
+                SchemaViolation::UnknownObjectClass("inetOrgPerson".to_string()),
+                SchemaViolation::MissingAttribute {
+                    object_class: "person".to_string(),
+                    attribute: "cn".to_string()
+                },
+                SchemaViolation::MultipleValues("sn".to_string()),
+                SchemaViolation::DisallowedAttribute("mail".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn reject_broken_definitions() {
+        assert!("( 2.5.6.6 NAME 'person".parse::<ObjectClassDefinition>().is_err());
+        assert!(
+            "2.5.6.6 NAME 'person' )"
+                .parse::<ObjectClassDefinition>()
+                .is_err()
+        );
+        assert!(
+            "( 2.5.6.6 MUST ( sn $ cn "
+                .parse::<ObjectClassDefinition>()
+                .is_err()
+        );
+    }
+}
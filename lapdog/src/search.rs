@@ -4,28 +4,51 @@ use std::{
     fmt::Display,
     io::{ErrorKind, Read},
     marker::PhantomData,
+    num::NonZero,
+    str::Utf8Error,
 };
 
 use crate::{
-    LdapConnection, ReceiveMessageError, SendMessageError, WriteExt,
+    EncodingRules, LdapConnection, ReceiveMessageError, SearchEnd, SendMessageError, WriteExt,
+    attribute::description_matches,
+    control::{self, Control},
+    extended::{self, ReadExtendedError},
+    ldif,
     length::{LengthError, read_length},
     message::RequestProtocolOp,
     parse::ParseLdap,
     read::ReadExt,
     result::ResultCode,
     tag::{
-        self, OCTET_STRING, UNIVERSAL_BOOLEAN, UNIVERSAL_ENUMERATED, UNIVERSAL_INTEGER, UNIVERSAL_SEQUENCE,
-        UNIVERSAL_SET,
+        self, OCTET_STRING, TagClass, UNIVERSAL_BOOLEAN, UNIVERSAL_ENUMERATED, UNIVERSAL_INTEGER,
+        UNIVERSAL_SEQUENCE, UNIVERSAL_SET,
     },
 };
 
+mod builder;
+mod chase;
 #[cfg(feature = "from_octets")]
 mod impl_traits;
+mod paged;
+mod range;
 mod types;
+pub(crate) use builder::SearchRequest;
+pub use builder::SearchRequestBuilder;
+pub use chase::{ChaseError, ChasePolicy};
+#[cfg(feature = "uuid")]
+pub use impl_traits::{BinaryGuid, InvalidGuidLength};
 #[cfg(feature = "derive")]
 pub use lapdog_derive::Entry;
-use tokio::sync::{mpsc::UnboundedReceiver, oneshot::Sender};
-pub use types::{DerefPolicy, Filter, MatchingRuleAssertion, Scope};
+pub use paged::PageCookie;
+pub use range::RangeRetrievalError;
+use tokio::{
+    sync::{
+        mpsc::{UnboundedReceiver, error::TryRecvError},
+        oneshot::Sender,
+    },
+    time::Instant,
+};
+pub use types::{DerefPolicy, Filter, FilterBuilder, IN_CHAIN_OID, MatchingRuleAssertion, Scope};
 
 impl LdapConnection {
     pub async fn search_all(
@@ -35,7 +58,11 @@ impl LdapConnection {
         deref_policy: DerefPolicy,
         filter: Filter<'_>,
     ) -> Result<SearchResults, BeginSearchError> {
-        self.search_raw(base_object, scope, deref_policy, filter, vec!["*"])
+        self.search_builder(base_object)
+            .scope(scope)
+            .deref_policy(deref_policy)
+            .filter(filter)
+            .send()
             .await
     }
     pub async fn search<'a>(
@@ -46,7 +73,33 @@ impl LdapConnection {
         filter: Filter<'_>,
         attributes: impl IntoIterator<Item = &'a str>,
     ) -> Result<SearchResults, BeginSearchError> {
-        self.search_raw(base_object, scope, deref_policy, filter, attributes)
+        self.search_builder(base_object)
+            .scope(scope)
+            .deref_policy(deref_policy)
+            .filter(filter)
+            .attributes(attributes)
+            .send()
+            .await
+    }
+    /// Like [`LdapConnection::search`], but attaches request controls, e.g. [`Control::matched_values`].
+    pub async fn search_with_controls<'a>(
+        &self,
+        base_object: &str,
+        scope: Scope,
+        deref_policy: DerefPolicy,
+        filter: Filter<'_>,
+        attributes: impl IntoIterator<Item = &'a str>,
+        controls: &[Control],
+    ) -> Result<SearchResults, BeginSearchError> {
+        controls
+            .iter()
+            .cloned()
+            .fold(self.search_builder(base_object), SearchRequestBuilder::control)
+            .scope(scope)
+            .deref_policy(deref_policy)
+            .filter(filter)
+            .attributes(attributes)
+            .send()
             .await
     }
     pub async fn search_as<Output: FromEntry>(
@@ -56,98 +109,799 @@ impl LdapConnection {
         deref_policy: DerefPolicy,
         filter: Filter<'_>,
     ) -> Result<SearchResults<Output>, BeginSearchError> {
-        let attributes = match Output::attributes() {
-            None => vec!["*"],
-            Some(v) => v.collect(),
-        };
-        self.search_raw(base_object, scope, deref_policy, filter, attributes)
+        self.search_builder(base_object)
+            .scope(scope)
+            .deref_policy(deref_policy)
+            .filter(filter)
+            .send_as()
             .await
     }
-    async fn search_raw<'a, Output: FromEntry>(
+    /// Like [`LdapConnection::search_as`], but also requests `extra_attributes`, e.g. operational attributes for logging.
+    ///
+    /// The extra attributes are ignored when parsing into `Output`.
+    pub async fn search_as_with_attributes<Output: FromEntry>(
         &self,
-        entry: &str,
+        base_object: &str,
         scope: Scope,
         deref_policy: DerefPolicy,
         filter: Filter<'_>,
-        attributes: impl IntoIterator<Item = &'a str>,
+        extra_attributes: &[&str],
     ) -> Result<SearchResults<Output>, BeginSearchError> {
-        let attributes: Vec<&str> = attributes.into_iter().collect();
-        let proto = RequestProtocolOp::Search {
-            entry,
-            scope,
-            deref_policy,
-            filter,
-            attributes: &attributes,
+        let mut attributes: Vec<&str> = match Output::attributes() {
+            None => vec!["*"],
+            Some(v) => v.collect(),
         };
-        let (incoming_messages, done) = self
-            .send_message(proto)
+        for extra in extra_attributes {
+            if !attributes.iter().any(|a| a.eq_ignore_ascii_case(extra)) {
+                attributes.push(extra);
+            }
+        }
+        self.search_builder(base_object)
+            .scope(scope)
+            .deref_policy(deref_policy)
+            .filter(filter)
+            .attributes(attributes)
+            .send_as()
+            .await
+    }
+    /// Builds a search below `base_object` with all options LDAP offers, e.g. size and time limits
+    pub fn search_builder<'a>(&'a self, base_object: &'a str) -> SearchRequestBuilder<'a> {
+        SearchRequestBuilder::new(self, base_object)
+    }
+    pub(crate) async fn search_raw<Output: FromEntry>(
+        &self,
+        request: SearchRequest<'_>,
+        controls: &[Control],
+    ) -> Result<SearchResults<Output>, BeginSearchError> {
+        let resumed_page = paged::resumes_page(controls);
+        let (message_id, incoming_messages, done) = self
+            .send_message_with_controls(RequestProtocolOp::Search(request), controls)
             .await
-            .map_err(BeginSearchError)?
+            .map_err(|e| BeginSearchError(BeginSearchErrorKind::Send(e)))?
             .into_receiver();
         Ok(SearchResults {
+            message_id,
             incoming_messages,
             buffer: Default::default(),
             done: Some(done),
+            deadline: self.operation_timeout.map(|timeout| Instant::now() + timeout),
+            timed_out: false,
+            final_result: None,
+            response_controls: Vec::new(),
+            resumed_page,
+            bytes_received: 0,
             _e: PhantomData,
         })
     }
 }
 
+impl LdapConnection {
+    /// Searches for at most one entry, e.g. a user by a unique `sAMAccountName`.
+    ///
+    /// Returns `Ok(None)` if nothing matched. If a second entry arrives, the search is abandoned
+    /// and [`SearchOneError::TooManyResults`] is returned.
+    pub async fn search_one<Output: FromEntry>(
+        &self,
+        base_object: &str,
+        scope: Scope,
+        deref_policy: DerefPolicy,
+        filter: Filter<'_>,
+    ) -> Result<Option<Output>, SearchOneError> {
+        let results = self
+            .search_as::<Output>(base_object, scope, deref_policy, filter)
+            .await
+            .map_err(SearchOneError::Begin)?;
+        self.single_result(results).await
+    }
+    /// Collects all entries matching `filter` below and including `base_object`, the most common kind of search.
+    ///
+    /// This searches the whole subtree without dereferencing aliases and stops at the first error. A search the server
+    /// truncated because of a limit returns the entries up to there. For other scopes or controls, or to process
+    /// entries as they arrive, use [`LdapConnection::search_builder`].
+    ///
+    /// ```no_run
+    /// # use lapdog::{LdapConnection, search::{Filter, RawEntry}};
+    /// # async fn f(connection: &LdapConnection) -> Result<(), lapdog::LapdogError> {
+    /// let people: Vec<RawEntry> = connection
+    ///     .find_all("ou=people,dc=example,dc=com", Filter::equal("objectClass", "person"))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn find_all<Output: FromEntry>(
+        &self,
+        base_object: &str,
+        filter: Filter<'_>,
+    ) -> Result<Vec<Output>, CollectError> {
+        collect(self, base_object, Scope::WholeSubtree, DerefPolicy::Never, filter).await
+    }
+    /// Reads the entry at `dn`, e.g. a user or group whose DN is already known.
+    ///
+    /// This is a base search with the filter `(objectClass=*)`. An entry that doesn't exist fails with
+    /// [`ReadEntryError::NoSuchObject`]; so does one the server hides from the bound identity by returning nothing.
+    pub async fn read_entry<Output: FromEntry>(&self, dn: &str) -> Result<Output, ReadEntryError> {
+        self.search_one(
+            dn,
+            Scope::Base,
+            DerefPolicy::Never,
+            Filter::Present("objectClass"),
+        )
+        .await?
+        .ok_or(ReadEntryError::NoSuchObject(String::new()))
+    }
+    /// Reads at most one entry from `results`, abandoning the search if there are more
+    pub(crate) async fn single_result<Output: FromEntry>(
+        &self,
+        mut results: SearchResults<Output>,
+    ) -> Result<Option<Output>, SearchOneError> {
+        let mut found = None;
+        loop {
+            let error = match results.next().await {
+                Some(Ok(SearchResult::Entry(entry))) if found.is_none() => {
+                    found = Some(entry);
+                    continue;
+                }
+                Some(Ok(SearchResult::Entry(_))) => SearchOneError::TooManyResults,
+                Some(Ok(SearchResult::Reference(_) | SearchResult::Intermediate { .. })) => continue,
+                Some(Ok(SearchResult::Done {
+                    code: ResultCode::Success,
+                    ..
+                })) => return Ok(found),
+                Some(Ok(SearchResult::Done {
+                    code,
+                    diagnostics_message,
+                    ..
+                })) => {
+                    return Err(SearchOneError::ServerError {
+                        code,
+                        message: diagnostics_message,
+                    });
+                }
+                Some(Err(SearchResultError::ConnectionClosed)) | None => {
+                    return Err(SearchOneError::Disconnected);
+                }
+                Some(Err(e)) => SearchOneError::Search(e),
+            };
+            // the result is already decided, so a failed abandon doesn't matter
+            let _ = self.abandon(results.message_id()).await;
+            return Err(error);
+        }
+    }
+    /// Counts the entries matching `filter` without transferring their attributes.
+    ///
+    /// The server still has to find every entry, so this is only cheaper than a search in the amount of data sent.
+    /// Server size limits apply as well: if more entries match than the server returns for one search, this fails
+    /// with `SizeLimitExceeded` instead of counting them.
+    // Servers supporting the virtual list view control report an estimated count without sending entries,
+    // which would be cheaper still, but lapdog doesn't implement that control yet
+    pub async fn count(
+        &self,
+        base_object: &str,
+        scope: Scope,
+        deref_policy: DerefPolicy,
+        filter: Filter<'_>,
+    ) -> Result<usize, CountError> {
+        let mut results = self
+            .search_builder(base_object)
+            .scope(scope)
+            .deref_policy(deref_policy)
+            .filter(filter)
+            // RFC 4511: "1.1" requests no attributes at all
+            .attributes(["1.1"])
+            .types_only(true)
+            .send()
+            .await
+            .map_err(CountError::Begin)?;
+        let mut count = 0;
+        loop {
+            match results.next().await {
+                Some(Ok(SearchResult::Entry(_))) => count += 1,
+                Some(Ok(SearchResult::Reference(_) | SearchResult::Intermediate { .. })) => {}
+                Some(Ok(SearchResult::Done {
+                    code: ResultCode::Success,
+                    ..
+                })) => return Ok(count),
+                Some(Ok(SearchResult::Done {
+                    code,
+                    diagnostics_message,
+                    ..
+                })) => {
+                    return Err(CountError::ServerError {
+                        code,
+                        message: diagnostics_message,
+                    });
+                }
+                Some(Err(SearchResultError::ConnectionClosed)) | None => {
+                    return Err(CountError::Disconnected);
+                }
+                Some(Err(e)) => return Err(CountError::Search(e)),
+            }
+        }
+    }
+    /// Checks whether the server accepts `filter` without fetching any data, e.g. to lint filters entered in an
+    /// admin UI.
+    ///
+    /// This searches only `base_object` for no attributes, so it's cheap no matter how many entries would match.
+    /// Result codes the server uses for bad filters, like `undefinedAttributeType` or `inappropriateMatching`,
+    /// become [`ValidateFilterError::Rejected`]; other errors, like a `base_object` that doesn't exist,
+    /// are [`ValidateFilterError::ServerError`].
+    /// Servers differ in what they reject: OpenLDAP evaluates a filter on an unknown attribute to Undefined
+    /// instead of failing, so passing this isn't a guarantee the filter means what it should.
+    pub async fn validate_filter(
+        &self,
+        base_object: &str,
+        filter: Filter<'_>,
+    ) -> Result<(), ValidateFilterError> {
+        let mut results = self
+            .search_builder(base_object)
+            .scope(Scope::Base)
+            .filter(filter)
+            .size_limit(1)
+            .attributes(["1.1"])
+            .types_only(true)
+            .send()
+            .await
+            .map_err(ValidateFilterError::Begin)?;
+        loop {
+            match results.next().await {
+                Some(Ok(
+                    SearchResult::Entry(_) | SearchResult::Reference(_) | SearchResult::Intermediate { .. },
+                )) => {}
+                Some(Ok(SearchResult::Done {
+                    code: ResultCode::Success | ResultCode::SizeLimitExceeded,
+                    ..
+                })) => return Ok(()),
+                Some(Ok(SearchResult::Done {
+                    code:
+                        code @ (ResultCode::ProtocolError
+                        | ResultCode::UndefinedAttributeType
+                        | ResultCode::InappropriateMatching
+                        | ResultCode::InvalidAttributeSyntax),
+                    diagnostics_message,
+                    ..
+                })) => {
+                    return Err(ValidateFilterError::Rejected {
+                        code,
+                        message: diagnostics_message,
+                    });
+                }
+                Some(Ok(SearchResult::Done {
+                    code,
+                    diagnostics_message,
+                    ..
+                })) => {
+                    return Err(ValidateFilterError::ServerError {
+                        code,
+                        message: diagnostics_message,
+                    });
+                }
+                Some(Err(SearchResultError::ConnectionClosed)) | None => {
+                    return Err(ValidateFilterError::Disconnected);
+                }
+                Some(Err(e)) => return Err(ValidateFilterError::Search(e)),
+            }
+        }
+    }
+    /// Tells the server to stop processing an operation, e.g. a search via [`SearchResults::message_id`].
+    ///
+    /// The server doesn't respond to this, so there is no confirmation the operation actually stopped.
+    pub async fn abandon(&self, message_id: NonZero<i32>) -> Result<(), AbandonError> {
+        self.send_message(RequestProtocolOp::Abandon { message_id })
+            .await
+            .map_err(AbandonError)?;
+        Ok(())
+    }
+}
+
+pub(crate) async fn collect<Output: FromEntry>(
+    connection: &LdapConnection,
+    base_object: &str,
+    scope: Scope,
+    deref_policy: DerefPolicy,
+    filter: Filter<'_>,
+) -> Result<Vec<Output>, CollectError> {
+    let (entries, _) = collect_with_references(connection, base_object, scope, deref_policy, filter).await?;
+    Ok(entries)
+}
+
+/// Like [`collect`], but also returns the URLs of the continuation references
+pub(crate) async fn collect_with_references<Output: FromEntry>(
+    connection: &LdapConnection,
+    base_object: &str,
+    scope: Scope,
+    deref_policy: DerefPolicy,
+    filter: Filter<'_>,
+) -> Result<(Vec<Output>, Vec<Vec<String>>), CollectError> {
+    let mut results = connection
+        .search_as::<Output>(base_object, scope, deref_policy, filter)
+        .await
+        .map_err(CollectError::Begin)?;
+    let mut entries = Vec::new();
+    let mut references = Vec::new();
+    loop {
+        match results.next().await {
+            Some(Ok(SearchResult::Entry(entry))) => entries.push(entry),
+            Some(Ok(SearchResult::Reference(urls))) => references.push(urls),
+            Some(Ok(SearchResult::Intermediate { .. })) => {}
+            Some(Ok(SearchResult::Done {
+                code: ResultCode::Success,
+                ..
+            })) => return Ok((entries, references)),
+            Some(Ok(SearchResult::Done { .. })) if results.was_truncated() => {
+                return Ok((entries, references));
+            }
+            Some(Ok(SearchResult::Done {
+                code,
+                diagnostics_message,
+                ..
+            })) => {
+                return Err(CollectError::ServerError {
+                    code,
+                    message: diagnostics_message,
+                });
+            }
+            Some(Err(SearchResultError::ConnectionClosed)) | None => {
+                return Err(CollectError::Disconnected);
+            }
+            Some(Err(e)) => return Err(CollectError::Search(e)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SearchOneError {
+    Begin(BeginSearchError),
+    Search(SearchResultError),
+    TooManyResults,
+    Disconnected,
+    ServerError { code: ResultCode, message: String },
+}
+impl std::error::Error for SearchOneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Begin(b) => Some(b),
+            Self::Search(s) => Some(s),
+            Self::TooManyResults | Self::Disconnected | Self::ServerError { .. } => None,
+        }
+    }
+}
+impl Display for SearchOneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Begin(b) => b.fmt(f),
+            Self::Search(s) => s.fmt(f),
+            Self::TooManyResults => write!(f, "Search returned more than one entry"),
+            Self::Disconnected => write!(f, "Connection disconnected"),
+            Self::ServerError { code, message } => {
+                write!(f, "Server returned an error. Code: {code} (\"{message}\")",)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadEntryError {
+    /// The entry doesn't exist or isn't visible to the bound identity, with the server's diagnostic message
+    NoSuchObject(String),
+    SearchOne(SearchOneError),
+}
+impl From<SearchOneError> for ReadEntryError {
+    fn from(value: SearchOneError) -> Self {
+        match value {
+            SearchOneError::ServerError {
+                code: ResultCode::NoSuchObject,
+                message,
+            } => Self::NoSuchObject(message),
+            e => Self::SearchOne(e),
+        }
+    }
+}
+impl std::error::Error for ReadEntryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::NoSuchObject(_) => None,
+            Self::SearchOne(e) => Some(e),
+        }
+    }
+}
+impl Display for ReadEntryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSuchObject(message) => write!(f, "Entry does not exist (\"{message}\")"),
+            Self::SearchOne(e) => e.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CountError {
+    Begin(BeginSearchError),
+    Search(SearchResultError),
+    Disconnected,
+    ServerError { code: ResultCode, message: String },
+}
+impl std::error::Error for CountError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Begin(b) => Some(b),
+            Self::Search(s) => Some(s),
+            Self::Disconnected | Self::ServerError { .. } => None,
+        }
+    }
+}
+impl Display for CountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Begin(b) => b.fmt(f),
+            Self::Search(s) => s.fmt(f),
+            Self::Disconnected => write!(f, "Connection disconnected"),
+            Self::ServerError { code, message } => {
+                write!(f, "Server returned an error. Code: {code} (\"{message}\")",)
+            }
+        }
+    }
+}
+
+/// Error of [`LdapConnection::find_all`] and [`RetryingConnection::search_collect`](crate::retry::RetryingConnection::search_collect)
+#[derive(Debug)]
+pub enum CollectError {
+    Begin(BeginSearchError),
+    Search(SearchResultError),
+    Disconnected,
+    ServerError { code: ResultCode, message: String },
+}
+impl Error for CollectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Begin(b) => Some(b),
+            Self::Search(s) => Some(s),
+            Self::Disconnected | Self::ServerError { .. } => None,
+        }
+    }
+}
+impl Display for CollectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Begin(b) => b.fmt(f),
+            Self::Search(s) => s.fmt(f),
+            Self::Disconnected => write!(f, "Connection disconnected"),
+            Self::ServerError { code, message } => {
+                write!(f, "Server returned an error. Code: {code} (\"{message}\")",)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ValidateFilterError {
+    Begin(BeginSearchError),
+    Search(SearchResultError),
+    Disconnected,
+    /// The server refused the filter
+    Rejected {
+        code: ResultCode,
+        message: String,
+    },
+    /// The search failed for a reason unrelated to the filter
+    ServerError {
+        code: ResultCode,
+        message: String,
+    },
+}
+impl std::error::Error for ValidateFilterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Begin(b) => Some(b),
+            Self::Search(s) => Some(s),
+            Self::Disconnected | Self::Rejected { .. } | Self::ServerError { .. } => None,
+        }
+    }
+}
+impl Display for ValidateFilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Begin(b) => b.fmt(f),
+            Self::Search(s) => s.fmt(f),
+            Self::Disconnected => write!(f, "Connection disconnected"),
+            Self::Rejected { code, message } => {
+                write!(f, "Server rejected the filter. Code: {code} (\"{message}\")",)
+            }
+            Self::ServerError { code, message } => {
+                write!(f, "Server returned an error. Code: {code} (\"{message}\")",)
+            }
+        }
+    }
+}
+
+/// Error of [`SearchResults::try_for_each_entry`]
 #[derive(Debug)]
-pub struct BeginSearchError(SendMessageError);
+pub enum SearchOrUserError<E> {
+    Search(SearchResultError),
+    ServerError {
+        code: ResultCode,
+        message: String,
+    },
+    /// The error returned by the callback
+    User(E),
+}
+impl<E: Error + 'static> std::error::Error for SearchOrUserError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Search(s) => Some(s),
+            Self::User(e) => Some(e),
+            Self::ServerError { .. } => None,
+        }
+    }
+}
+impl<E: Display> Display for SearchOrUserError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Search(s) => s.fmt(f),
+            Self::ServerError { code, message } => {
+                write!(f, "Server returned an error. Code: {code} (\"{message}\")",)
+            }
+            Self::User(e) => e.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AbandonError(SendMessageError);
+impl std::error::Error for AbandonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+impl Display for AbandonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to send abandon request: {}", self.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct BeginSearchError(pub(crate) BeginSearchErrorKind);
+#[derive(Debug)]
+pub(crate) enum BeginSearchErrorKind {
+    Send(SendMessageError),
+    EmptyBaseWithNonBaseScope,
+}
 impl BeginSearchError {
     pub fn is_disconnect(&self) -> bool {
         match &self.0 {
-            SendMessageError::Io(error) if error.kind() == ErrorKind::ConnectionReset => true,
-            SendMessageError::ReceiveMessage(ReceiveMessageError::ConnectionClosed) => true,
+            BeginSearchErrorKind::Send(SendMessageError::Io(error)) => {
+                error.kind() == ErrorKind::ConnectionReset
+            }
+            BeginSearchErrorKind::Send(SendMessageError::ReceiveMessage(
+                ReceiveMessageError::ConnectionClosed,
+            )) => true,
             _ => false,
         }
     }
+    /// Whether the search was a one-level or subtree search from the empty base, which is refused unless allowed
+    /// with [`SearchRequestBuilder::allow_empty_base`] or [`LdapConnection::set_allow_empty_base`]. Nothing was sent
+    /// then.
+    pub fn is_empty_base(&self) -> bool {
+        matches!(self.0, BeginSearchErrorKind::EmptyBaseWithNonBaseScope)
+    }
 }
 impl std::error::Error for BeginSearchError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(&self.0)
+        match &self.0 {
+            BeginSearchErrorKind::Send(e) => Some(e),
+            BeginSearchErrorKind::EmptyBaseWithNonBaseScope => None,
+        }
     }
 }
 impl Display for BeginSearchError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Failed to dispatch search request: {:?}", self.0)
+        match &self.0 {
+            BeginSearchErrorKind::Send(e) => write!(f, "Failed to dispatch search request: {e:?}"),
+            BeginSearchErrorKind::EmptyBaseWithNonBaseScope => write!(
+                f,
+                "Search from the empty base needs base object scope or an explicit opt-in"
+            ),
+        }
     }
 }
 
 pub struct SearchResults<Output = RawEntry> {
+    message_id: NonZero<i32>,
     incoming_messages: UnboundedReceiver<Result<Vec<u8>, ReceiveMessageError>>,
     buffer: VecDeque<u8>,
-    done: Option<Sender<()>>,
+    done: Option<Sender<SearchEnd>>,
+    deadline: Option<Instant>,
+    /// Set once the deadline passed, the search is abandoned then and yields nothing more
+    timed_out: bool,
+    final_result: Option<(ResultCode, String)>,
+    response_controls: Vec<Control>,
+    resumed_page: bool,
+    bytes_received: u64,
     _e: PhantomData<Output>,
 }
+impl<Output> SearchResults<Output> {
+    /// Message ID of the search request, used to cancel it
+    pub fn message_id(&self) -> NonZero<i32> {
+        self.message_id
+    }
+    /// Whether the server stopped the search early because of a size, time or administrative limit.
+    ///
+    /// All entries the server found are still yielded before [`SearchResult::Done`], so these are partial results.
+    pub fn was_truncated(&self) -> bool {
+        matches!(
+            self.final_result,
+            Some((
+                ResultCode::SizeLimitExceeded
+                    | ResultCode::TimeLimitExceeded
+                    | ResultCode::AdminLimitExceeded,
+                _
+            ))
+        )
+    }
+    /// The result code and diagnostic message of [`SearchResult::Done`], `None` until it was received.
+    ///
+    /// This stays available after iterating, e.g. to log how a search ended even if it succeeded.
+    pub fn final_result(&self) -> Option<(ResultCode, &str)> {
+        self.final_result
+            .as_ref()
+            .map(|(code, message)| (*code, message.as_str()))
+    }
+    /// Controls the server attached to [`SearchResult::Done`], empty until it was received
+    pub fn response_controls(&self) -> &[Control] {
+        &self.response_controls
+    }
+    /// Bytes of the responses to this search taken from the connection so far, e.g. to spot unexpectedly large
+    /// result sets.
+    ///
+    /// This counts each message without its envelope of a few bytes, i.e. the sequence header and message ID.
+    /// [`LdapConnection::bytes_received`] counts whole messages of all operations.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+    /// Sets the point in time after which waiting for more results fails with [`SearchResultError::Timeout`].
+    ///
+    /// Unlike a timeout on each read, this bounds the total time of the search. It defaults to the start of the search
+    /// plus [`LdapConnection::operation_timeout`].
+    pub fn set_deadline(&mut self, deadline: Option<std::time::Instant>) {
+        self.deadline = deadline.map(Instant::from_std);
+    }
+    /// Gives up on the search unless it's over, so the server stops working on it and the connection forgets it
+    pub(crate) fn give_up(&mut self) {
+        if let Some(shutdown) = self.done.take() {
+            let _ = shutdown.send(SearchEnd::Abandon);
+        }
+    }
+}
 impl<Output: FromEntry> SearchResults<Output> {
+    /// Waits for the next result of the search.
+    ///
+    /// Once the deadline passed, this returns [`SearchResultError::Timeout`] and abandons the search, and `None`
+    /// after that.
     pub async fn next(&mut self) -> Option<Result<SearchResult<Output>, SearchResultError>> {
-        let res = if !self.buffer.is_empty() {
-            read_search_as::<Output, _>(&mut self.buffer)
-        } else {
-            match self.incoming_messages.recv().await {
-                Some(Ok(body)) => {
-                    self.buffer = body.into();
-                    read_search_as::<Output, _>(&mut self.buffer)
-                }
-                Some(Err(ReceiveMessageError::ConnectionClosed)) | None => {
-                    if let Some(shutdown) = self.done.take() {
-                        let _ = shutdown.send(());
-                    }
-                    return None;
+        if self.timed_out {
+            return None;
+        }
+        if !self.buffer.is_empty() {
+            return Some(self.read_buffered());
+        }
+        let message = match self.deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, self.incoming_messages.recv()).await {
+                Ok(message) => message,
+                Err(_) => return Some(self.time_out()),
+            },
+            None => self.incoming_messages.recv().await,
+        };
+        self.handle_message(message)
+    }
+    /// Like [`SearchResults::next`], but returns `None` instead of waiting if no response has arrived yet.
+    ///
+    /// This lets a single loop poll many searches, e.g. from a thread outside the async runtime; the connection's
+    /// background task keeps receiving in the meantime. Like `next`, this also returns `None` once the search is
+    /// over, so stop polling after [`SearchResult::Done`] or an error.
+    pub fn try_next(&mut self) -> Option<Result<SearchResult<Output>, SearchResultError>> {
+        if self.timed_out {
+            return None;
+        }
+        if !self.buffer.is_empty() {
+            return Some(self.read_buffered());
+        }
+        let message = match self.incoming_messages.try_recv() {
+            Ok(message) => Some(message),
+            Err(TryRecvError::Empty) => {
+                if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return Some(self.time_out());
                 }
+                return None;
             }
+            Err(TryRecvError::Disconnected) => None,
         };
+        self.handle_message(message)
+    }
+    fn time_out(&mut self) -> Result<SearchResult<Output>, SearchResultError> {
+        self.timed_out = true;
+        self.give_up();
+        Err(SearchResultError::Timeout)
+    }
+    fn handle_message(
+        &mut self,
+        message: Option<Result<Vec<u8>, ReceiveMessageError>>,
+    ) -> Option<Result<SearchResult<Output>, SearchResultError>> {
+        match message {
+            // the search is over, whatever the message is
+            Some(Ok(_)) if self.final_result.is_some() => Some(Err(SearchResultError::MessageAfterDone)),
+            Some(Ok(body)) => {
+                self.bytes_received += body.len() as u64;
+                self.buffer = body.into();
+                Some(self.read_buffered())
+            }
+            Some(Err(ReceiveMessageError::ConnectionClosed)) | None => {
+                // the server hung up before finishing the search
+                if let Some(shutdown) = self.done.take() {
+                    let _ = shutdown.send(SearchEnd::Finished);
+                    return Some(Err(SearchResultError::ConnectionClosed));
+                }
+                None
+            }
+        }
+    }
+    fn read_buffered(&mut self) -> Result<SearchResult<Output>, SearchResultError> {
+        let res = read_search_as::<Output, _>(&mut self.buffer);
         if let Err(SearchResultError::CouldNotReadSize) = res {
             self.buffer.clear();
         }
-        if let Ok(SearchResult::Done { .. }) = res
-            && let Some(shutdown) = self.done.take()
+        // what's left of the message are its controls
+        let controls = control::read_controls(self.buffer.make_contiguous());
+        self.buffer.clear();
+        // the search is over even if the controls of its end are malformed
+        let is_done = matches!(res, Ok(SearchResult::Done { .. }));
+        if let Ok(SearchResult::Done {
+            code,
+            diagnostics_message,
+            ..
+        }) = &res
         {
-            let _ = shutdown.send(());
+            self.final_result = Some((*code, diagnostics_message.clone()));
+            if let Some(shutdown) = self.done.take() {
+                let _ = shutdown.send(SearchEnd::Finished);
+            }
+        }
+        let Some(controls) = controls else {
+            return Err(SearchResultError::InvalidSchema);
+        };
+        if is_done {
+            self.response_controls = controls;
+        }
+        res
+    }
+    /// Calls `f` for every entry until the search is done, stopping at the first error.
+    ///
+    /// Errors of the search and of `f` are kept apart in [`SearchOrUserError`]. Searches stopped by a size, time or
+    /// administrative limit succeed, check [`SearchResults::was_truncated`] for those.
+    pub async fn try_for_each_entry<E>(
+        &mut self,
+        mut f: impl FnMut(Output) -> Result<(), E>,
+    ) -> Result<(), SearchOrUserError<E>> {
+        while let Some(result) = self.next().await {
+            match result.map_err(SearchOrUserError::Search)? {
+                SearchResult::Entry(entry) => f(entry).map_err(SearchOrUserError::User)?,
+                SearchResult::Reference(_) | SearchResult::Intermediate { .. } => {}
+                SearchResult::Done {
+                    code: ResultCode::Success,
+                    ..
+                } => return Ok(()),
+                SearchResult::Done { .. } if self.was_truncated() => return Ok(()),
+                SearchResult::Done {
+                    code,
+                    diagnostics_message,
+                    ..
+                } => {
+                    return Err(SearchOrUserError::ServerError {
+                        code,
+                        message: diagnostics_message,
+                    });
+                }
+            }
         }
-        Some(res)
+        Ok(())
     }
 }
 
@@ -178,7 +932,7 @@ pub(crate) fn read_search_as<E: FromEntry, R: Read>(
                 return Err(SearchResultError::InvalidSchema);
             };
             let Ok(object_name) = String::from_utf8(name_bytes) else {
-                return Err(SearchResultError::InvalidSchema);
+                return Err(SearchResultError::InvalidUtf8 { field: "objectName" });
             };
             let Ok(UNIVERSAL_SEQUENCE) = bytes.read_single_byte() else {
                 return Err(SearchResultError::InvalidSchema);
@@ -186,7 +940,9 @@ pub(crate) fn read_search_as<E: FromEntry, R: Read>(
             let attr_list_len = bytes
                 .read_ber_length()
                 .map_err(|_| SearchResultError::InvalidSchema)?;
-            assert_eq!(bytes.len(), attr_list_len);
+            if bytes.len() != attr_list_len {
+                return Err(SearchResultError::InvalidSchema);
+            }
             let mut attributes = Vec::<Attribute>::new();
             while !bytes.is_empty() {
                 let Ok(UNIVERSAL_SEQUENCE) = bytes.read_single_byte() else {
@@ -206,12 +962,15 @@ pub(crate) fn read_search_as<E: FromEntry, R: Read>(
                 let strlen = attr_reader
                     .read_ber_length()
                     .map_err(|_| SearchResultError::InvalidSchema)?;
-                let mut attr_type = String::new();
-                attr_reader
-                    .by_ref()
-                    .take(strlen as u64)
-                    .read_to_string(&mut attr_type)
-                    .map_err(SearchResultError::Io)?;
+                let Some((attr_type, rest)) = attr_reader.split_at_checked(strlen) else {
+                    return Err(SearchResultError::InvalidSchema);
+                };
+                attr_reader = rest;
+                let Ok(attr_type) = String::from_utf8(attr_type.to_vec()) else {
+                    return Err(SearchResultError::InvalidUtf8 {
+                        field: "attribute type",
+                    });
+                };
 
                 let mut attr_values = Vec::new();
                 let Ok(UNIVERSAL_SET) = attr_reader.read_single_byte() else {
@@ -252,7 +1011,7 @@ pub(crate) fn read_search_as<E: FromEntry, R: Read>(
             let code = int
                 .try_into()
                 .ok()
-                .and_then(ResultCode::from_code)
+                .and_then(ResultCode::from_u16)
                 .ok_or(SearchResultError::InvalidSchema)?;
             // matched dn
             if bytes
@@ -293,9 +1052,76 @@ pub(crate) fn read_search_as<E: FromEntry, R: Read>(
                 diagnostics_message,
             })
         }
-        19 => Ok(SearchResult::Reference),
-        _ => todo!(),
+        19 => read_reference(bytes).map(SearchResult::Reference),
+        24 => match extended::read_response(bytes) {
+            Ok(response) => Err(SearchResultError::ExtendedResponse {
+                code: ResultCode::Success,
+                message: response.diagnostics_message,
+            }),
+            Err(ReadExtendedError::ServerError { code, message }) => {
+                Err(SearchResultError::ExtendedResponse { code, message })
+            }
+            Err(ReadExtendedError::Io(_) | ReadExtendedError::InvalidSchema) => {
+                Err(SearchResultError::InvalidSchema)
+            }
+        },
+        25 => {
+            let (name, value) = read_intermediate(bytes)?;
+            Ok(SearchResult::Intermediate { name, value })
+        }
+        _ => Err(SearchResultError::UnexpectedProtocolOp { tag, body: this_msg }),
+    }
+}
+
+/// Reads the URLs of a SearchResultReference, `SEQUENCE OF URI` with the sequence tag replaced
+fn read_reference(mut bytes: &[u8]) -> Result<Vec<String>, SearchResultError> {
+    let mut urls = Vec::new();
+    while !bytes.is_empty() {
+        let Ok(OCTET_STRING) = bytes.read_single_byte() else {
+            return Err(SearchResultError::InvalidSchema);
+        };
+        let len = read_length(&mut bytes)?;
+        let Some((url, rest)) = bytes.split_at_checked(len) else {
+            return Err(SearchResultError::InvalidSchema);
+        };
+        bytes = rest;
+        let Ok(url) = String::from_utf8(url.to_vec()) else {
+            return Err(SearchResultError::InvalidUtf8 { field: "URI" });
+        };
+        urls.push(url);
+    }
+    if urls.is_empty() {
+        return Err(SearchResultError::InvalidSchema);
+    }
+    Ok(urls)
+}
+/// Reads the optional `responseName` and `responseValue` of an IntermediateResponse
+fn read_intermediate(mut bytes: &[u8]) -> Result<(Option<String>, Option<Vec<u8>>), SearchResultError> {
+    const RESPONSE_NAME: u8 = TagClass::ContextSpecific.into_bits();
+    const RESPONSE_VALUE: u8 = TagClass::ContextSpecific.into_bits() | 0x1;
+    let mut name = None;
+    let mut value = None;
+    while !bytes.is_empty() {
+        let tag = bytes.read_single_byte().map_err(SearchResultError::Io)?;
+        let len = read_length(&mut bytes)?;
+        let Some((body, rest)) = bytes.split_at_checked(len) else {
+            return Err(SearchResultError::InvalidSchema);
+        };
+        bytes = rest;
+        match tag {
+            RESPONSE_NAME if name.is_none() && value.is_none() => {
+                let Ok(oid) = String::from_utf8(body.to_vec()) else {
+                    return Err(SearchResultError::InvalidUtf8 {
+                        field: "responseName",
+                    });
+                };
+                name = Some(oid);
+            }
+            RESPONSE_VALUE if value.is_none() => value = Some(body.to_vec()),
+            _ => return Err(SearchResultError::InvalidSchema),
+        }
     }
+    Ok((name, value))
 }
 #[derive(Debug)]
 pub enum SearchResultError {
@@ -303,6 +1129,29 @@ pub enum SearchResultError {
     Io(std::io::Error),
     InvalidEntry(FailedToGetFromEntry),
     InvalidSchema,
+    /// The server sent a DN or attribute type that isn't valid UTF-8, which LDAPv3 requires
+    InvalidUtf8 {
+        field: &'static str,
+    },
+    /// The deadline of the search passed before it completed, so it was abandoned
+    Timeout,
+    /// The connection closed before the search completed, unlike [`SearchResultError::Io`] this isn't a read error
+    ConnectionClosed,
+    /// The server answered the search with an extended response instead of a search result,
+    /// usually because it can't continue the search
+    ExtendedResponse {
+        code: ResultCode,
+        message: String,
+    },
+    /// A protocol op that doesn't belong to a search, with its tag and content
+    UnexpectedProtocolOp {
+        tag: u8,
+        body: Vec<u8>,
+    },
+    /// The server sent another response after [`SearchResult::Done`], which a well-behaved server never does.
+    ///
+    /// Responses are routed by message ID, so the stray response doesn't affect other operations on the connection.
+    MessageAfterDone,
 }
 impl From<LengthError> for SearchResultError {
     fn from(value: LengthError) -> Self {
@@ -315,7 +1164,14 @@ impl From<LengthError> for SearchResultError {
 impl std::error::Error for SearchResultError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            Self::InvalidSchema | Self::CouldNotReadSize => None,
+            Self::InvalidSchema
+            | Self::InvalidUtf8 { .. }
+            | Self::CouldNotReadSize
+            | Self::Timeout
+            | Self::ConnectionClosed
+            | Self::ExtendedResponse { .. }
+            | Self::UnexpectedProtocolOp { .. }
+            | Self::MessageAfterDone => None,
             Self::Io(io) => Some(io),
             Self::InvalidEntry(ie) => Some(ie),
         }
@@ -327,7 +1183,15 @@ impl Display for SearchResultError {
             Self::InvalidEntry(i) => write!(f, "Entry did not match struct: {i}"),
             Self::CouldNotReadSize => write!(f, "failed to read message size"),
             Self::InvalidSchema => write!(f, "Invalid LDAP message"),
+            Self::InvalidUtf8 { field } => write!(f, "Invalid LDAP message: {field} is not valid UTF-8"),
             Self::Io(io) => write!(f, "failed to read LDAP message: {io}"),
+            Self::Timeout => write!(f, "search did not complete before its deadline"),
+            Self::ConnectionClosed => write!(f, "connection closed before the search completed"),
+            Self::ExtendedResponse { code, message } => {
+                write!(f, "server interrupted the search. Code: {code} (\"{message}\")")
+            }
+            Self::UnexpectedProtocolOp { tag, .. } => write!(f, "unexpected protocol op with tag {tag:#04x}"),
+            Self::MessageAfterDone => write!(f, "server sent a response after the search was done"),
         }
     }
 }
@@ -335,7 +1199,15 @@ impl Display for SearchResultError {
 #[derive(Debug)]
 pub enum SearchResult<T = RawEntry> {
     Entry(T),
-    Reference,
+    /// A continuation reference: part of the searched subtree is held by other servers. The URLs are alternatives,
+    /// see [`select_referral`](crate::url::select_referral) and [`LdapConnection::find_all_chasing`].
+    Reference(Vec<String>),
+    /// An IntermediateResponse interleaved with the entries, e.g. the sync info messages of the Content
+    /// Synchronization control. `name` is the OID identifying the kind of response, if the server sent one.
+    Intermediate {
+        name: Option<String>,
+        value: Option<Vec<u8>>,
+    },
     Done {
         code: ResultCode,
         matched_dn: String,
@@ -343,39 +1215,37 @@ pub enum SearchResult<T = RawEntry> {
     },
 }
 
-pub(crate) fn write_search<'a>(
-    base_object: &str,
-    scope: Scope,
-    deref_policy: DerefPolicy,
-    filter: &Filter<'_>,
-    attributes: impl IntoIterator<Item = &'a str>,
-) -> Vec<u8> {
+pub(crate) fn write_search(request: &SearchRequest<'_>, rules: EncodingRules) -> Vec<u8> {
     let mut out = Vec::new();
     out.push(OCTET_STRING);
-    out.write_ber_length(base_object.len()).unwrap();
-    out.extend_from_slice(base_object.as_bytes());
+    out.write_ber_length(request.base_object.len()).unwrap();
+    out.extend_from_slice(request.base_object.as_bytes());
 
     // scope
-    write_integer_with_tag(&mut out, UNIVERSAL_ENUMERATED, scope.as_num().into());
+    write_integer_with_tag(&mut out, UNIVERSAL_ENUMERATED, request.scope.as_num().into());
 
     // deref Aliases
-    write_integer_with_tag(&mut out, UNIVERSAL_ENUMERATED, deref_policy.as_num().into());
+    write_integer_with_tag(
+        &mut out,
+        UNIVERSAL_ENUMERATED,
+        request.deref_policy.as_num().into(),
+    );
 
     // size limit
-    write_integer_with_tag(&mut out, UNIVERSAL_INTEGER, 0);
+    write_integer_with_tag(&mut out, UNIVERSAL_INTEGER, request.size_limit);
 
     // time limit
-    write_integer_with_tag(&mut out, UNIVERSAL_INTEGER, 0);
+    write_integer_with_tag(&mut out, UNIVERSAL_INTEGER, request.time_limit);
 
     // types only
     out.push(UNIVERSAL_BOOLEAN);
     out.write_ber_length(1).unwrap();
-    out.push(0x00);
+    out.push(if request.types_only { 0xFF } else { 0x00 });
 
-    filter.write_into(&mut out).unwrap();
+    request.filter.write_into(&mut out, rules).unwrap();
 
     out.write_sequence(UNIVERSAL_SEQUENCE, move |attr_sequence| {
-        for attr in attributes {
+        for attr in &request.attributes {
             attr_sequence.push(OCTET_STRING);
             attr_sequence.write_ber_length(attr.len()).unwrap();
             attr_sequence.extend_from_slice(attr.as_bytes());
@@ -405,6 +1275,70 @@ pub struct Attribute {
     pub r#type: String,
     pub values: Vec<Vec<u8>>,
 }
+impl RawEntry {
+    /// All values of an attribute, empty if the entry doesn't have it.
+    ///
+    /// Like all accessors, this matches the attribute description with [`description_matches`], so the case and
+    /// order of options don't matter and a `;binary` option may be missing on either side.
+    pub fn get_all(&self, name: &str) -> &[Vec<u8>] {
+        self.attributes
+            .iter()
+            .find(|attr| description_matches(name, &attr.r#type))
+            .map_or(&[], |attr| &attr.values)
+    }
+    /// The first value of an attribute
+    pub fn get_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.get_all(name).first().map(Vec::as_slice)
+    }
+    /// The first value of an attribute as text, e.g. a `cn`
+    pub fn get_str(&self, name: &str) -> Option<Result<&str, Utf8Error>> {
+        self.get_bytes(name).map(std::str::from_utf8)
+    }
+    /// The first value of an attribute parsed like a field of a derived [`FromEntry`] struct,
+    /// e.g. `entry.get_parsed::<u32>("uidNumber")`
+    #[cfg(feature = "from_octets")]
+    pub fn get_parsed<T: FromOctetString>(&self, name: &str) -> Option<Result<T, T::Err>> {
+        self.get_bytes(name).map(T::from_octet_string)
+    }
+    /// Moves the values of an attribute out of the entry, removing the attribute.
+    ///
+    /// Unlike cloning from [`RawEntry::attributes`], this doesn't copy large values like `jpegPhoto`.
+    pub fn take_attribute(&mut self, name: &str) -> Option<Vec<Vec<u8>>> {
+        let idx = self
+            .attributes
+            .iter()
+            .position(|attr| description_matches(name, &attr.r#type))?;
+        Some(self.attributes.remove(idx).values)
+    }
+    /// Sorts the attributes by their position in `order`, e.g. for fixed-column exports.
+    ///
+    /// Attributes are matched like in [`RawEntry::get_all`]. Attributes missing from `order` move to the end,
+    /// keeping the order the server sent them in.
+    pub fn reorder(&mut self, order: &[&str]) {
+        self.attributes.sort_by_key(|attr| {
+            order
+                .iter()
+                .position(|name| description_matches(name, &attr.r#type))
+                .unwrap_or(order.len())
+        });
+    }
+}
+/// Renders the entry like `ldapsearch -LLL` does, with non-text values base64-encoded
+impl Display for RawEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        ldif::write_line(f, "dn", self.object_name.as_bytes())?;
+        for attr in &self.attributes {
+            // types-only searches return attributes without values
+            if attr.values.is_empty() {
+                writeln!(f, "{}:", attr.r#type)?;
+            }
+            for value in &attr.values {
+                ldif::write_line(f, &attr.r#type, value)?;
+            }
+        }
+        Ok(())
+    }
+}
 impl FromEntry for RawEntry {
     fn from_entry(entry: RawEntry) -> Result<Self, FailedToGetFromEntry> {
         Ok(entry)
@@ -414,11 +1348,33 @@ impl FromEntry for RawEntry {
 pub trait FromEntry: Sized {
     fn from_entry(entry: RawEntry) -> Result<Self, FailedToGetFromEntry>;
 
+    /// Attributes to request, `None` requests all user attributes.
+    ///
+    /// The derive macro lists them in field declaration order. Servers may return attributes in any order though,
+    /// see [`RawEntry::reorder`] for a deterministic order.
+    #[must_use]
+    fn attributes() -> Option<impl Iterator<Item = &'static str>> {
+        None::<std::iter::Empty<&str>>
+    }
+}
+/// Like [`FromEntry`], but borrows from the entry instead of copying its values, e.g. into `&'a str` fields.
+///
+/// `#[derive(Entry)]` implements this instead of [`FromEntry`] for structs with a lifetime. Search for [`RawEntry`]
+/// and parse each entry with [`FromEntryRef::from_entry_ref`].
+pub trait FromEntryRef<'a>: Sized {
+    fn from_entry_ref(entry: &'a RawEntry) -> Result<Self, FailedToGetFromEntry>;
+
+    /// Attributes to request, `None` requests all user attributes. See [`FromEntry::attributes`].
     #[must_use]
     fn attributes() -> Option<impl Iterator<Item = &'static str>> {
         None::<std::iter::Empty<&str>>
     }
 }
+impl<'a> FromEntryRef<'a> for &'a RawEntry {
+    fn from_entry_ref(entry: &'a RawEntry) -> Result<Self, FailedToGetFromEntry> {
+        Ok(entry)
+    }
+}
 #[derive(Debug)]
 pub enum FailedToGetFromEntry {
     MissingField(&'static str),
@@ -457,3 +1413,458 @@ pub trait FromMultipleOctetStrings: Sized {
     type Err: Error;
     fn from_multiple_octet_strings<'a>(values: impl Iterator<Item = &'a [u8]>) -> Result<Self, Self::Err>;
 }
+
+#[cfg(feature = "from_octets")]
+/// Single value parsing that may borrow from the value, used for structs deriving [`FromEntryRef`].
+///
+/// Everything implementing [`FromOctetString`] implements this as well.
+pub trait FromOctetStringRef<'a>: Sized {
+    type Err: Error;
+    fn from_octet_string_ref(bytes: &'a [u8]) -> Result<Self, Self::Err>;
+}
+
+#[cfg(feature = "from_octets")]
+/// Multiple value parsing that may borrow from the values, used for structs deriving [`FromEntryRef`].
+///
+/// Only implemented for collections of [`FromOctetStringRef`] types, not for every [`FromMultipleOctetStrings`] type.
+pub trait FromMultipleOctetStringsRef<'a>: Sized {
+    type Err: Error;
+    fn from_multiple_octet_strings_ref(values: impl Iterator<Item = &'a [u8]>) -> Result<Self, Self::Err>;
+}
+
+#[cfg(test)]
+mod test {
+    use std::{marker::PhantomData, num::NonZero};
+
+    use super::{
+        Attribute, PageCookie, RawEntry, ReadEntryError, SearchOneError, SearchOrUserError, SearchResult,
+        SearchResultError, SearchResults, read_search_as,
+    };
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    use crate::{
+        ReceiveMessageError, SearchEnd,
+        control::{Control, write_controls},
+        result::ResultCode,
+    };
+
+    const TYPES_ONLY_ENTRY: [u8; 18] = [
+        0x64, 0x10, 0x04, 0x04, b'c', b'n', b'=', b'a', 0x30, 0x08, 0x30, 0x06, 0x04, 0x02, b'c', b'n', 0x31,
+        0x00,
+    ];
+
+    /// Results of a search with message ID 1 reading its messages from `incoming_messages`
+    fn results(
+        incoming_messages: UnboundedReceiver<Result<Vec<u8>, ReceiveMessageError>>,
+    ) -> SearchResults<RawEntry> {
+        SearchResults {
+            message_id: NonZero::new(1).unwrap(),
+            incoming_messages,
+            buffer: Default::default(),
+            done: None,
+            deadline: None,
+            timed_out: false,
+            final_result: None,
+            response_controls: Vec::new(),
+            resumed_page: false,
+            bytes_received: 0,
+            _e: PhantomData,
+        }
+    }
+
+    #[tokio::test]
+    async fn find_all_collects_entries() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        use crate::{search::Filter, test::mock_connection};
+        let (connection, mut server) = mock_connection().await;
+        tokio::spawn(async move {
+            assert!(server.read(&mut [0; 256]).await.unwrap() > 0);
+            let mut responses = vec![0x30, 0x15, 0x02, 0x01, 0x01];
+            responses.extend_from_slice(&TYPES_ONLY_ENTRY);
+            responses.extend_from_slice(&[
+                0x30, 0x0c, 0x02, 0x01, 0x01, 0x65, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00,
+            ]);
+            server.write_all(&responses).await.unwrap();
+            // keep the connection open until the client is done
+            let _ = server.read(&mut [0; 1]).await;
+        });
+        let entries: Vec<RawEntry> = connection
+            .find_all("dc=x", Filter::Present("objectClass"))
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].object_name, "cn=a");
+    }
+
+    #[tokio::test]
+    async fn empty_base_needs_opt_in() {
+        use tokio::io::AsyncReadExt;
+
+        use crate::{
+            search::{DerefPolicy, Filter, Scope},
+            test::mock_connection,
+        };
+        let (mut connection, mut server) = mock_connection().await;
+
+        let Err(error) = connection
+            .search_all("", Scope::SingleLevel, DerefPolicy::Never, Filter::Present("cn"))
+            .await
+        else {
+            panic!("search from the empty base was sent");
+        };
+        assert!(error.is_empty_base());
+
+        let _root_dse = connection
+            .search_all("", Scope::Base, DerefPolicy::Never, Filter::Present("cn"))
+            .await
+            .unwrap();
+        let _global_catalog = connection
+            .search_builder("")
+            .allow_empty_base(true)
+            .send()
+            .await
+            .unwrap();
+        connection.set_allow_empty_base(true);
+        let _whole_directory = connection
+            .search_all("", Scope::SingleLevel, DerefPolicy::Never, Filter::Present("cn"))
+            .await
+            .unwrap();
+        assert!(server.read(&mut [0; 256]).await.unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn timeout_abandons_search() {
+        use tokio::io::AsyncReadExt;
+
+        use crate::{
+            search::{DerefPolicy, Filter, Scope},
+            test::mock_connection,
+        };
+        let (connection, mut server) = mock_connection().await;
+        let mut results = connection
+            .search_all(
+                "dc=x",
+                Scope::WholeSubtree,
+                DerefPolicy::Never,
+                Filter::Present("cn"),
+            )
+            .await
+            .unwrap();
+        let mut search = vec![0; connection.bytes_sent() as usize];
+        server.read_exact(&mut search).await.unwrap();
+        results.set_deadline(Some(std::time::Instant::now()));
+        assert!(matches!(
+            results.next().await,
+            Some(Err(SearchResultError::Timeout))
+        ));
+        assert!(results.next().await.is_none());
+        assert!(results.try_next().is_none());
+
+        let mut abandon = [0; 8];
+        server.read_exact(&mut abandon).await.unwrap();
+        // message ID 2, abandoning the search with ID 1
+        assert_eq!(abandon, [0x30, 0x06, 0x02, 0x01, 0x02, 0x50, 0x01, 0x01]);
+    }
+
+    #[tokio::test]
+    async fn message_after_done() {
+        let (sx, incoming_messages) = tokio::sync::mpsc::unbounded_channel();
+        let (done, _) = tokio::sync::oneshot::channel();
+        let mut results = results(incoming_messages);
+        results.done = Some(done);
+        sx.send(Ok(vec![0x65, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00]))
+            .unwrap();
+        sx.send(Ok(TYPES_ONLY_ENTRY.to_vec())).unwrap();
+        drop(sx);
+        assert!(matches!(
+            results.next().await,
+            Some(Ok(SearchResult::Done { .. }))
+        ));
+        assert!(matches!(
+            results.next().await,
+            Some(Err(SearchResultError::MessageAfterDone))
+        ));
+        assert!(results.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn final_result_after_success() {
+        let (sx, incoming_messages) = tokio::sync::mpsc::unbounded_channel();
+        let mut results = results(incoming_messages);
+        sx.send(Ok(TYPES_ONLY_ENTRY.to_vec())).unwrap();
+        sx.send(Ok(vec![
+            0x65, 0x09, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x02, b'o', b'k',
+        ]))
+        .unwrap();
+        drop(sx);
+        assert!(matches!(results.next().await, Some(Ok(SearchResult::Entry(_)))));
+        assert!(results.final_result().is_none());
+        assert!(matches!(
+            results.next().await,
+            Some(Ok(SearchResult::Done { .. }))
+        ));
+        assert!(results.next().await.is_none());
+        assert!(matches!(
+            results.final_result(),
+            Some((ResultCode::Success, "ok"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn size_limit_keeps_entries() {
+        let (sx, incoming_messages) = tokio::sync::mpsc::unbounded_channel();
+        let mut results = results(incoming_messages);
+        sx.send(Ok(TYPES_ONLY_ENTRY.to_vec())).unwrap();
+        sx.send(Ok(TYPES_ONLY_ENTRY.to_vec())).unwrap();
+        sx.send(Ok(vec![0x65, 0x07, 0x0a, 0x01, 0x04, 0x04, 0x00, 0x04, 0x00]))
+            .unwrap();
+        assert!(matches!(results.next().await, Some(Ok(SearchResult::Entry(_)))));
+        assert!(matches!(results.next().await, Some(Ok(SearchResult::Entry(_)))));
+        assert!(!results.was_truncated());
+        assert!(matches!(
+            results.next().await,
+            Some(Ok(SearchResult::Done {
+                code: ResultCode::SizeLimitExceeded,
+                ..
+            }))
+        ));
+        assert!(results.was_truncated());
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_in_entry() {
+        let (sx, incoming_messages) = tokio::sync::mpsc::unbounded_channel();
+        let mut results = results(incoming_messages);
+        let mut invalid_name = TYPES_ONLY_ENTRY;
+        invalid_name[7] = 0xff;
+        let mut invalid_type = TYPES_ONLY_ENTRY;
+        invalid_type[14] = 0xc3;
+        sx.send(Ok(invalid_name.to_vec())).unwrap();
+        sx.send(Ok(invalid_type.to_vec())).unwrap();
+        sx.send(Ok(TYPES_ONLY_ENTRY.to_vec())).unwrap();
+        assert!(matches!(
+            results.next().await,
+            Some(Err(SearchResultError::InvalidUtf8 { field: "objectName" }))
+        ));
+        assert!(matches!(
+            results.next().await,
+            Some(Err(SearchResultError::InvalidUtf8 {
+                field: "attribute type"
+            }))
+        ));
+        // the search goes on with the next message
+        assert!(matches!(results.next().await, Some(Ok(SearchResult::Entry(_)))));
+    }
+
+    #[test]
+    fn poll_without_waiting() {
+        let (sx, incoming_messages) = tokio::sync::mpsc::unbounded_channel();
+        let (done, _) = tokio::sync::oneshot::channel();
+        let mut results = results(incoming_messages);
+        results.done = Some(done);
+        assert!(results.try_next().is_none());
+        sx.send(Ok(TYPES_ONLY_ENTRY.to_vec())).unwrap();
+        assert!(matches!(results.try_next(), Some(Ok(SearchResult::Entry(_)))));
+        assert_eq!(results.bytes_received(), TYPES_ONLY_ENTRY.len() as u64);
+        assert!(results.try_next().is_none());
+        drop(sx);
+        assert!(matches!(
+            results.try_next(),
+            Some(Err(SearchResultError::ConnectionClosed))
+        ));
+    }
+
+    #[tokio::test]
+    async fn classify_other_protocol_ops() {
+        let (sx, incoming_messages) = tokio::sync::mpsc::unbounded_channel();
+        let mut results = results(incoming_messages);
+        // IntermediateResponse with the name 1.2 and the value 0x05
+        sx.send(Ok(vec![
+            0x79, 0x08, 0x80, 0x03, b'1', b'.', b'2', 0x81, 0x01, 0x05,
+        ]))
+        .unwrap();
+        // ExtendedResponse with unavailable (52) and the message "bye"
+        sx.send(Ok(vec![
+            0x78, 0x0a, 0x0a, 0x01, 0x34, 0x04, 0x00, 0x04, 0x03, b'b', b'y', b'e',
+        ]))
+        .unwrap();
+        // a ModifyResponse doesn't belong to a search
+        sx.send(Ok(vec![0x67, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00]))
+            .unwrap();
+        assert!(matches!(
+            results.next().await,
+            Some(Ok(SearchResult::Intermediate { name: Some(name), value: Some(value) }))
+                if name == "1.2" && value == [0x05]
+        ));
+        assert!(matches!(
+            results.next().await,
+            Some(Err(SearchResultError::ExtendedResponse { code: ResultCode::Unavailable, message }))
+                if message == "bye"
+        ));
+        assert!(matches!(
+            results.next().await,
+            Some(Err(SearchResultError::UnexpectedProtocolOp { tag: 0x67, body })) if body.len() == 7
+        ));
+    }
+
+    #[tokio::test]
+    async fn malformed_controls_still_end_search() {
+        let (sx, incoming_messages) = tokio::sync::mpsc::unbounded_channel();
+        let (done, mut finished) = tokio::sync::oneshot::channel();
+        let mut results = results(incoming_messages);
+        results.done = Some(done);
+        // a successful SearchResultDone, then controls that claim more bytes than follow
+        sx.send(Ok(vec![
+            0x65, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00, 0xa0, 0x05, 0x30,
+        ]))
+        .unwrap();
+        assert!(matches!(
+            results.next().await,
+            Some(Err(SearchResultError::InvalidSchema))
+        ));
+        assert!(matches!(results.final_result(), Some((ResultCode::Success, ""))));
+        assert!(matches!(finished.try_recv(), Ok(SearchEnd::Finished)));
+        drop(sx);
+        assert!(results.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn connection_closed_mid_search() {
+        let (sx, incoming_messages) = tokio::sync::mpsc::unbounded_channel();
+        let (done, _) = tokio::sync::oneshot::channel();
+        let mut results = results(incoming_messages);
+        results.done = Some(done);
+        sx.send(Ok(TYPES_ONLY_ENTRY.to_vec())).unwrap();
+        drop(sx);
+        assert!(matches!(results.next().await, Some(Ok(SearchResult::Entry(_)))));
+        assert!(matches!(
+            results.next().await,
+            Some(Err(SearchResultError::ConnectionClosed))
+        ));
+        assert!(results.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn try_for_each_entry_keeps_user_error() {
+        let (sx, incoming_messages) = tokio::sync::mpsc::unbounded_channel();
+        let mut results = results(incoming_messages);
+        sx.send(Ok(TYPES_ONLY_ENTRY.to_vec())).unwrap();
+        sx.send(Ok(TYPES_ONLY_ENTRY.to_vec())).unwrap();
+        let mut seen = 0;
+        let result = results
+            .try_for_each_entry(|_| {
+                seen += 1;
+                if seen == 2 { Err("second entry") } else { Ok(()) }
+            })
+            .await;
+        assert!(matches!(result, Err(SearchOrUserError::User("second entry"))));
+    }
+
+    #[tokio::test]
+    async fn next_page_cookie() {
+        let (sx, incoming_messages) = tokio::sync::mpsc::unbounded_channel();
+        let mut results = results(incoming_messages);
+        let cookie = PageCookie::from_bytes(b"page 2".to_vec());
+        let mut done = vec![0x65, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00];
+        write_controls(&mut done, &[Control::paged_results(0, Some(&cookie))]).unwrap();
+        sx.send(Ok(done)).unwrap();
+        assert!(results.next_page().is_none());
+        assert!(matches!(
+            results.next().await,
+            Some(Ok(SearchResult::Done { .. }))
+        ));
+        assert_eq!(results.next_page(), Some(cookie));
+        assert!(!results.page_cookie_rejected());
+    }
+
+    #[test]
+    fn reorder_attributes() {
+        let mut entry = RawEntry {
+            object_name: "cn=a".to_string(),
+            attributes: ["mail", "objectClass", "cn", "sn"]
+                .map(|name| Attribute {
+                    r#type: name.to_string(),
+                    values: Vec::new(),
+                })
+                .into(),
+        };
+        entry.reorder(&["SN", "cn"]);
+        let order: Vec<_> = entry.attributes.iter().map(|a| a.r#type.as_str()).collect();
+        assert_eq!(order, ["sn", "cn", "mail", "objectClass"]);
+    }
+
+    #[test]
+    fn attribute_accessors() {
+        let entry = RawEntry {
+            object_name: "cn=a".to_string(),
+            attributes: vec![
+                Attribute {
+                    r#type: "cn".to_string(),
+                    values: vec![b"a".to_vec(), b"b".to_vec()],
+                },
+                Attribute {
+                    r#type: "jpegPhoto".to_string(),
+                    values: vec![vec![0xff, 0xd8]],
+                },
+            ],
+        };
+        assert_eq!(entry.get_str("CN"), Some(Ok("a")));
+        assert!(matches!(entry.get_str("jpegphoto"), Some(Err(_))));
+        assert_eq!(entry.get_bytes("jpegPhoto"), Some([0xff, 0xd8].as_slice()));
+        assert_eq!(entry.get_bytes("jpegPhoto;binary"), Some([0xff, 0xd8].as_slice()));
+        assert!(entry.get_all("cn;lang-de").is_empty());
+        assert_eq!(entry.get_all("cn").len(), 2);
+        assert!(entry.get_all("mail").is_empty());
+        assert_eq!(entry.get_str("mail"), None);
+        #[cfg(feature = "from_octets")]
+        assert_eq!(entry.get_parsed::<String>("cn").unwrap().unwrap(), "a");
+    }
+
+    #[test]
+    fn language_variants() {
+        // the variant comes first, so it would win if a bare type matched subtypes too
+        let mut entry = RawEntry {
+            object_name: "cn=a".to_string(),
+            attributes: vec![
+                Attribute {
+                    r#type: "cn;lang-fr".to_string(),
+                    values: vec![b"Jean".to_vec()],
+                },
+                Attribute {
+                    r#type: "cn".to_string(),
+                    values: vec![b"John".to_vec()],
+                },
+            ],
+        };
+        assert_eq!(entry.get_str("cn"), Some(Ok("John")));
+        assert_eq!(entry.get_str("CN;Lang-FR"), Some(Ok("Jean")));
+        assert_eq!(entry.take_attribute("cn"), Some(vec![b"John".to_vec()]));
+        assert_eq!(entry.get_str("cn"), None);
+    }
+
+    #[test]
+    fn types_only_entry() {
+        let Ok(SearchResult::Entry(entry)) = read_search_as::<RawEntry, _>(TYPES_ONLY_ENTRY.as_slice())
+        else {
+            panic!("failed to read entry");
+        };
+        assert_eq!(entry.attributes[0].r#type, "cn");
+        assert!(entry.attributes[0].values.is_empty());
+        assert_eq!(entry.to_string(), "dn: cn=a\ncn:\n");
+    }
+
+    #[test]
+    fn missing_entry() {
+        let error = ReadEntryError::from(SearchOneError::ServerError {
+            code: ResultCode::NoSuchObject,
+            message: "no such entry".to_string(),
+        });
+        assert!(matches!(error, ReadEntryError::NoSuchObject(ref m) if m == "no such entry"));
+        let error = ReadEntryError::from(SearchOneError::ServerError {
+            code: ResultCode::InsufficientAccessRights,
+            message: String::new(),
+        });
+        assert!(matches!(error, ReadEntryError::SearchOne(_)));
+    }
+}
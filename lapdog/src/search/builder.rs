@@ -0,0 +1,145 @@
+use crate::{
+    LdapConnection,
+    control::Control,
+    search::{
+        BeginSearchError, BeginSearchErrorKind, DerefPolicy, Filter, FromEntry, RawEntry, Scope,
+        SearchResults,
+    },
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct SearchRequest<'a> {
+    pub(crate) base_object: &'a str,
+    pub(crate) scope: Scope,
+    pub(crate) deref_policy: DerefPolicy,
+    pub(crate) size_limit: i32,
+    pub(crate) time_limit: i32,
+    pub(crate) types_only: bool,
+    pub(crate) filter: Filter<'a>,
+    pub(crate) attributes: Vec<&'a str>,
+}
+
+/// Builder for searches that need more than the shortcuts on [`LdapConnection`] offer.
+///
+/// Defaults to a subtree search for all entries below the base, without limits, that never dereferences aliases.
+///
+/// ```no_run
+/// # use lapdog::{LdapConnection, search::{Filter, Scope}};
+/// # async fn f(connection: &LdapConnection) {
+/// let results = connection
+///     .search_builder("dc=example,dc=com")
+///     .scope(Scope::SingleLevel)
+///     .filter(Filter::equal("objectClass", b"person"))
+///     .size_limit(100)
+///     .send()
+///     .await;
+/// # }
+/// ```
+#[must_use]
+pub struct SearchRequestBuilder<'a> {
+    connection: &'a LdapConnection,
+    base_object: &'a str,
+    scope: Scope,
+    deref_policy: DerefPolicy,
+    size_limit: i32,
+    time_limit: i32,
+    types_only: bool,
+    filter: Filter<'a>,
+    attributes: Option<Vec<&'a str>>,
+    controls: Vec<Control>,
+    allow_empty_base: bool,
+}
+impl<'a> SearchRequestBuilder<'a> {
+    pub(crate) fn new(connection: &'a LdapConnection, base_object: &'a str) -> Self {
+        Self {
+            connection,
+            base_object,
+            scope: Scope::WholeSubtree,
+            deref_policy: DerefPolicy::default(),
+            size_limit: 0,
+            time_limit: 0,
+            types_only: false,
+            filter: Filter::Present("objectClass"),
+            attributes: None,
+            controls: Vec::new(),
+            allow_empty_base: connection.allow_empty_base,
+        }
+    }
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+    pub fn deref_policy(mut self, deref_policy: DerefPolicy) -> Self {
+        self.deref_policy = deref_policy;
+        self
+    }
+    pub fn filter(mut self, filter: Filter<'a>) -> Self {
+        self.filter = filter;
+        self
+    }
+    /// Maximum number of entries the server returns, 0 means no limit.
+    /// The server may enforce a lower limit of its own.
+    pub fn size_limit(mut self, entries: u32) -> Self {
+        self.size_limit = i32::try_from(entries).unwrap_or(i32::MAX);
+        self
+    }
+    /// Maximum number of seconds the server spends on the search, 0 means no limit.
+    /// The server may enforce a lower limit of its own.
+    pub fn time_limit(mut self, seconds: u32) -> Self {
+        self.time_limit = i32::try_from(seconds).unwrap_or(i32::MAX);
+        self
+    }
+    /// Only return attribute types, without their values, e.g. to discover which attributes an entry has.
+    ///
+    /// The attributes of the returned entries have empty value lists.
+    pub fn types_only(mut self, types_only: bool) -> Self {
+        self.types_only = types_only;
+        self
+    }
+    /// Attributes to return, defaults to the ones of the output type, or all user attributes (`*`)
+    pub fn attributes<'b: 'a>(mut self, attributes: impl IntoIterator<Item = &'b str>) -> Self {
+        self.attributes = Some(attributes.into_iter().map(|a| a as &str).collect());
+        self
+    }
+    /// Attaches a request control, can be called multiple times
+    pub fn control(mut self, control: Control) -> Self {
+        self.controls.push(control);
+        self
+    }
+    /// Allows a one-level or subtree search from the empty base `""`.
+    ///
+    /// With base object scope, the empty base is the root DSE. With any other scope it's the whole directory, which
+    /// many servers reject, others scan every naming context they hold, so such searches fail with
+    /// [`BeginSearchError::is_empty_base`] unless allowed here. Searches of an Active Directory global catalog
+    /// (port 3268) are the common reason to allow it.
+    ///
+    /// This defaults to [`LdapConnection::allows_empty_base`]. Allow it there for the shortcuts like `search_all`,
+    /// `search_one`, `count` and `find_all`, and those of [`RetryingConnection`](crate::retry::RetryingConnection).
+    pub fn allow_empty_base(mut self, allow: bool) -> Self {
+        self.allow_empty_base = allow;
+        self
+    }
+    pub async fn send(self) -> Result<SearchResults<RawEntry>, BeginSearchError> {
+        self.send_as().await
+    }
+    pub async fn send_as<Output: FromEntry>(self) -> Result<SearchResults<Output>, BeginSearchError> {
+        if self.base_object.is_empty() && !matches!(self.scope, Scope::Base) && !self.allow_empty_base {
+            return Err(BeginSearchError(BeginSearchErrorKind::EmptyBaseWithNonBaseScope));
+        }
+        let attributes = self.attributes.unwrap_or_else(|| match Output::attributes() {
+            None => vec!["*"],
+            Some(v) => v.collect(),
+        });
+        let request = SearchRequest {
+            base_object: self.base_object,
+            scope: self.scope,
+            deref_policy: self.deref_policy,
+            size_limit: self.size_limit,
+            time_limit: self.time_limit,
+            types_only: self.types_only,
+            filter: self.filter,
+            attributes,
+        };
+        self.connection.search_raw(request, &self.controls).await
+    }
+}
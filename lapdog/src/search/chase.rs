@@ -0,0 +1,325 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque, hash_map::Entry},
+    error::Error,
+    fmt::Display,
+};
+
+use crate::{
+    LapdogError, LdapConnection,
+    search::{CollectError, DerefPolicy, Filter, FromEntry, Scope, collect_with_references},
+    url::{LdapUrl, Scheme, select_referral},
+};
+
+/// How far [`LdapConnection::find_all_chasing`] follows continuation references
+#[derive(Clone, Debug)]
+pub struct ChasePolicy {
+    /// Most references followed in a row. A reference beyond that fails the search with [`ChaseError::TooDeep`],
+    /// so 0 fails on the first reference.
+    pub max_depth: u32,
+    /// Scheme to pick if a reference lists several URLs, see [`select_referral`]
+    pub preferred_scheme: Scheme,
+    /// Where the connection the search starts on leads, e.g. `ldap://dc01.example.com`. References to that
+    /// server are searched on the connection itself, and one pointing back to the original base is skipped.
+    pub origin: Option<LdapUrl>,
+}
+impl Default for ChasePolicy {
+    fn default() -> Self {
+        Self {
+            max_depth: 5,
+            preferred_scheme: Scheme::Ldap,
+            origin: None,
+        }
+    }
+}
+
+impl LdapConnection {
+    /// Like [`LdapConnection::find_all`], but also searches the parts of the subtree held by other servers.
+    ///
+    /// Active Directory answers a subtree search that reaches into another naming context, e.g. a child domain
+    /// or the `DomainDnsZones` partition, with a continuation reference instead of its entries. Every such
+    /// reference is searched in turn, with `connect` opening the connection to its server. It should bind with the
+    /// same credentials as this connection, and may return `Ok(None)` to skip servers, e.g. outside the forest:
+    ///
+    #[cfg_attr(any(feature = "native-tls", feature = "rustls"), doc = "```no_run")]
+    #[cfg_attr(not(any(feature = "native-tls", feature = "rustls")), doc = "```ignore")]
+    /// # fn tls_config(host: &str) -> lapdog::StreamConfig { unimplemented!() }
+    /// # async fn run(connection: &lapdog::LdapConnection) -> Result<(), lapdog::LapdogError> {
+    /// use lapdog::{TlsConnection, search::{ChasePolicy, Filter, RawEntry}, url::LdapUrl};
+    ///
+    /// let connect = |url: LdapUrl| async move {
+    ///     let (Some(host), Some(port)) = (url.host.as_deref(), url.port()) else {
+    ///         return Ok(None);
+    ///     };
+    ///     if !host.ends_with(".example.com") {
+    ///         return Ok(None);
+    ///     }
+    ///     // a TLS config for the host, e.g. from StreamConfig::native_tls(host)
+    ///     let mut connection = TlsConnection::new((host, port), &tls_config(host)).await?;
+    ///     connection.bind_simple("cn=reader,dc=example,dc=com", b"secret").await?;
+    ///     Ok(Some(connection.into_inner()))
+    /// };
+    /// let filter = Filter::equal("objectClass", "user");
+    /// let users: Vec<RawEntry> = connection
+    ///     .find_all_chasing("dc=example,dc=com", &filter, &ChasePolicy::default(), connect)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Connections are reused for references to the same server. A server and base that was already searched is
+    /// skipped, so references pointing back don't loop. That includes the original server and base if
+    /// [`ChasePolicy::origin`] names the server.
+    pub async fn find_all_chasing<Output, C, F>(
+        &self,
+        base_object: &str,
+        filter: &Filter<'_>,
+        policy: &ChasePolicy,
+        mut connect: C,
+    ) -> Result<Vec<Output>, ChaseError>
+    where
+        Output: FromEntry,
+        C: FnMut(LdapUrl) -> F,
+        F: Future<Output = Result<Option<LdapConnection>, LapdogError>>,
+    {
+        let (mut entries, references) = collect_with_references(
+            self,
+            base_object,
+            Scope::WholeSubtree,
+            DerefPolicy::Never,
+            filter.clone(),
+        )
+        .await
+        .map_err(|error| ChaseError::Search { url: None, error })?;
+        let mut pending: VecDeque<_> = references.into_iter().map(|urls| (urls, 1)).collect();
+        let mut connections = HashMap::new();
+        // `None` is the server of this connection
+        let server_of = |url: &LdapUrl| {
+            let server = (url.scheme, url.host.as_ref()?.to_ascii_lowercase(), url.port());
+            let origin = policy.origin.as_ref().and_then(|origin| {
+                Some((
+                    origin.scheme,
+                    origin.host.as_ref()?.to_ascii_lowercase(),
+                    origin.port(),
+                ))
+            });
+            (origin.as_ref() != Some(&server)).then_some(server)
+        };
+        let mut searched = HashSet::from([(None, base_object.to_ascii_lowercase())]);
+        while let Some((urls, depth)) = pending.pop_front() {
+            let Some(url) = select_referral(urls.iter().map(String::as_str), policy.preferred_scheme) else {
+                return Err(ChaseError::InvalidReference(urls));
+            };
+            if depth > policy.max_depth {
+                return Err(ChaseError::TooDeep(url));
+            }
+            let server = server_of(&url);
+            // a reference without a DN continues below the original base
+            let base = if url.dn.is_empty() { base_object } else { &url.dn };
+            if !searched.insert((server.clone(), base.to_ascii_lowercase())) {
+                continue;
+            }
+            let connection = match server.map(|server| connections.entry(server)) {
+                // no host leaves the server to the client, so stay on this one
+                None => self,
+                Some(Entry::Occupied(connection)) => &*connection.into_mut(),
+                Some(Entry::Vacant(vacant)) => match connect(url.clone()).await {
+                    Ok(Some(connection)) => &*vacant.insert(connection),
+                    Ok(None) => continue,
+                    Err(error) => {
+                        return Err(ChaseError::Connect {
+                            url,
+                            error: Box::new(error),
+                        });
+                    }
+                },
+            };
+            let (found, references) = collect_with_references(
+                connection,
+                base,
+                Scope::WholeSubtree,
+                DerefPolicy::Never,
+                filter.clone(),
+            )
+            .await
+            .map_err(|error| ChaseError::Search {
+                url: Some(url.clone()),
+                error,
+            })?;
+            entries.extend(found);
+            pending.extend(references.into_iter().map(|urls| (urls, depth + 1)));
+        }
+        Ok(entries)
+    }
+}
+
+/// Error of [`LdapConnection::find_all_chasing`]
+#[derive(Debug)]
+pub enum ChaseError {
+    /// A search failed, `url` is the reference that led to it, `None` for the original search
+    Search {
+        url: Option<LdapUrl>,
+        error: CollectError,
+    },
+    /// None of the URLs of a reference could be parsed
+    InvalidReference(Vec<String>),
+    /// `connect` failed for the server of a reference
+    Connect { url: LdapUrl, error: Box<LapdogError> },
+    /// A reference was more than [`ChasePolicy::max_depth`] references away from the original search
+    TooDeep(LdapUrl),
+}
+impl Error for ChaseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Search { error, .. } => Some(error),
+            Self::Connect { error, .. } => Some(error.as_ref()),
+            Self::InvalidReference(_) | Self::TooDeep(_) => None,
+        }
+    }
+}
+impl Display for ChaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Search { url: None, error } => error.fmt(f),
+            Self::Search {
+                url: Some(url),
+                error,
+            } => {
+                write!(f, "Search of reference to {} failed: {error}", url.dn)
+            }
+            Self::InvalidReference(urls) => {
+                write!(f, "Invalid continuation reference to {}", urls.join(", "))
+            }
+            Self::Connect { url, error } => {
+                let host = url.host.as_deref().unwrap_or_default();
+                write!(f, "Failed to connect to {host} to follow a reference: {error}")
+            }
+            Self::TooDeep(url) => write!(f, "Too many references followed to reach {}", url.dn),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::{ChaseError, ChasePolicy};
+    use crate::{
+        LdapConnection, StreamConfig,
+        search::{Filter, RawEntry},
+        test::mock_connection,
+    };
+
+    /// Answers the first search on `server` with an entry named `name`, references to `urls` and a successful done
+    fn serve(mut server: TcpStream, name: &'static str, urls: Vec<String>) {
+        tokio::spawn(async move {
+            assert!(server.read(&mut [0; 256]).await.unwrap() > 0);
+            let entry = [
+                &[0x64, name.len() as u8 + 4, 0x04, name.len() as u8],
+                name.as_bytes(),
+                &[0x30, 0x00],
+            ]
+            .concat();
+            let mut operations = vec![entry];
+            for url in urls {
+                let reference = [
+                    &[0x73, url.len() as u8 + 2, 0x04, url.len() as u8],
+                    url.as_bytes(),
+                ]
+                .concat();
+                operations.push(reference);
+            }
+            operations.push(vec![0x65, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00]);
+            for operation in operations {
+                let message = [
+                    &[0x30, operation.len() as u8 + 3, 0x02, 0x01, 0x01],
+                    &operation[..],
+                ]
+                .concat();
+                server.write_all(&message).await.unwrap();
+            }
+            // keep the connection open until the client is done
+            let _ = server.read(&mut [0; 1]).await;
+        });
+    }
+
+    #[tokio::test]
+    async fn chase_references() {
+        let (connection, origin) = mock_connection().await;
+        let child = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let child_url = format!("ldap://{}/dc=child,dc=x", child.local_addr().unwrap());
+        serve(
+            origin,
+            "dc=x",
+            vec![child_url.clone(), "ldap://unreachable.invalid/dc=y".into()],
+        );
+        tokio::spawn(async move {
+            let (child, _) = child.accept().await.unwrap();
+            // the child refers back to itself, which is skipped
+            serve(child, "dc=child,dc=x", vec![child_url]);
+        });
+
+        let entries: Vec<RawEntry> = connection
+            .find_all_chasing(
+                "dc=x",
+                &Filter::Present("objectClass"),
+                &ChasePolicy::default(),
+                |url| async move {
+                    if url.dn == "dc=y" {
+                        return Ok(None);
+                    }
+                    let address = (url.host.clone().unwrap(), url.port().unwrap());
+                    let stream = TcpStream::connect(address).await.unwrap();
+                    Ok(Some(
+                        LdapConnection::from_stream(stream, &StreamConfig::default()).await?,
+                    ))
+                },
+            )
+            .await
+            .unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.object_name.as_str()).collect();
+        assert_eq!(names, ["dc=x", "dc=child,dc=x"]);
+    }
+
+    #[tokio::test]
+    async fn skip_references_to_origin() {
+        let (connection, origin) = mock_connection().await;
+        let address = origin.local_addr().unwrap();
+        serve(
+            origin,
+            "dc=x",
+            vec!["ldap:///dc=x".into(), format!("ldap://{address}/DC=X")],
+        );
+
+        let policy = ChasePolicy {
+            origin: Some(format!("ldap://{address}").parse().unwrap()),
+            ..ChasePolicy::default()
+        };
+        let entries: Vec<RawEntry> = connection
+            .find_all_chasing("dc=x", &Filter::Present("objectClass"), &policy, |_| async {
+                panic!("connected to follow a reference to the origin")
+            })
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn depth_limit() {
+        let (connection, origin) = mock_connection().await;
+        serve(origin, "dc=x", vec!["ldap:///dc=child,dc=x".into()]);
+
+        let policy = ChasePolicy {
+            max_depth: 0,
+            ..ChasePolicy::default()
+        };
+        let result = connection
+            .find_all_chasing::<RawEntry, _, _>("dc=x", &Filter::Present("objectClass"), &policy, |_| async {
+                Ok(None)
+            })
+            .await;
+        assert!(matches!(result, Err(ChaseError::TooDeep(url)) if url.dn == "dc=child,dc=x"));
+    }
+}
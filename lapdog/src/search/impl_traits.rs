@@ -7,7 +7,9 @@ use std::{
     },
 };
 
-use crate::search::{FromMultipleOctetStrings, FromOctetString};
+use crate::search::{
+    FromMultipleOctetStrings, FromMultipleOctetStringsRef, FromOctetString, FromOctetStringRef,
+};
 
 impl FromOctetString for String {
     type Err = std::string::FromUtf8Error;
@@ -21,6 +23,30 @@ impl FromOctetString for Box<str> {
         String::from_octet_string(bytes).map(|s| s.into_boxed_str())
     }
 }
+/// On Unix, the bytes are used as they are, so paths that aren't valid UTF-8 survive.
+/// Elsewhere, e.g. on Windows, the value has to be UTF-8.
+#[cfg(unix)]
+impl FromOctetString for std::ffi::OsString {
+    type Err = Infallible;
+    fn from_octet_string(bytes: &[u8]) -> Result<Self, Self::Err> {
+        use std::os::unix::ffi::OsStrExt;
+        Ok(std::ffi::OsStr::from_bytes(bytes).to_os_string())
+    }
+}
+#[cfg(not(unix))]
+impl FromOctetString for std::ffi::OsString {
+    type Err = std::string::FromUtf8Error;
+    fn from_octet_string(bytes: &[u8]) -> Result<Self, Self::Err> {
+        String::from_octet_string(bytes).map(Into::into)
+    }
+}
+/// Like [`OsString`](std::ffi::OsString), this only accepts non-UTF-8 paths on Unix
+impl FromOctetString for std::path::PathBuf {
+    type Err = <std::ffi::OsString as FromOctetString>::Err;
+    fn from_octet_string(bytes: &[u8]) -> Result<Self, Self::Err> {
+        std::ffi::OsString::from_octet_string(bytes).map(Into::into)
+    }
+}
 impl FromOctetString for () {
     type Err = Infallible;
     fn from_octet_string(_bytes: &[u8]) -> Result<Self, Self::Err> {
@@ -111,6 +137,119 @@ where
     }
 }
 
+impl<'a, T: FromOctetString> FromOctetStringRef<'a> for T {
+    type Err = T::Err;
+    fn from_octet_string_ref(bytes: &'a [u8]) -> Result<Self, Self::Err> {
+        T::from_octet_string(bytes)
+    }
+}
+impl<'a> FromOctetStringRef<'a> for &'a str {
+    type Err = std::str::Utf8Error;
+    fn from_octet_string_ref(bytes: &'a [u8]) -> Result<Self, Self::Err> {
+        str::from_utf8(bytes)
+    }
+}
+impl<'a> FromOctetStringRef<'a> for &'a [u8] {
+    type Err = Infallible;
+    fn from_octet_string_ref(bytes: &'a [u8]) -> Result<Self, Self::Err> {
+        Ok(bytes)
+    }
+}
+/// Like `Option<T>` for [`FromOctetString`], so `default` may be used with borrowed fields.
+impl<'a> FromOctetStringRef<'a> for Option<&'a str> {
+    type Err = std::str::Utf8Error;
+    fn from_octet_string_ref(bytes: &'a [u8]) -> Result<Self, Self::Err> {
+        str::from_utf8(bytes).map(Some)
+    }
+}
+impl<'a> FromOctetStringRef<'a> for Option<&'a [u8]> {
+    type Err = Infallible;
+    fn from_octet_string_ref(bytes: &'a [u8]) -> Result<Self, Self::Err> {
+        Ok(Some(bytes))
+    }
+}
+
+impl<'a, T: FromOctetStringRef<'a>> FromMultipleOctetStringsRef<'a> for Vec<T> {
+    type Err = T::Err;
+    fn from_multiple_octet_strings_ref(values: impl Iterator<Item = &'a [u8]>) -> Result<Self, Self::Err> {
+        values.map(T::from_octet_string_ref).collect()
+    }
+}
+impl<'a, T: FromOctetStringRef<'a>> FromMultipleOctetStringsRef<'a> for Box<[T]> {
+    type Err = T::Err;
+    fn from_multiple_octet_strings_ref(values: impl Iterator<Item = &'a [u8]>) -> Result<Self, Self::Err> {
+        Vec::from_multiple_octet_strings_ref(values).map(|res| res.into_boxed_slice())
+    }
+}
+
+/// Parses the hyphenated text form, e.g. OpenLDAP's `entryUUID`.
+///
+/// For binary GUIDs like Active Directory's `objectGUID`, use [`BinaryGuid`] instead.
+#[cfg(feature = "uuid")]
+impl FromOctetString for uuid::Uuid {
+    type Err = uuid::Error;
+    fn from_octet_string(bytes: &[u8]) -> Result<Self, Self::Err> {
+        uuid::Uuid::try_parse_ascii(bytes)
+    }
+}
+/// A GUID in the 16 byte binary form, e.g. Active Directory's `objectGUID`.
+///
+/// The first three fields are little-endian, so `{01020304-0506-0708-...}` is stored as `04 03 02 01 06 05 08 07 ...`.
+/// For text UUIDs like OpenLDAP's `entryUUID`, parse into [`uuid::Uuid`] directly.
+#[cfg(feature = "uuid")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BinaryGuid(pub uuid::Uuid);
+#[cfg(feature = "uuid")]
+impl BinaryGuid {
+    /// The binary form Active Directory stores and compares, to search with e.g. [`Filter::equal`](crate::search::Filter::equal)
+    pub fn to_filter_bytes(&self) -> [u8; 16] {
+        self.0.to_bytes_le()
+    }
+}
+#[cfg(feature = "uuid")]
+impl FromOctetString for BinaryGuid {
+    type Err = InvalidGuidLength;
+    fn from_octet_string(bytes: &[u8]) -> Result<Self, Self::Err> {
+        let bytes = bytes.try_into().map_err(|_| InvalidGuidLength(bytes.len()))?;
+        Ok(Self(uuid::Uuid::from_bytes_le(bytes)))
+    }
+}
+/// The hyphenated text form, the counterpart of parsing with [`FromOctetString`]
+#[cfg(feature = "uuid")]
+impl crate::modify::ToOctetString for uuid::Uuid {
+    fn to_octet_string(&self) -> Vec<u8> {
+        self.hyphenated().to_string().into_bytes()
+    }
+}
+#[cfg(feature = "uuid")]
+impl crate::modify::ToOctetString for BinaryGuid {
+    fn to_octet_string(&self) -> Vec<u8> {
+        self.to_filter_bytes().to_vec()
+    }
+}
+#[cfg(feature = "uuid")]
+#[derive(Clone, Copy, Debug)]
+pub struct InvalidGuidLength(usize);
+#[cfg(feature = "uuid")]
+impl std::error::Error for InvalidGuidLength {}
+#[cfg(feature = "uuid")]
+impl Display for InvalidGuidLength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "binary GUID must be 16 bytes, got {}", self.0)
+    }
+}
+
+/// DER encoded certificates, e.g. from `userCertificate;binary` or `cACertificate;binary`.
+///
+/// Servers only return the DER form when the `;binary` option is requested, so rename fields accordingly.
+#[cfg(feature = "x509")]
+impl FromOctetString for x509_cert::Certificate {
+    type Err = x509_cert::der::Error;
+    fn from_octet_string(bytes: &[u8]) -> Result<Self, Self::Err> {
+        x509_cert::der::Decode::from_der(bytes)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum ParseIntegerError {
     Utf8(std::str::Utf8Error),
@@ -140,3 +279,43 @@ impl Display for ParseBoolError {
         write!(f, "value was not 'TRUE' or 'FALSE'")
     }
 }
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "uuid")]
+    use super::BinaryGuid;
+    #[cfg(feature = "uuid")]
+    use crate::search::Filter;
+    use crate::search::FromOctetString;
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn binary_and_text_guid() {
+        let text = uuid::Uuid::from_octet_string(b"01020304-0506-0708-090a-0b0c0d0e0f10").unwrap();
+        let binary =
+            BinaryGuid::from_octet_string(&[4, 3, 2, 1, 6, 5, 8, 7, 9, 10, 11, 12, 13, 14, 15, 16]).unwrap();
+        assert_eq!(binary.0, text);
+        assert!(BinaryGuid::from_octet_string(b"01020304-0506-0708-090a-0b0c0d0e0f10").is_err());
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn search_by_guid() {
+        let guid = BinaryGuid("01020304-0506-0708-090a-0b0c0d0e0f10".parse().unwrap());
+        let bytes = guid.to_filter_bytes();
+        assert_eq!(BinaryGuid::from_octet_string(&bytes).unwrap(), guid);
+        assert_eq!(
+            Filter::equal("objectGUID", &bytes).to_string(),
+            r"(objectGUID=\04\03\02\01\06\05\08\07\09\0a\0b\0c\0d\0e\0f\10)"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_path() {
+        use std::{os::unix::ffi::OsStrExt, path::PathBuf};
+
+        let path = PathBuf::from_octet_string(b"/home/j\xf6rg").unwrap();
+        assert_eq!(path.as_os_str().as_bytes(), b"/home/j\xf6rg");
+    }
+}
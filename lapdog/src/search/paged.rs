@@ -0,0 +1,89 @@
+use crate::{
+    LdapConnection,
+    control::{self, Control, PAGED_RESULTS_OID},
+    result::ResultCode,
+    search::{BeginSearchError, DerefPolicy, Filter, Scope, SearchResults},
+};
+
+/// Position of a paged search, used to request the page after it.
+///
+/// The cookie is opaque and can be stored outside of the connection, e.g. handed to a web client, via
+/// [`PageCookie::as_bytes`] and [`PageCookie::from_bytes`].
+///
+/// Many servers, e.g. Active Directory and OpenLDAP, only accept a cookie on the connection that issued it and
+/// for the exact same search. Resuming elsewhere fails, see [`SearchResults::page_cookie_rejected`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PageCookie(Vec<u8>);
+impl PageCookie {
+    pub fn from_bytes(bytes: impl Into<Vec<u8>>) -> Self {
+        Self(bytes.into())
+    }
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl LdapConnection {
+    /// Searches for one page of at most `page_size` entries, starting after `cookie` or at the beginning.
+    ///
+    /// The cookie for the following page is available from [`SearchResults::next_page`] once the search is done.
+    /// Use [`Control::paged_results`] with [`LdapConnection::search_builder`] to page searches with more options.
+    pub async fn search_paged(
+        &self,
+        base_object: &str,
+        scope: Scope,
+        deref_policy: DerefPolicy,
+        filter: Filter<'_>,
+        page_size: u32,
+        cookie: Option<&PageCookie>,
+    ) -> Result<SearchResults, BeginSearchError> {
+        self.search_builder(base_object)
+            .scope(scope)
+            .deref_policy(deref_policy)
+            .filter(filter)
+            .control(Control::paged_results(page_size, cookie))
+            .send()
+            .await
+    }
+}
+
+impl<Output> SearchResults<Output> {
+    /// Cookie to request the page after this one, `None` on the last page or if the search wasn't paged.
+    ///
+    /// Only available once [`SearchResult::Done`](crate::search::SearchResult::Done) was received.
+    pub fn next_page(&self) -> Option<PageCookie> {
+        self.response_controls
+            .iter()
+            .filter(|c| c.oid == PAGED_RESULTS_OID)
+            .find_map(|c| control::read_paged_results(c.value.as_deref()?))
+            .map(|(_, cookie)| cookie)
+            .filter(|cookie| !cookie.is_empty())
+            .map(PageCookie)
+    }
+    /// Whether the search resumed from a cookie the server didn't accept, e.g. one from a different connection.
+    ///
+    /// Servers don't have a dedicated result code for this, so this checks for the ones Active Directory and
+    /// OpenLDAP use. Start over without a cookie in that case.
+    pub fn page_cookie_rejected(&self) -> bool {
+        self.resumed_page
+            && matches!(
+                self.final_result,
+                Some((
+                    ResultCode::UnwillingToPerform | ResultCode::ProtocolError | ResultCode::OperationsError,
+                    _
+                ))
+            )
+    }
+}
+
+/// Whether the controls resume a paged search from a cookie
+pub(crate) fn resumes_page(controls: &[Control]) -> bool {
+    controls
+        .iter()
+        .filter(|c| c.oid == PAGED_RESULTS_OID)
+        .filter_map(|c| control::read_paged_results(c.value.as_deref()?))
+        .any(|(_, cookie)| !cookie.is_empty())
+}
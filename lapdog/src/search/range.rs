@@ -0,0 +1,146 @@
+use std::{error::Error, fmt::Display};
+
+use crate::{
+    LdapConnection,
+    search::{Filter, RawEntry, Scope, SearchOneError},
+};
+
+impl LdapConnection {
+    /// Fetches the remaining values of attributes the server only returned partially, like Active Directory does
+    /// for large multi-valued attributes such as a group's `member`.
+    ///
+    /// Such attributes come back as e.g. `member;range=0-1499`. This issues base searches for the following ranges
+    /// until all values are read, and renames the attribute to `member`. Attributes without a range are untouched.
+    ///
+    /// To read the completed entry into a typed struct, search for [`RawEntry`] and call
+    /// [`FromEntry::from_entry`](crate::search::FromEntry::from_entry) afterwards.
+    pub async fn complete_ranges(&self, entry: &mut RawEntry) -> Result<(), RangeRetrievalError> {
+        for i in 0..entry.attributes.len() {
+            let Some((description, mut end)) = parse_range(&entry.attributes[i].r#type) else {
+                continue;
+            };
+            let mut values = std::mem::take(&mut entry.attributes[i].values);
+            while let Some(high) = end {
+                let next = format!("{description};range={}-*", high + 1);
+                let results = self
+                    .search_builder(&entry.object_name)
+                    .scope(Scope::Base)
+                    .filter(Filter::Present("objectClass"))
+                    .attributes([next.as_str()])
+                    .send()
+                    .await
+                    .map_err(|e| RangeRetrievalError::Search(SearchOneError::Begin(e)))?;
+                let mut chunk = self
+                    .single_result(results)
+                    .await
+                    .map_err(RangeRetrievalError::Search)?
+                    .ok_or(RangeRetrievalError::EntryNotFound)?;
+                let attribute =
+                    chunk
+                        .attributes
+                        .iter_mut()
+                        .find_map(|attr| match parse_range(&attr.r#type) {
+                            Some((d, end)) if d.eq_ignore_ascii_case(&description) => Some((attr, end)),
+                            _ => None,
+                        });
+                // the attribute changed since the first request, e.g. the values were removed
+                let Some((attribute, chunk_end)) = attribute else {
+                    return Err(RangeRetrievalError::InconsistentRange(next));
+                };
+                // a server repeating or going back to an earlier range would keep this looping forever
+                if !advances(high, chunk_end) {
+                    return Err(RangeRetrievalError::InconsistentRange(next));
+                }
+                values.append(&mut attribute.values);
+                end = chunk_end;
+            }
+            entry.attributes[i].r#type = description;
+            entry.attributes[i].values = values;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a range ending at `chunk_end` (`None` for the last one) continues after a range ending at `high`
+fn advances(high: u64, chunk_end: Option<u64>) -> bool {
+    chunk_end.is_none_or(|chunk_end| chunk_end > high)
+}
+
+/// Splits `member;range=0-1499` into `member` and the upper bound of the range, `None` if it's the last range (`*`)
+fn parse_range(attribute_description: &str) -> Option<(String, Option<u64>)> {
+    let mut options = attribute_description.split(';');
+    let mut description = options.next()?.to_string();
+    let mut range = None;
+    for option in options {
+        match option.get(..6) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("range=") => range = Some(&option[6..]),
+            _ => {
+                description.push(';');
+                description.push_str(option);
+            }
+        }
+    }
+    let (_, high) = range?.split_once('-')?;
+    let end = match high {
+        "*" => None,
+        high => Some(high.parse().ok()?),
+    };
+    Some((description, end))
+}
+
+#[derive(Debug)]
+pub enum RangeRetrievalError {
+    Search(SearchOneError),
+    /// The entry disappeared between two ranges
+    EntryNotFound,
+    /// The server didn't return the requested range of the attribute, or one that ends before the previous range
+    InconsistentRange(String),
+}
+impl Error for RangeRetrievalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Search(s) => Some(s),
+            Self::EntryNotFound | Self::InconsistentRange(_) => None,
+        }
+    }
+}
+impl Display for RangeRetrievalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Search(s) => s.fmt(f),
+            Self::EntryNotFound => write!(f, "Entry no longer exists"),
+            Self::InconsistentRange(r) => write!(f, "Server did not return the range {r}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{advances, parse_range};
+
+    #[test]
+    fn range_options() {
+        assert_eq!(
+            parse_range("member;range=0-1499"),
+            Some(("member".to_string(), Some(1499)))
+        );
+        assert_eq!(
+            parse_range("member;Range=1500-*"),
+            Some(("member".to_string(), None))
+        );
+        assert_eq!(
+            parse_range("member;binary;range=0-9"),
+            Some(("member;binary".to_string(), Some(9)))
+        );
+        assert_eq!(parse_range("member"), None);
+        assert_eq!(parse_range("member;range=0-x"), None);
+    }
+
+    #[test]
+    fn ranges_must_advance() {
+        assert!(advances(1499, Some(2999)));
+        assert!(advances(1499, None));
+        assert!(!advances(1499, Some(1499)));
+        assert!(!advances(1499, Some(0)));
+    }
+}
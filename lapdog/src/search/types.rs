@@ -1,7 +1,11 @@
-use std::{io::Write, ops::Not};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult, Write as _},
+    io::Write,
+    ops::Not,
+};
 
 use crate::{
-    WriteExt,
+    EncodeError, EncodingRules, WriteExt,
     attribute::AttributeValueAssertion,
     tag::{PrimitiveOrConstructed, TagClass},
 };
@@ -18,11 +22,21 @@ impl Scope {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// When a search follows alias entries (`objectClass: alias`) to the entry their `aliasedObjectName` points to.
+///
+/// Only searches dereference aliases, compare and modify always act on the named entry itself.
+/// Following aliases can pull whole other subtrees into a search, with surprising results and a big performance cost,
+/// so [`DerefPolicy::Never`] is the default. lapdog's own base searches, e.g. for the root DSE, never dereference.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum DerefPolicy {
+    #[default]
     Never = 0,
+    /// Dereference aliases below the base object, but not the base object itself
     InSearching = 1,
+    /// Only dereference the base object, e.g. to search below the target of an alias
     FindingBaseObj = 2,
+    /// Dereference both the base object and aliases found below it.
+    /// A subtree search may then cover large parts of the directory that aren't below the base object.
     Always = 3,
 }
 impl DerefPolicy {
@@ -31,6 +45,9 @@ impl DerefPolicy {
     }
 }
 
+/// OID of Active Directory's `LDAP_MATCHING_RULE_IN_CHAIN`, which walks a chain of DN references
+pub const IN_CHAIN_OID: &str = "1.2.840.113556.1.4.1941";
+
 #[derive(Clone, Debug)]
 pub enum Filter<'a> {
     And(Vec<Filter<'a>>),
@@ -45,6 +62,15 @@ pub enum Filter<'a> {
     ExtensibleMatch(MatchingRuleAssertion<'a>),
 }
 impl Filter<'_> {
+    /// Values may be anything that's bytes, e.g. `"Steve"`, `b"Steve"` or a `Vec<u8>`.
+    /// They're sent as is, escaping only happens when the filter is displayed as a string.
+    ///
+    /// ```
+    /// # use lapdog::search::Filter;
+    /// let guid = [0xab, 0xcd, 0x28];
+    /// let filter = Filter::and([Filter::equal("cn", "Steve (IT)"), Filter::equal("objectGUID", &guid)]);
+    /// assert_eq!(filter.to_string(), r"(&(cn=Steve \28IT\29)(objectGUID=\ab\cd\28))");
+    /// ```
     pub fn and<'f>(filters: impl IntoIterator<Item = Filter<'f>>) -> Filter<'f> {
         let filters = filters.into_iter().collect();
         Filter::And(filters)
@@ -53,17 +79,117 @@ impl Filter<'_> {
         let filters = filters.into_iter().collect();
         Filter::Or(filters)
     }
-    pub fn equal<'s>(attribute_desc: &'s str, value: &'s [u8]) -> Filter<'s> {
-        Filter::Equal(AttributeValueAssertion::new(attribute_desc, value))
+    /// The empty AND `(&)`, which matches every entry (RFC 4526).
+    ///
+    /// Support varies: servers that implement it list the feature `1.3.6.1.4.1.4203.1.5.3` in the root DSE's
+    /// `supportedFeatures`, others may reject the search. `Filter::Present("objectClass")` works everywhere.
+    pub fn always_true<'f>() -> Filter<'f> {
+        Filter::And(Vec::new())
+    }
+    /// The empty OR `(|)`, which matches no entry (RFC 4526). See [`Filter::always_true`] for server support.
+    pub fn always_false<'f>() -> Filter<'f> {
+        Filter::Or(Vec::new())
+    }
+    pub fn equal<'s>(attribute_desc: &'s str, value: &'s (impl AsRef<[u8]> + ?Sized)) -> Filter<'s> {
+        Filter::Equal(AttributeValueAssertion::new(attribute_desc, value.as_ref()))
+    }
+    /// `(attr>=value)`. LDAP has no strict `>`, use `!Filter::less_or_equal(..)` instead.
+    ///
+    /// Values are compared using the attribute's ORDERING matching rule. Numbers stored in
+    /// a string syntax compare lexicographically, so `"9" >= "10"` unless the schema says otherwise.
+    pub fn greater_or_equal<'s>(
+        attribute_desc: &'s str,
+        value: &'s (impl AsRef<[u8]> + ?Sized),
+    ) -> Filter<'s> {
+        Filter::GreaterOrEqual(AttributeValueAssertion::new(attribute_desc, value.as_ref()))
     }
-    pub fn greater_or_equal<'s>(attribute_desc: &'s str, value: &'s [u8]) -> Filter<'s> {
-        Filter::GreaterOrEqual(AttributeValueAssertion::new(attribute_desc, value))
+    /// `(attr<=value)`. LDAP has no strict `<`, use `!Filter::greater_or_equal(..)` instead.
+    ///
+    /// See [`Filter::greater_or_equal`] for how values are ordered.
+    pub fn less_or_equal<'s>(attribute_desc: &'s str, value: &'s (impl AsRef<[u8]> + ?Sized)) -> Filter<'s> {
+        Filter::LessOrEqual(AttributeValueAssertion::new(attribute_desc, value.as_ref()))
     }
-    pub fn less_or_equal<'s>(attribute_desc: &'s str, value: &'s [u8]) -> Filter<'s> {
-        Filter::LessOrEqual(AttributeValueAssertion::new(attribute_desc, value))
+    /// Alias of [`Filter::greater_or_equal`] for binary values, e.g. of attributes ordered by
+    /// `octetStringOrderingMatch`, which compares byte by byte.
+    ///
+    /// The bytes are sent unchanged and never interpreted as UTF-8. Shorter values sort before longer ones that
+    /// start with them, so integers only compare correctly when encoded big-endian with a fixed width:
+    ///
+    /// ```
+    /// # use lapdog::search::Filter;
+    /// let since = 0x01d9_0000_0000_0000u64.to_be_bytes();
+    /// let filter = Filter::gte_bytes("exampleStamp", &since);
+    /// assert_eq!(filter.to_string(), r"(exampleStamp>=\01\d9\00\00\00\00\00\00)");
+    /// ```
+    ///
+    /// Attributes with an integer syntax like Active Directory's FILETIME attributes (`lastLogonTimestamp`,
+    /// `pwdLastSet`) aren't binary, they're compared as numbers and take the decimal string with
+    /// [`Filter::greater_or_equal`].
+    pub fn gte_bytes<'s>(attribute_desc: &'s str, value: &'s [u8]) -> Filter<'s> {
+        Filter::greater_or_equal(attribute_desc, value)
     }
-    pub fn approximate_match<'s>(attribute_desc: &'s str, value: &'s [u8]) -> Filter<'s> {
-        Filter::ApproxMatch(AttributeValueAssertion::new(attribute_desc, value))
+    /// Alias of [`Filter::less_or_equal`] for binary values, see [`Filter::gte_bytes`]
+    pub fn lte_bytes<'s>(attribute_desc: &'s str, value: &'s [u8]) -> Filter<'s> {
+        Filter::less_or_equal(attribute_desc, value)
+    }
+    /// Approximate match, `(attr~=value)`.
+    ///
+    /// What counts as "approximately equal" is up to the server, often a phonetic algorithm like soundex.
+    /// Servers without support for it treat it as an equality match.
+    pub fn approximate_match<'s>(
+        attribute_desc: &'s str,
+        value: &'s (impl AsRef<[u8]> + ?Sized),
+    ) -> Filter<'s> {
+        Filter::ApproxMatch(AttributeValueAssertion::new(attribute_desc, value.as_ref()))
+    }
+    /// Extensible match, e.g. `(memberOf:1.2.840.113556.1.4.1941:=cn=Admins,dc=example,dc=com)`.
+    ///
+    /// If `attribute_desc` is omitted, `matching_rule` must be present.
+    /// `dn_attributes` additionally matches against the attributes of the entry's DN.
+    pub fn extensible_match<'s>(
+        matching_rule: Option<&'s str>,
+        attribute_desc: Option<&'s str>,
+        value: &'s (impl AsRef<[u8]> + ?Sized),
+        dn_attributes: bool,
+    ) -> Filter<'s> {
+        Filter::ExtensibleMatch(MatchingRuleAssertion {
+            matching_rule,
+            r#type: attribute_desc,
+            match_value: value.as_ref(),
+            dn_attributes: Some(dn_attributes),
+        })
+    }
+    /// Matches entries whose `attribute_desc` refers to `dn` directly or transitively,
+    /// e.g. every member of a group including nested groups (Active Directory only).
+    ///
+    /// ```
+    /// # use lapdog::search::Filter;
+    /// let nested_members = Filter::in_chain("memberOf", "cn=Admins,dc=example,dc=com");
+    /// ```
+    pub fn in_chain<'s>(attribute_desc: &'s str, dn: &'s str) -> Filter<'s> {
+        Self::extensible_match(Some(IN_CHAIN_OID), Some(attribute_desc), dn, false)
+    }
+    pub(crate) fn validate(&self) -> Result<(), EncodeError> {
+        match self {
+            Filter::And(f) | Filter::Or(f) => f.iter().try_for_each(Filter::validate),
+            Filter::Not(f) => f.validate(),
+            Filter::Present("") => Err(EncodeError::EmptyAttributeDescription),
+            Filter::Equal(ava)
+            | Filter::GreaterOrEqual(ava)
+            | Filter::LessOrEqual(ava)
+            | Filter::ApproxMatch(ava)
+                if ava.attribute_desc.is_empty() =>
+            {
+                Err(EncodeError::EmptyAttributeDescription)
+            }
+            // RFC 4511: if the type is omitted, the matching rule must be present
+            Filter::ExtensibleMatch(MatchingRuleAssertion {
+                matching_rule: None,
+                r#type: None,
+                ..
+            }) => Err(EncodeError::InvalidFilter),
+            _ => Ok(()),
+        }
     }
     fn tag_number(&self) -> u8 {
         match self {
@@ -87,18 +213,34 @@ impl Filter<'_> {
     fn tag(&self) -> u8 {
         TagClass::ContextSpecific.into_bits() | self.primitive_or_constructed().into_bit() | self.tag_number()
     }
-    pub(super) fn write_into<W: Write>(&self, mut wout: W) -> Result<(), std::io::Error> {
+    pub(crate) fn write_into<W: Write>(
+        &self,
+        mut wout: W,
+        rules: EncodingRules,
+    ) -> Result<(), std::io::Error> {
         let tag = self.tag();
 
         wout.write_sequence(tag, |mut v| {
             match self {
+                Self::And(f) | Self::Or(f) if rules == EncodingRules::Der => {
+                    // DER requires SET OF elements in ascending order of their encodings
+                    let mut encoded = f
+                        .iter()
+                        .map(|subfilter| {
+                            let mut buf = Vec::new();
+                            subfilter.write_into(&mut buf, rules).map(|()| buf)
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    encoded.sort();
+                    encoded.into_iter().for_each(|e| v.extend(e));
+                }
                 Self::And(f) | Self::Or(f) => {
                     for subfilter in f {
-                        subfilter.write_into(&mut v)?;
+                        subfilter.write_into(&mut v, rules)?;
                     }
                 }
                 Self::Not(f) => {
-                    f.write_into(v)?;
+                    f.write_into(v, rules)?;
                 }
                 Self::Present(attr) => {
                     v.extend_from_slice(attr.as_bytes());
@@ -107,7 +249,7 @@ impl Filter<'_> {
                 | Filter::GreaterOrEqual(ava)
                 | Filter::LessOrEqual(ava)
                 | Filter::ApproxMatch(ava) => ava.write_body_into(v)?,
-                Filter::ExtensibleMatch(em) => em.write_body_into(v),
+                Filter::ExtensibleMatch(em) => em.write_body_into(v, rules),
             };
             Ok(())
         })?;
@@ -115,6 +257,67 @@ impl Filter<'_> {
         Ok(())
     }
 }
+/// The string representation of RFC 4515, e.g. for logging or passing to other LDAP tools
+impl Display for Filter<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_char('(')?;
+        match self {
+            Self::And(filters) | Self::Or(filters) => {
+                f.write_char(if matches!(self, Self::And(_)) { '&' } else { '|' })?;
+                for filter in filters {
+                    write!(f, "{filter}")?;
+                }
+            }
+            Self::Not(filter) => write!(f, "!{filter}")?,
+            Self::Present(attr) => write!(f, "{attr}=*")?,
+            Self::Equal(ava)
+            | Self::GreaterOrEqual(ava)
+            | Self::LessOrEqual(ava)
+            | Self::ApproxMatch(ava) => {
+                let operator = match self {
+                    Self::GreaterOrEqual(_) => ">=",
+                    Self::LessOrEqual(_) => "<=",
+                    Self::ApproxMatch(_) => "~=",
+                    _ => "=",
+                };
+                write!(f, "{}{operator}", ava.attribute_desc)?;
+                write_escaped_value(f, ava.assertion_value)?;
+            }
+            Self::ExtensibleMatch(mra) => {
+                if let Some(t) = mra.r#type {
+                    f.write_str(t)?;
+                }
+                if mra.dn_attributes == Some(true) {
+                    f.write_str(":dn")?;
+                }
+                if let Some(rule) = mra.matching_rule {
+                    write!(f, ":{rule}")?;
+                }
+                f.write_str(":=")?;
+                write_escaped_value(f, mra.match_value)?;
+            }
+        }
+        f.write_char(')')
+    }
+}
+
+/// Escapes the characters RFC 4515 reserves and control characters as `\xx`, as well as every non-ASCII byte
+/// if the value isn't UTF-8
+fn write_escaped_value(f: &mut Formatter<'_>, value: &[u8]) -> FmtResult {
+    let write_byte = |f: &mut Formatter<'_>, b: u8| match b {
+        b'*' | b'(' | b')' | b'\\' => write!(f, "\\{b:02x}"),
+        b if b.is_ascii_control() || !b.is_ascii() => write!(f, "\\{b:02x}"),
+        b => f.write_char(b as char),
+    };
+    match std::str::from_utf8(value) {
+        Ok(value) => value.chars().try_for_each(|c| match u8::try_from(c) {
+            Ok(b) if b.is_ascii() => write_byte(f, b),
+            _ => f.write_char(c),
+        }),
+        Err(_) => value.iter().try_for_each(|&b| write_byte(f, b)),
+    }
+}
+
 impl Not for Filter<'_> {
     type Output = Self;
     fn not(self) -> Self::Output {
@@ -122,6 +325,78 @@ impl Not for Filter<'_> {
     }
 }
 
+/// Assembles a filter clause by clause, e.g. from the facets selected in a search UI.
+///
+/// Clauses combine from left to right: after `push_and(a)`, `push_and(b)`, `push_or(c)` the filter is
+/// `(|(&(a)(b))(c))`. AND and OR groups with a single element are replaced by that element.
+///
+/// ```
+/// # use lapdog::search::{Filter, FilterBuilder};
+/// let mut builder = FilterBuilder::new();
+/// for department in ["IT", "HR"] {
+///     builder.push_or(Filter::equal("department", department));
+/// }
+/// builder.push_and(Filter::equal("objectClass", "person"));
+/// assert_eq!(builder.build().to_string(), "(&(|(department=IT)(department=HR))(objectClass=person))");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FilterBuilder<'a> {
+    filter: Option<Filter<'a>>,
+}
+impl<'a> FilterBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Whether no clause was pushed yet
+    pub fn is_empty(&self) -> bool {
+        self.filter.is_none()
+    }
+    /// Requires `clause` in addition to everything pushed so far
+    pub fn push_and(&mut self, clause: Filter<'a>) -> &mut Self {
+        self.filter = Some(match self.filter.take() {
+            None => clause,
+            Some(Filter::And(mut filters)) => {
+                filters.push(clause);
+                Filter::And(filters)
+            }
+            Some(filter) => Filter::And(vec![filter, clause]),
+        });
+        self
+    }
+    /// Allows `clause` as an alternative to everything pushed so far
+    pub fn push_or(&mut self, clause: Filter<'a>) -> &mut Self {
+        self.filter = Some(match self.filter.take() {
+            None => clause,
+            Some(Filter::Or(mut filters)) => {
+                filters.push(clause);
+                Filter::Or(filters)
+            }
+            Some(filter) => Filter::Or(vec![filter, clause]),
+        });
+        self
+    }
+    /// The assembled filter.
+    ///
+    /// Without any clauses, this is [`Filter::always_true`]. Not every server supports that,
+    /// check [`FilterBuilder::is_empty`] to use e.g. `(objectClass=*)` instead.
+    pub fn build(self) -> Filter<'a> {
+        self.filter.map_or_else(Filter::always_true, collapse)
+    }
+}
+
+/// Replaces single-element AND and OR groups with their element
+fn collapse(filter: Filter<'_>) -> Filter<'_> {
+    match filter {
+        Filter::And(mut filters) | Filter::Or(mut filters) if filters.len() == 1 => {
+            collapse(filters.pop().expect("one element"))
+        }
+        Filter::And(filters) => Filter::And(filters.into_iter().map(collapse).collect()),
+        Filter::Or(filters) => Filter::Or(filters.into_iter().map(collapse).collect()),
+        Filter::Not(filter) => Filter::Not(Box::new(collapse(*filter))),
+        filter => filter,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MatchingRuleAssertion<'a> {
     pub matching_rule: Option<&'a str>,
@@ -140,7 +415,7 @@ const DN_ATTRIBUTES: u8 =
     TagClass::ContextSpecific.into_bits() | PrimitiveOrConstructed::Primitive.into_bit() | 0x4;
 
 impl MatchingRuleAssertion<'_> {
-    fn write_body_into(&self, w: &mut Vec<u8>) {
+    fn write_body_into(&self, w: &mut Vec<u8>, rules: EncodingRules) {
         if let Some(mr) = self.matching_rule {
             w.push(MATCHING_RULE);
             w.write_ber_length(mr.len()).unwrap();
@@ -154,10 +429,84 @@ impl MatchingRuleAssertion<'_> {
         w.push(MATCH_VALUE);
         w.write_ber_length(self.match_value.len()).unwrap();
         w.extend_from_slice(self.match_value);
-        if let Some(b) = self.dn_attributes {
+        // DER omits the default value of FALSE
+        if let Some(b) = self.dn_attributes
+            && (b || rules == EncodingRules::Ber)
+        {
             w.push(DN_ATTRIBUTES);
             w.write_ber_length(1).unwrap();
             w.push(if b { 0xFF } else { 0x00 });
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Filter, FilterBuilder};
+    use crate::EncodingRules;
+
+    #[test]
+    fn der_sorts_subfilters() {
+        let encode = |filter: Filter, rules| {
+            let mut encoded = Vec::new();
+            filter.write_into(&mut encoded, rules).unwrap();
+            encoded
+        };
+        let ba = Filter::or([Filter::equal("b", "1"), Filter::equal("a", "1")]);
+        let ab = Filter::or([Filter::equal("a", "1"), Filter::equal("b", "1")]);
+        assert_ne!(
+            encode(ba.clone(), EncodingRules::Ber),
+            encode(ab.clone(), EncodingRules::Ber)
+        );
+        assert_eq!(encode(ba, EncodingRules::Der), encode(ab, EncodingRules::Der));
+    }
+
+    #[test]
+    fn build_filter() {
+        assert_eq!(FilterBuilder::new().build().to_string(), "(&)");
+        assert_eq!(Filter::always_false().to_string(), "(|)");
+
+        let mut builder = FilterBuilder::new();
+        builder.push_and(Filter::Present("mail"));
+        assert_eq!(builder.clone().build().to_string(), "(mail=*)");
+        builder
+            .push_and(Filter::and([Filter::equal("cn", "a")]))
+            .push_or(Filter::equal("cn", "b"))
+            .push_or(!Filter::or([Filter::equal("cn", "c")]));
+        assert_eq!(builder.build().to_string(), "(|(&(mail=*)(cn=a))(cn=b)(!(cn=c)))");
+    }
+
+    #[test]
+    fn display_filter() {
+        let filter = Filter::and([
+            Filter::equal("objectClass", "person"),
+            !Filter::Present("mail"),
+            Filter::or([
+                Filter::greater_or_equal("uidNumber", "1000"),
+                Filter::approximate_match("cn", b"st*ve".as_slice()),
+            ]),
+            Filter::in_chain("memberOf", "cn=Admins,dc=x"),
+        ]);
+        assert_eq!(
+            filter.to_string(),
+            r"(&(objectClass=person)(!(mail=*))(|(uidNumber>=1000)(cn~=st\2ave))(memberOf:1.2.840.113556.1.4.1941:=cn=Admins,dc=x))"
+        );
+        assert_eq!(
+            Filter::equal("objectSid", &[0x01, 0x05, 0xff, b'a']).to_string(),
+            r"(objectSid=\01\05\ffa)"
+        );
+    }
+
+    #[test]
+    fn binary_ordering() {
+        let (low, high) = ([0x00, 0xff], [0xff, 0xfe, b'a']);
+        let Filter::GreaterOrEqual(ava) = Filter::gte_bytes("x", &low) else {
+            panic!("not a >= filter");
+        };
+        assert_eq!(ava.assertion_value, low);
+        assert_eq!(
+            Filter::and([Filter::gte_bytes("x", &low), Filter::lte_bytes("x", &high)]).to_string(),
+            r"(&(x>=\00\ff)(x<=\ff\fea))"
+        );
+    }
+}
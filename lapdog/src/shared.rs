@@ -0,0 +1,196 @@
+//! Sharing one connection between tasks or threads that take turns instead of running operations concurrently.
+//!
+//! LDAP itself allows many operations in flight on one connection, told apart by their message IDs, and
+//! [`LdapConnection`] supports that for searches. Binds and other operations that change the connection's state
+//! need it to themselves though, which a lock around the connection only guarantees if it's held until the
+//! operation is over. [`SyncLdapConnection`] does that, for searches until their last result was read.
+
+use std::{
+    num::NonZero,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::{
+    LdapConnection,
+    extended::ExtendedOpError,
+    modify::{Change, ModifyError},
+    search::{
+        BeginSearchError, CollectError, CountError, DerefPolicy, Filter, FromEntry, RawEntry, ReadEntryError,
+        Scope, SearchResults,
+    },
+};
+
+/// An [`LdapConnection`] behind a mutex, held for one operation at a time.
+///
+/// Cloning it shares the connection. Operations not offered here run on the connection returned by
+/// [`SyncLdapConnection::lock`], which is held until the guard is dropped:
+///
+/// ```no_run
+/// # async fn run(connection: lapdog::LdapConnection) -> Result<(), lapdog::LapdogError> {
+/// use lapdog::shared::SyncLdapConnection;
+///
+/// let shared = SyncLdapConnection::new(connection);
+/// let clone = shared.clone();
+/// tokio::spawn(async move { clone.who_am_i().await });
+/// let schema = shared.lock().await.read_schema().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SyncLdapConnection(Arc<Mutex<LdapConnection>>);
+impl SyncLdapConnection {
+    pub fn new(connection: LdapConnection) -> Self {
+        Self(Arc::new(Mutex::new(connection)))
+    }
+    /// Waits for the operations of others to finish, then gives exclusive access to the connection
+    pub async fn lock(&self) -> OwnedMutexGuard<LdapConnection> {
+        self.0.clone().lock_owned().await
+    }
+    /// The connection, `None` if it's still shared through clones or an unfinished search
+    pub fn into_inner(self) -> Option<LdapConnection> {
+        Arc::into_inner(self.0).map(Mutex::into_inner)
+    }
+    /// [`LdapConnection::search_all`], holding the lock until the search is over, see [`SyncSearchResults`]
+    pub async fn search_all(
+        &self,
+        base_object: &str,
+        scope: Scope,
+        deref_policy: DerefPolicy,
+        filter: Filter<'_>,
+    ) -> Result<SyncSearchResults<RawEntry>, BeginSearchError> {
+        self.search_as(base_object, scope, deref_policy, filter).await
+    }
+    /// [`LdapConnection::search_as`], holding the lock until the search is over, see [`SyncSearchResults`]
+    pub async fn search_as<Output: FromEntry>(
+        &self,
+        base_object: &str,
+        scope: Scope,
+        deref_policy: DerefPolicy,
+        filter: Filter<'_>,
+    ) -> Result<SyncSearchResults<Output>, BeginSearchError> {
+        let guard = self.lock().await;
+        let results = guard.search_as(base_object, scope, deref_policy, filter).await?;
+        Ok(SyncSearchResults {
+            results: Some(results),
+            guard: Some(guard),
+        })
+    }
+    /// [`LdapConnection::find_all`] with the lock held
+    pub async fn find_all<Output: FromEntry>(
+        &self,
+        base_object: &str,
+        filter: Filter<'_>,
+    ) -> Result<Vec<Output>, CollectError> {
+        self.lock().await.find_all(base_object, filter).await
+    }
+    /// [`LdapConnection::read_entry`] with the lock held
+    pub async fn read_entry<Output: FromEntry>(&self, dn: &str) -> Result<Output, ReadEntryError> {
+        self.lock().await.read_entry(dn).await
+    }
+    /// [`LdapConnection::count`] with the lock held
+    pub async fn count(
+        &self,
+        base_object: &str,
+        scope: Scope,
+        deref_policy: DerefPolicy,
+        filter: Filter<'_>,
+    ) -> Result<usize, CountError> {
+        self.lock()
+            .await
+            .count(base_object, scope, deref_policy, filter)
+            .await
+    }
+    /// [`LdapConnection::modify`] with the lock held
+    pub async fn modify(&self, object: &str, changes: &[Change<'_>]) -> Result<(), ModifyError> {
+        self.lock().await.modify(object, changes).await
+    }
+    /// [`LdapConnection::who_am_i`] with the lock held
+    pub async fn who_am_i(&self) -> Result<Option<String>, ExtendedOpError> {
+        self.lock().await.who_am_i().await
+    }
+}
+
+/// The results of a search on a [`SyncLdapConnection`], which stays locked until the search is over.
+///
+/// Dropping the results before [`SearchResult::Done`](crate::search::SearchResult::Done) abandons the search, and
+/// the lock is released once the connection forgot it.
+pub struct SyncSearchResults<Output = RawEntry> {
+    results: Option<SearchResults<Output>>,
+    guard: Option<OwnedMutexGuard<LdapConnection>>,
+}
+impl<Output> Deref for SyncSearchResults<Output> {
+    type Target = SearchResults<Output>;
+    fn deref(&self) -> &Self::Target {
+        self.results.as_ref().expect("only taken on drop")
+    }
+}
+impl<Output> DerefMut for SyncSearchResults<Output> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.results.as_mut().expect("only taken on drop")
+    }
+}
+impl<Output> Drop for SyncSearchResults<Output> {
+    fn drop(&mut self) {
+        let (Some(mut results), Some(guard)) = (self.results.take(), self.guard.take()) else {
+            return;
+        };
+        let id = results.message_id();
+        results.give_up();
+        drop(results);
+        // the search is forgotten by a task of its own, even after it finished
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(release_when_forgotten(guard, id));
+        }
+    }
+}
+
+/// Holds `guard` until the search `id` is no longer in flight, so the next operation finds the connection idle
+async fn release_when_forgotten(guard: OwnedMutexGuard<LdapConnection>, id: NonZero<i32>) {
+    while guard.inflight_requests.lock().await.contains_key(&id) {
+        tokio::task::yield_now().await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::io::AsyncReadExt;
+
+    use super::SyncLdapConnection;
+    use crate::{
+        search::{DerefPolicy, Filter, Scope},
+        test::mock_connection,
+    };
+
+    #[tokio::test]
+    async fn search_holds_lock_until_over() {
+        let (connection, mut server) = mock_connection().await;
+        let shared = SyncLdapConnection::new(connection);
+        let results = shared
+            .search_all(
+                "dc=x",
+                Scope::WholeSubtree,
+                DerefPolicy::Never,
+                Filter::Present("cn"),
+            )
+            .await
+            .unwrap();
+        assert!(shared.0.try_lock().is_err());
+
+        drop(results);
+        let connection = tokio::time::timeout(Duration::from_secs(1), shared.lock())
+            .await
+            .expect("lock not released");
+        assert!(connection.inflight_requests.lock().await.is_empty());
+        // the search, then the abandon of it
+        let mut sent = [0; 64];
+        let mut read = 0;
+        while !sent[..read].ends_with(&[0x50, 0x01, 0x01]) {
+            read += server.read(&mut sent[read..]).await.unwrap();
+        }
+    }
+}
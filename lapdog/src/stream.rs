@@ -1,35 +1,38 @@
+#[cfg(any(feature = "kerberos", feature = "native-tls", feature = "rustls"))]
+use std::io::ErrorKind;
 #[cfg(feature = "kerberos")]
-use std::collections::VecDeque;
-use std::{io::Read, pin::Pin, sync::Arc};
+use std::{collections::VecDeque, io::Read, pin::Pin, sync::Arc};
 
-#[cfg(any(feature = "native-tls", feature = "rustls"))]
-use tokio::io::{ReadHalf, WriteHalf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
 #[cfg(feature = "kerberos")]
 use tokio::sync::Mutex;
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{
-        TcpStream,
-        tcp::{OwnedReadHalf, OwnedWriteHalf},
-    },
-};
 
 #[cfg(feature = "kerberos")]
 use crate::bind::kerberos::MaybeEncryptClientContext;
+#[cfg(feature = "kerberos")]
+use crate::read::{ReadExt, ReadLdap};
 use crate::{
+    TlsInfo,
     parse::ParseLdap,
-    read::{AsyncReadLdap, ReadExt, ReadLdap},
+    read::AsyncReadLdap,
     tag::{UNIVERSAL_INTEGER, UNIVERSAL_SEQUENCE},
 };
 
+/// Anything LDAP can be spoken over, usually a TCP socket
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+/// The transport below TLS and Kerberos encryption
+pub type Socket = Box<dyn Transport>;
+
 pub enum StreamWriteHalf {
-    Plain(OwnedWriteHalf),
+    Plain(WriteHalf<Socket>),
     #[cfg(feature = "native-tls")]
-    NativeTls(WriteHalf<tokio_native_tls::TlsStream<TcpStream>>),
+    NativeTls(WriteHalf<tokio_native_tls::TlsStream<Socket>>),
     #[cfg(feature = "kerberos")]
-    Kerberos(Arc<Mutex<MaybeEncryptClientContext>>, OwnedWriteHalf),
+    Kerberos(Arc<Mutex<MaybeEncryptClientContext>>, WriteHalf<Socket>),
     #[cfg(feature = "rustls")]
-    Rustls(WriteHalf<tokio_rustls::TlsStream<TcpStream>>),
+    Rustls(WriteHalf<tokio_rustls::TlsStream<Socket>>),
 }
 impl StreamWriteHalf {
     pub async fn write_message(&mut self, m: &[u8]) -> Result<(), std::io::Error> {
@@ -51,17 +54,23 @@ impl StreamWriteHalf {
     }
 }
 
+/// The reading side, buffered as messages are read a few bytes at a time
 pub enum StreamReadHalf {
-    Plain(OwnedReadHalf),
+    Plain(BufReader<ReadHalf<Socket>>),
     #[cfg(feature = "native-tls")]
-    NativeTls(ReadHalf<tokio_native_tls::TlsStream<TcpStream>>),
+    NativeTls(BufReader<ReadHalf<tokio_native_tls::TlsStream<Socket>>>),
     #[cfg(feature = "kerberos")]
-    Kerberos(Arc<Mutex<MaybeEncryptClientContext>>, VecDeque<u8>, OwnedReadHalf),
+    Kerberos(
+        Arc<Mutex<MaybeEncryptClientContext>>,
+        VecDeque<u8>,
+        ReadHalf<Socket>,
+    ),
     #[cfg(feature = "rustls")]
-    Rustls(ReadHalf<tokio_rustls::TlsStream<TcpStream>>),
+    Rustls(BufReader<ReadHalf<tokio_rustls::TlsStream<Socket>>>),
 }
 impl StreamReadHalf {
-    pub async fn get_next_message(&mut self) -> Result<(i32, Vec<u8>), std::io::Error> {
+    /// Reads the next message, returning its ID, its body after the ID and its encoded size
+    pub async fn get_next_message(&mut self) -> Result<(i32, Vec<u8>, usize), std::io::Error> {
         match self {
             StreamReadHalf::Plain(owned_read_half) => Ok(read_message_head_async(owned_read_half).await?),
             #[cfg(feature = "native-tls")]
@@ -87,12 +96,12 @@ impl StreamReadHalf {
 
 async fn read_message_head_async<R: AsyncReadExt + Unpin>(
     r: &mut R,
-) -> Result<(i32, Vec<u8>), std::io::Error> {
+) -> Result<(i32, Vec<u8>, usize), std::io::Error> {
     let seq_tag = r.read_u8().await?;
     if seq_tag != UNIVERSAL_SEQUENCE {
         panic!("Not a sequence");
     }
-    let (Some(len), _) = r.read_length().await? else {
+    let (Some(len), len_size) = r.read_length().await? else {
         panic!()
     };
     let mut buffer = vec![0; len];
@@ -102,15 +111,16 @@ async fn read_message_head_async<R: AsyncReadExt + Unpin>(
     if tag != UNIVERSAL_INTEGER {
         panic!("message id is not an int");
     }
-    Ok((message_id, buf_read.to_vec()))
+    Ok((message_id, buf_read.to_vec(), 1 + len_size + len))
 }
 
-fn read_message_head_sync<R: Read>(r: &mut R) -> (i32, Vec<u8>) {
+#[cfg(feature = "kerberos")]
+fn read_message_head_sync<R: Read>(r: &mut R) -> (i32, Vec<u8>, usize) {
     let seq_tag = r.read_single_byte().unwrap();
     if seq_tag != UNIVERSAL_SEQUENCE {
         panic!("Not a sequence");
     }
-    let (Some(len), _) = r.read_length().unwrap() else {
+    let (Some(len), len_size) = r.read_length().unwrap() else {
         panic!()
     };
     let mut buffer = vec![0; len];
@@ -120,34 +130,61 @@ fn read_message_head_sync<R: Read>(r: &mut R) -> (i32, Vec<u8>) {
     if tag != UNIVERSAL_INTEGER {
         panic!("message id is not an int");
     }
-    (message_id, buf_read.to_vec())
+    (message_id, buf_read.to_vec(), 1 + len_size + len)
 }
 
 #[allow(clippy::large_enum_variant)]
 pub enum Stream {
-    Plain(TcpStream),
+    Plain(Socket),
     #[cfg(feature = "native-tls")]
-    NativeTls(tokio_native_tls::TlsStream<TcpStream>),
+    NativeTls(tokio_native_tls::TlsStream<Socket>),
     #[cfg(feature = "kerberos")]
-    Kerberos(Arc<Mutex<MaybeEncryptClientContext>>, VecDeque<u8>, TcpStream),
+    Kerberos(Arc<Mutex<MaybeEncryptClientContext>>, VecDeque<u8>, Socket),
     #[cfg(feature = "rustls")]
-    Rustls(tokio_rustls::TlsStream<TcpStream>),
+    Rustls(tokio_rustls::TlsStream<Socket>),
 }
 impl Stream {
-    pub fn split(self) -> (StreamReadHalf, StreamWriteHalf) {
+    /// Details of the TLS session, `None` for streams without TLS
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        match self {
+            #[cfg(feature = "native-tls")]
+            // native-tls doesn't report any of it
+            Self::NativeTls(_) => Some(TlsInfo::default()),
+            #[cfg(feature = "rustls")]
+            Self::Rustls(rustls) => {
+                let state = rustls.get_ref().1;
+                Some(TlsInfo {
+                    protocol_version: state.protocol_version().map(|v| format!("{v:?}")),
+                    cipher_suite: state
+                        .negotiated_cipher_suite()
+                        .map(|c| format!("{:?}", c.suite())),
+                    resumed: state.handshake_kind() == Some(rustls::HandshakeKind::Resumed),
+                })
+            }
+            _ => None,
+        }
+    }
+    /// Splits the stream, buffering reads with `read_buffer_size` bytes. Kerberos frames are read whole anyway.
+    pub fn split(self, read_buffer_size: usize) -> (StreamReadHalf, StreamWriteHalf) {
         match self {
             Self::Plain(p) => {
-                let (r, w) = p.into_split();
-                (StreamReadHalf::Plain(r), StreamWriteHalf::Plain(w))
+                let (r, w) = tokio::io::split(p);
+                (
+                    StreamReadHalf::Plain(BufReader::with_capacity(read_buffer_size, r)),
+                    StreamWriteHalf::Plain(w),
+                )
             }
             #[cfg(feature = "native-tls")]
             Self::NativeTls(n) => {
                 let (r, w) = tokio::io::split(n);
-                (StreamReadHalf::NativeTls(r), StreamWriteHalf::NativeTls(w))
+                (
+                    StreamReadHalf::NativeTls(BufReader::with_capacity(read_buffer_size, r)),
+                    StreamWriteHalf::NativeTls(w),
+                )
             }
             #[cfg(feature = "kerberos")]
             Self::Kerberos(client, buf, tcp) => {
-                let (r, w) = tcp.into_split();
+                let (r, w) = tokio::io::split(tcp);
                 (
                     StreamReadHalf::Kerberos(client.clone(), buf, r),
                     StreamWriteHalf::Kerberos(client, w),
@@ -156,33 +193,50 @@ impl Stream {
             #[cfg(feature = "rustls")]
             Self::Rustls(rustls) => {
                 let (r, w) = tokio::io::split(rustls);
-                (StreamReadHalf::Rustls(r), StreamWriteHalf::Rustls(w))
+                (
+                    StreamReadHalf::Rustls(BufReader::with_capacity(read_buffer_size, r)),
+                    StreamWriteHalf::Rustls(w),
+                )
             }
         }
     }
-    #[cfg(feature = "kerberos")]
-    pub fn unsplit(read: StreamReadHalf, write: StreamWriteHalf) -> Self {
-        match (read, write) {
+    /// Joins the halves again. Only called without requests in flight, so nothing should be buffered; bytes that
+    /// are, e.g. sent by the server right after its StartTLS response, fail with [`ErrorKind::InvalidData`] instead
+    /// of being dropped or read as part of the new stream.
+    #[cfg(any(feature = "kerberos", feature = "native-tls", feature = "rustls"))]
+    pub fn unsplit(read: StreamReadHalf, write: StreamWriteHalf) -> std::io::Result<Self> {
+        Ok(match (read, write) {
             (StreamReadHalf::Plain(owned_read_half), StreamWriteHalf::Plain(owned_write_half)) => {
-                Stream::Plain(owned_read_half.reunite(owned_write_half).unwrap())
+                Stream::Plain(unbuffer(owned_read_half)?.unsplit(owned_write_half))
             }
             #[cfg(feature = "native-tls")]
             (StreamReadHalf::NativeTls(read_half), StreamWriteHalf::NativeTls(write_half)) => {
-                Stream::NativeTls(read_half.unsplit(write_half))
+                Stream::NativeTls(unbuffer(read_half)?.unsplit(write_half))
             }
             #[cfg(feature = "rustls")]
             (StreamReadHalf::Rustls(read_half), StreamWriteHalf::Rustls(write_half)) => {
-                Stream::Rustls(read_half.unsplit(write_half))
+                Stream::Rustls(unbuffer(read_half)?.unsplit(write_half))
             }
             #[cfg(feature = "kerberos")]
             (
                 StreamReadHalf::Kerberos(client, buf, owned_read_half),
                 StreamWriteHalf::Kerberos(_, owned_write_half),
-            ) => Stream::Kerberos(client, buf, owned_read_half.reunite(owned_write_half).unwrap()),
-            #[cfg(any(feature = "native-tls", feature = "kerberos"))]
+            ) => Stream::Kerberos(client, buf, owned_read_half.unsplit(owned_write_half)),
             _ => unreachable!(),
-        }
+        })
+    }
+}
+
+/// Takes the reader out of its buffer, failing if the buffer still holds bytes nobody read
+#[cfg(any(feature = "kerberos", feature = "native-tls", feature = "rustls"))]
+fn unbuffer<R: AsyncRead>(reader: BufReader<R>) -> std::io::Result<R> {
+    if !reader.buffer().is_empty() {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "received data that doesn't belong to any request",
+        ));
     }
+    Ok(reader.into_inner())
 }
 
 #[cfg(feature = "kerberos")]
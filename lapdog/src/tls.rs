@@ -0,0 +1,159 @@
+//! Convenience constructors for the TLS variants of [`StreamConfig`] and the [`TlsConnection`] they lead to
+
+use std::ops::{Deref, DerefMut};
+#[cfg(feature = "rustls")]
+use std::sync::Arc;
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::ToSocketAddrs,
+};
+
+use crate::{ConnectError, ConnectOptions, LdapConnection, StreamConfig};
+
+/// A connection that is known to be protected by TLS, either from the start or after
+/// [`LdapConnection::start_tls`].
+///
+/// Operations that send secrets, like [`TlsConnection::bind_simple`], only exist here, so sending a password over
+/// an unencrypted connection doesn't compile. Everything else is available through [`Deref`] to [`LdapConnection`].
+pub struct TlsConnection(pub(crate) LdapConnection);
+impl TlsConnection {
+    /// Like [`LdapConnection::new`], failing with [`ConnectError::NotTls`] before connecting if `config` is
+    /// [`StreamConfig::Plain`]
+    pub async fn new(addr: impl ToSocketAddrs, config: &StreamConfig) -> Result<Self, ConnectError> {
+        Self::connect_with_options(addr, config, &ConnectOptions::default()).await
+    }
+    /// Like [`LdapConnection::connect_with_options`], see [`TlsConnection::new`]
+    pub async fn connect_with_options(
+        addr: impl ToSocketAddrs,
+        config: &StreamConfig,
+        options: &ConnectOptions,
+    ) -> Result<Self, ConnectError> {
+        if !config.is_tls() {
+            return Err(ConnectError::NotTls);
+        }
+        LdapConnection::connect_with_options(addr, config, options)
+            .await
+            .map(Self)
+    }
+    /// Like [`LdapConnection::from_stream`], see [`TlsConnection::new`]
+    pub async fn from_stream<S>(stream: S, config: &StreamConfig) -> Result<Self, ConnectError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        if !config.is_tls() {
+            return Err(ConnectError::NotTls);
+        }
+        LdapConnection::from_stream(stream, config).await.map(Self)
+    }
+    pub fn into_inner(self) -> LdapConnection {
+        self.0
+    }
+}
+/// Checks whether the connection already uses TLS, e.g. one set up with a TLS [`StreamConfig`] by code that
+/// returns plain [`LdapConnection`]s. Hands the connection back if it doesn't.
+impl TryFrom<LdapConnection> for TlsConnection {
+    type Error = LdapConnection;
+    fn try_from(connection: LdapConnection) -> Result<Self, Self::Error> {
+        if connection.tls_info.is_some() {
+            Ok(Self(connection))
+        } else {
+            Err(connection)
+        }
+    }
+}
+impl From<TlsConnection> for LdapConnection {
+    fn from(value: TlsConnection) -> Self {
+        value.0
+    }
+}
+impl Deref for TlsConnection {
+    type Target = LdapConnection;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl DerefMut for TlsConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl StreamConfig {
+    /// rustls config that verifies the server against `roots`, e.g. a company CA, with rustls' default protocol
+    /// versions. LDAP doesn't use ALPN, so none is offered.
+    ///
+    /// `provider` picks the crypto backend, e.g. `rustls::crypto::aws_lc_rs::default_provider()`.
+    #[cfg(feature = "rustls")]
+    pub fn rustls_with_roots(
+        provider: Arc<rustls::crypto::CryptoProvider>,
+        roots: rustls::RootCertStore,
+        domain: impl Into<String>,
+    ) -> Result<Self, rustls::Error> {
+        let config = rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Self::Rustls {
+            config: Arc::new(config),
+            domain: domain.into(),
+        })
+    }
+    /// rustls config that verifies the server against Mozilla's root certificates, see
+    /// [`StreamConfig::rustls_with_roots`].
+    ///
+    /// Directories with certificates from an internal CA need [`StreamConfig::rustls_with_roots`] instead.
+    #[cfg(feature = "webpki-roots")]
+    pub fn rustls_webpki_roots(
+        provider: Arc<rustls::crypto::CryptoProvider>,
+        domain: impl Into<String>,
+    ) -> Result<Self, rustls::Error> {
+        let roots = rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        Self::rustls_with_roots(provider, roots, domain)
+    }
+    /// native-tls config that verifies the server against the system's root certificates
+    #[cfg(feature = "native-tls")]
+    pub fn native_tls(domain: impl Into<String>) -> Result<Self, native_tls::Error> {
+        Self::native_tls_with_root_certificates([], domain)
+    }
+    /// native-tls config that also trusts `roots`, e.g. a company CA, on top of the system's root certificates.
+    ///
+    /// Certificates can be read with [`native_tls::Certificate::from_pem`] or [`native_tls::Certificate::from_der`].
+    #[cfg(feature = "native-tls")]
+    pub fn native_tls_with_root_certificates(
+        roots: impl IntoIterator<Item = native_tls::Certificate>,
+        domain: impl Into<String>,
+    ) -> Result<Self, native_tls::Error> {
+        let mut builder = native_tls::TlsConnector::builder();
+        for root in roots {
+            builder.add_root_certificate(root);
+        }
+        Ok(Self::NativeTls {
+            connector: builder.build()?,
+            domain: domain.into(),
+        })
+    }
+    /// native-tls config that presents `identity` as client certificate, e.g. for
+    /// [`LdapConnection::bind_sasl_external`](crate::LdapConnection::bind_sasl_external). `roots` are trusted on
+    /// top of the system's root certificates.
+    ///
+    /// Identities can be read with [`native_tls::Identity::from_pkcs12`] or [`native_tls::Identity::from_pkcs8`].
+    #[cfg(feature = "native-tls")]
+    pub fn native_tls_with_identity(
+        identity: native_tls::Identity,
+        roots: impl IntoIterator<Item = native_tls::Certificate>,
+        domain: impl Into<String>,
+    ) -> Result<Self, native_tls::Error> {
+        let mut builder = native_tls::TlsConnector::builder();
+        builder.identity(identity);
+        for root in roots {
+            builder.add_root_certificate(root);
+        }
+        Ok(Self::NativeTls {
+            connector: builder.build()?,
+            domain: domain.into(),
+        })
+    }
+}
@@ -0,0 +1,145 @@
+//! LDAP transactions (RFC 5805), to apply several updates atomically.
+//!
+//! Start a transaction, send the updates with it, then commit or abort it:
+//!
+//! ```no_run
+//! # async fn run(connection: &mut lapdog::LdapConnection) -> Result<(), lapdog::LapdogError> {
+//! use lapdog::modify::{Change, Operation};
+//!
+//! let transaction = connection.start_transaction().await?;
+//! let values: &[&[u8]] = &[b"cn=bob,ou=people,dc=example,dc=com"];
+//! let add_member = [Change {
+//!     operation: Operation::Add,
+//!     attribute_type: "member",
+//!     attribute_values: values,
+//! }];
+//! connection
+//!     .modify_in_transaction(&transaction, "cn=admins,ou=groups,dc=example,dc=com", &add_member)
+//!     .await?;
+//! connection
+//!     .modify_in_transaction(&transaction, "cn=staff,ou=groups,dc=example,dc=com", &add_member)
+//!     .await?;
+//! connection.commit_transaction(transaction).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Few servers implement this; OpenLDAP does from 2.5 on with the `txn` support of its MDB backend, and lists
+//! [`START_TRANSACTION_OID`] in the root DSE's `supportedExtension` then. A server accepts an update into the
+//! transaction with a success result, it is only applied on commit. If any update fails then, the whole transaction
+//! fails with the error of that update and nothing is applied.
+
+use crate::{
+    LdapConnection, WriteExt,
+    control::Control,
+    extended::ExtendedOpError,
+    modify::{Change, ModifyError},
+    tag::{OCTET_STRING, UNIVERSAL_BOOLEAN, UNIVERSAL_SEQUENCE},
+};
+
+/// OID of the Start Transaction extended operation
+pub const START_TRANSACTION_OID: &str = "1.3.6.1.1.21.1";
+/// OID of the Transaction Specification control, which adds an update to a transaction
+pub const TRANSACTION_SPECIFICATION_OID: &str = "1.3.6.1.1.21.2";
+/// OID of the End Transaction extended operation
+pub const END_TRANSACTION_OID: &str = "1.3.6.1.1.21.3";
+
+/// A transaction started with [`LdapConnection::start_transaction`].
+///
+/// A transaction is bound to the connection it was started on. Dropping it without committing leaves it open on the
+/// server until the connection closes or the server gives up on it.
+#[derive(Debug)]
+#[must_use = "transactions need to be committed or aborted"]
+pub struct Transaction {
+    id: Vec<u8>,
+}
+impl Transaction {
+    /// The identifier the server assigned to the transaction
+    pub fn id(&self) -> &[u8] {
+        &self.id
+    }
+    /// The control that adds an update to this transaction, for operations without a `_in_transaction` method
+    pub fn control(&self) -> Control {
+        Control::new(TRANSACTION_SPECIFICATION_OID, true, Some(self.id.clone()))
+    }
+}
+
+impl LdapConnection {
+    /// Starts a transaction, see the [module docs](crate::transaction)
+    pub async fn start_transaction(&mut self) -> Result<Transaction, ExtendedOpError> {
+        let response = self.extended_operation(START_TRANSACTION_OID, None).await?;
+        match response.value {
+            Some(id) if !id.is_empty() => Ok(Transaction { id }),
+            _ => Err(ExtendedOpError::InvalidSchema),
+        }
+    }
+    /// Adds a modification to `transaction`, it is only applied once the transaction is committed
+    pub async fn modify_in_transaction(
+        &mut self,
+        transaction: &Transaction,
+        object: &str,
+        changes: &[Change<'_>],
+    ) -> Result<(), ModifyError> {
+        self.modify_with_controls(object, changes, &[transaction.control()])
+            .await
+    }
+    /// Applies all updates of `transaction` atomically.
+    ///
+    /// If one of them fails, nothing is applied and the server's error describes the failed update.
+    pub async fn commit_transaction(&mut self, transaction: Transaction) -> Result<(), ExtendedOpError> {
+        self.end_transaction(transaction, true).await
+    }
+    /// Discards all updates of `transaction`
+    pub async fn abort_transaction(&mut self, transaction: Transaction) -> Result<(), ExtendedOpError> {
+        self.end_transaction(transaction, false).await
+    }
+    async fn end_transaction(
+        &mut self,
+        transaction: Transaction,
+        commit: bool,
+    ) -> Result<(), ExtendedOpError> {
+        let value = write_end_transaction(&transaction.id, commit);
+        self.extended_operation(END_TRANSACTION_OID, Some(&value)).await?;
+        Ok(())
+    }
+}
+
+/// Writes the `txnEndReq` value of an End Transaction request
+fn write_end_transaction(id: &[u8], commit: bool) -> Vec<u8> {
+    let mut value = Vec::new();
+    value
+        .write_sequence(UNIVERSAL_SEQUENCE, |seq| {
+            // DEFAULT TRUE, so it's only written to abort
+            if !commit {
+                seq.extend_from_slice(&[UNIVERSAL_BOOLEAN, 0x01, 0x00]);
+            }
+            seq.push(OCTET_STRING);
+            seq.write_ber_length(id.len())?;
+            seq.extend_from_slice(id);
+            Ok(())
+        })
+        .expect("infallible");
+    value
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TRANSACTION_SPECIFICATION_OID, Transaction, write_end_transaction};
+
+    #[test]
+    fn end_transaction_request() {
+        assert_eq!(
+            write_end_transaction(b"42", true),
+            [0x30, 0x04, 0x04, 0x02, b'4', b'2']
+        );
+        assert_eq!(
+            write_end_transaction(b"42", false),
+            [0x30, 0x07, 0x01, 0x01, 0x00, 0x04, 0x02, b'4', b'2']
+        );
+
+        let control = Transaction { id: b"42".to_vec() }.control();
+        assert_eq!(control.oid, TRANSACTION_SPECIFICATION_OID);
+        assert!(control.criticality);
+        assert_eq!(control.value.as_deref(), Some(&b"42"[..]));
+    }
+}
@@ -0,0 +1,213 @@
+//! LDAP URLs (RFC 4516), as returned by servers in referrals
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    str::FromStr,
+};
+
+use crate::{LDAP_PORT, LDAPS_PORT};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Scheme {
+    Ldap,
+    Ldaps,
+    /// LDAP over a Unix domain socket
+    Ldapi,
+}
+impl Scheme {
+    /// Port to connect to if the URL doesn't specify one, `None` for `ldapi`
+    pub fn default_port(self) -> Option<u16> {
+        match self {
+            Self::Ldap => Some(LDAP_PORT),
+            Self::Ldaps => Some(LDAPS_PORT),
+            Self::Ldapi => None,
+        }
+    }
+}
+
+/// A parsed LDAP URL like `ldap://dc2.example.com:389/dc=example,dc=com`.
+///
+/// Only the parts needed to follow a referral are kept; the attributes, scope and filter are ignored.
+/// Percent-encoded characters in the DN and host are decoded.
+///
+/// ```
+/// # use lapdog::url::{LdapUrl, Scheme};
+/// let url: LdapUrl = "ldaps://dc2.example.com/ou=Users,dc=example,dc=com".parse().unwrap();
+/// assert_eq!(url.scheme, Scheme::Ldaps);
+/// assert_eq!(url.port(), Some(636));
+/// assert_eq!(url.dn, "ou=Users,dc=example,dc=com");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LdapUrl {
+    pub scheme: Scheme,
+    /// `None` if the URL leaves the server to the client, like `ldap:///dc=example,dc=com`.
+    /// For `ldapi`, this is the path of the socket.
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub dn: String,
+    /// The `bindname` extension: the DN the server suggests binding as
+    pub bind_name: Option<String>,
+}
+impl LdapUrl {
+    /// The explicit port, or the default of the scheme
+    pub fn port(&self) -> Option<u16> {
+        self.port.or(self.scheme.default_port())
+    }
+}
+impl FromStr for LdapUrl {
+    type Err = InvalidLdapUrl;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s.split_once("://").ok_or(InvalidLdapUrl)?;
+        let scheme = match scheme.to_ascii_lowercase().as_str() {
+            "ldap" => Scheme::Ldap,
+            "ldaps" => Scheme::Ldaps,
+            "ldapi" => Scheme::Ldapi,
+            _ => return Err(InvalidLdapUrl),
+        };
+        let (host_port, rest) = rest.split_once('/').unwrap_or((rest, ""));
+        let (host, port) = match host_port.strip_prefix('[') {
+            // IPv6 literal
+            Some(literal) => {
+                let (host, port) = literal.split_once(']').ok_or(InvalidLdapUrl)?;
+                (host, port.strip_prefix(':'))
+            }
+            None if scheme == Scheme::Ldapi => (host_port, None),
+            None => match host_port.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (host_port, None),
+            },
+        };
+        let host = match percent_decode(host)? {
+            host if host.is_empty() => None,
+            host => Some(host),
+        };
+        let port = match port {
+            Some("") | None => None,
+            Some(port) => Some(port.parse().map_err(|_| InvalidLdapUrl)?),
+        };
+
+        // dn ? attributes ? scope ? filter ? extensions
+        let mut parts = rest.splitn(5, '?');
+        let dn = percent_decode(parts.next().unwrap_or_default())?;
+        let mut bind_name = None;
+        for extension in parts
+            .nth(3)
+            .unwrap_or_default()
+            .split(',')
+            .filter(|e| !e.is_empty())
+        {
+            let (critical, extension) = match extension.strip_prefix('!') {
+                Some(extension) => (true, extension),
+                None => (false, extension),
+            };
+            let (name, value) = extension.split_once('=').unwrap_or((extension, ""));
+            if name.eq_ignore_ascii_case("bindname") {
+                bind_name = Some(percent_decode(value)?);
+            } else if critical {
+                // RFC 4516: a URL with an unsupported critical extension must not be used
+                return Err(InvalidLdapUrl);
+            }
+        }
+        Ok(Self {
+            scheme,
+            host,
+            port,
+            dn,
+            bind_name,
+        })
+    }
+}
+
+fn percent_decode(s: &str) -> Result<String, InvalidLdapUrl> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+        let mut hex_digit = || {
+            iter.next()
+                .and_then(|d| (d as char).to_digit(16))
+                .ok_or(InvalidLdapUrl)
+        };
+        let high = hex_digit()?;
+        let low = hex_digit()?;
+        bytes.push((high << 4 | low) as u8);
+    }
+    String::from_utf8(bytes).map_err(|_| InvalidLdapUrl)
+}
+
+/// Picks the referral to follow from the URLs a server returned.
+///
+/// The server lists equivalent alternatives, so the first URL with the `preferred` scheme wins,
+/// otherwise the first one with any scheme. Malformed URLs and URLs with unsupported critical extensions
+/// are skipped rather than failing the selection; `None` if no URL is usable.
+pub fn select_referral<'a>(urls: impl IntoIterator<Item = &'a str>, preferred: Scheme) -> Option<LdapUrl> {
+    let mut fallback = None;
+    for url in urls {
+        let Ok(url) = url.parse::<LdapUrl>() else {
+            continue;
+        };
+        if url.scheme == preferred {
+            return Some(url);
+        }
+        fallback.get_or_insert(url);
+    }
+    fallback
+}
+
+#[derive(Debug)]
+pub struct InvalidLdapUrl;
+impl Error for InvalidLdapUrl {}
+impl Display for InvalidLdapUrl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "invalid LDAP URL")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LdapUrl, Scheme, select_referral};
+
+    #[test]
+    fn parse_urls() {
+        let url: LdapUrl = "ldap://dc2.example.com:3268/cn=Doe%2C%20John,dc=example,dc=com??base"
+            .parse()
+            .unwrap();
+        assert_eq!(url.host.as_deref(), Some("dc2.example.com"));
+        assert_eq!(url.port(), Some(3268));
+        assert_eq!(url.dn, r"cn=Doe, John,dc=example,dc=com");
+
+        let url: LdapUrl = "ldap://[::1]/????bindname=cn=admin%2Cdc=x".parse().unwrap();
+        assert_eq!(url.host.as_deref(), Some("::1"));
+        assert_eq!(url.port(), Some(389));
+        assert_eq!(url.bind_name.as_deref(), Some("cn=admin,dc=x"));
+
+        let url: LdapUrl = "ldapi://%2Fvar%2Frun%2Fslapd%2Fldapi/".parse().unwrap();
+        assert_eq!(url.host.as_deref(), Some("/var/run/slapd/ldapi"));
+        assert_eq!(url.port(), None);
+
+        assert!("ldap:///dc=x".parse::<LdapUrl>().unwrap().host.is_none());
+        assert!("http://example.com".parse::<LdapUrl>().is_err());
+        assert!("ldap://host:port/".parse::<LdapUrl>().is_err());
+        assert!("ldap://host/dc=%zz".parse::<LdapUrl>().is_err());
+        assert!("ldap://host/????!x-unknown".parse::<LdapUrl>().is_err());
+        assert!("ldap://host/????x-unknown".parse::<LdapUrl>().is_ok());
+    }
+
+    #[test]
+    fn selection() {
+        let urls = [
+            "not a url",
+            "ldap://dc1.example.com/dc=example,dc=com",
+            "ldaps://dc2.example.com/dc=example,dc=com",
+        ];
+        let url = select_referral(urls, Scheme::Ldaps).unwrap();
+        assert_eq!(url.host.as_deref(), Some("dc2.example.com"));
+        let url = select_referral(urls, Scheme::Ldapi).unwrap();
+        assert_eq!(url.host.as_deref(), Some("dc1.example.com"));
+        assert_eq!(select_referral(["ldap://x:y/"], Scheme::Ldap), None);
+    }
+}